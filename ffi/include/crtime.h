@@ -0,0 +1,44 @@
+#ifndef CRTIME_H
+#define CRTIME_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle holding a planned batch of renames. */
+typedef struct CrtimePlan CrtimePlan;
+
+/* Result of applying a plan. */
+typedef struct CrtimeReport {
+    size_t renamed;
+    size_t skipped; /* changed since planning */
+    size_t failed;
+} CrtimeReport;
+
+/* Plans renames for every file in `dir`. Returns NULL on failure. */
+CrtimePlan *crtime_plan(const char *dir);
+
+/* Number of renames in the plan. */
+size_t crtime_plan_len(const CrtimePlan *plan);
+
+/* Current and new name of the item at `index`, or NULL if out of range.
+ * Strings are owned by the plan and live until crtime_plan_free. */
+const char *crtime_plan_item_name(const CrtimePlan *plan, size_t index);
+const char *crtime_plan_item_new_name(const CrtimePlan *plan, size_t index);
+
+/* Performs every rename in the plan, holding the lock of its directory and
+ * recorded in its journal, so that `crtime undo` and `crtime resume` work
+ * with the run. Every item counts as failed when the directory is in use
+ * by another run. */
+CrtimeReport crtime_apply(const CrtimePlan *plan);
+
+/* Releases a plan returned by crtime_plan. Accepts NULL. */
+void crtime_plan_free(CrtimePlan *plan);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRTIME_H */
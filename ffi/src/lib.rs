@@ -0,0 +1,178 @@
+//! C interface to the crtime rename engine.
+//!
+//! See `include/crtime.h` for the matching declarations.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crtime::event::Event;
+use crtime::journal::Journal;
+use crtime::lock::DirLock;
+use crtime::plan_file::SavedPlan;
+use crtime::{ApplyOptions, PlanOptions, RenamePlanItem};
+
+/// Opaque handle holding a planned batch of renames.
+pub struct CrtimePlan {
+    dir: PathBuf,
+    items: Vec<RenamePlanItem>,
+    names: Vec<(CString, CString)>,
+}
+
+/// Result of applying a plan.
+#[repr(C)]
+pub struct CrtimeReport {
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+fn c_string(s: &str) -> CString {
+    CString::new(s).unwrap_or_default()
+}
+
+/// Plans renames for every file in `dir`.
+///
+/// Returns null if `dir` is not valid UTF-8 or cannot be read.
+///
+/// # Safety
+///
+/// `dir` must be a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_plan(dir: *const c_char) -> *mut CrtimePlan {
+    if dir.is_null() {
+        return ptr::null_mut();
+    }
+
+    let dir = match CStr::from_ptr(dir).to_str() {
+        Ok(dir) => dir,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let items = match crtime::plan(Path::new(dir), &PlanOptions::default()) {
+        Ok(plan) => plan.items,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let names = items
+        .iter()
+        .map(|item| (c_string(&item.name), c_string(&item.new_name)))
+        .collect();
+
+    Box::into_raw(Box::new(CrtimePlan {
+        dir: PathBuf::from(dir),
+        items,
+        names,
+    }))
+}
+
+/// Number of renames in the plan.
+///
+/// # Safety
+///
+/// `plan` must be null or a pointer returned by `crtime_plan`.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_plan_len(plan: *const CrtimePlan) -> usize {
+    match plan.as_ref() {
+        Some(plan) => plan.items.len(),
+        None => 0,
+    }
+}
+
+/// Current name of the item at `index`, or null if out of range.
+///
+/// The string is owned by the plan and lives until `crtime_plan_free`.
+///
+/// # Safety
+///
+/// `plan` must be null or a pointer returned by `crtime_plan`.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_plan_item_name(
+    plan: *const CrtimePlan,
+    index: usize,
+) -> *const c_char {
+    match plan.as_ref().and_then(|plan| plan.names.get(index)) {
+        Some((name, _)) => name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// New name of the item at `index`, or null if out of range.
+///
+/// The string is owned by the plan and lives until `crtime_plan_free`.
+///
+/// # Safety
+///
+/// `plan` must be null or a pointer returned by `crtime_plan`.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_plan_item_new_name(
+    plan: *const CrtimePlan,
+    index: usize,
+) -> *const c_char {
+    match plan.as_ref().and_then(|plan| plan.names.get(index)) {
+        Some((_, new_name)) => new_name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Performs every rename in the plan, as the command line does: holding
+/// the lock of the directory, and recorded in its journal so that
+/// `crtime undo` and `crtime resume` work with the run.
+///
+/// # Safety
+///
+/// `plan` must be null or a pointer returned by `crtime_plan`.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_apply(plan: *const CrtimePlan) -> CrtimeReport {
+    match plan.as_ref() {
+        Some(plan) => match apply(plan) {
+            Ok(report) => report,
+            Err(_) => CrtimeReport {
+                renamed: 0,
+                skipped: 0,
+                failed: plan.items.len(),
+            },
+        },
+        None => CrtimeReport {
+            renamed: 0,
+            skipped: 0,
+            failed: 0,
+        },
+    }
+}
+
+fn apply(plan: &CrtimePlan) -> io::Result<CrtimeReport> {
+    let _lock = DirLock::acquire(&plan.dir)?;
+    let mut journal = Journal::open(&plan.dir)?;
+    let options = ApplyOptions::default();
+    SavedPlan::save(&plan.dir, &plan.items, journal.size()?, options.mode)?;
+
+    let outcome = crtime::apply(
+        &plan.items,
+        Some(&mut journal),
+        &options,
+        &mut |_: Event| {},
+    )?;
+    SavedPlan::remove(&plan.dir)?;
+
+    Ok(CrtimeReport {
+        renamed: outcome.renamed.len(),
+        skipped: outcome.skipped.len(),
+        failed: outcome.failed.len(),
+    })
+}
+
+/// Releases a plan returned by `crtime_plan`.
+///
+/// # Safety
+///
+/// `plan` must be null or a pointer returned by `crtime_plan` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn crtime_plan_free(plan: *mut CrtimePlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::stamp::FileStamp;
+use crate::{Plan, RenamePlanItem, SkippedItem};
+
+/// Start of the names of AppleDouble files, in which macOS keeps the
+/// resource forks and extended attributes of `Foo.jpg` as `._Foo.jpg` on
+/// filesystems and shares that cannot hold them.
+pub const PREFIX: &str = "._";
+
+/// What to do with the AppleDouble companion of a renamed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppleDoublePolicy {
+    /// Rename or copy it along with its file.
+    #[default]
+    Follow,
+    /// Leave it where it is.
+    Keep,
+    /// Delete it once its file has been moved.
+    Delete,
+}
+
+impl FromStr for AppleDoublePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follow" => Ok(AppleDoublePolicy::Follow),
+            "keep" => Ok(AppleDoublePolicy::Keep),
+            "delete" => Ok(AppleDoublePolicy::Delete),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether `name` is that of an AppleDouble file.
+pub fn is_companion_name(name: &str) -> bool {
+    name.starts_with(PREFIX) && name.len() > PREFIX.len()
+}
+
+/// The AppleDouble file next to `path`, if it has one.
+pub fn companion(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let companion = path.with_file_name(format!("{}{}", PREFIX, name));
+
+    match fs::symlink_metadata(&companion) {
+        Ok(meta) if meta.is_file() => Some(companion),
+        _ => None,
+    }
+}
+
+/// Adds the rename of every planned file's AppleDouble companion right
+/// after the file itself, to the file's new name with the prefix.
+///
+/// Companions whose new path is taken are listed in `Plan::skipped`.
+pub fn attach(plan: &mut Plan) {
+    let mut items = Vec::with_capacity(plan.items.len());
+
+    for item in std::mem::take(&mut plan.items) {
+        let companion = companion(&item.path)
+            .filter(|_| item.path != item.new_path)
+            .map(|path| follower(&item, path));
+
+        items.push(item);
+
+        match companion {
+            Some(Ok(companion)) => items.push(companion),
+            Some(Err(skipped)) => plan.skipped.push(skipped),
+            None => {}
+        }
+    }
+
+    plan.items = items;
+}
+
+fn follower(item: &RenamePlanItem, path: PathBuf) -> Result<RenamePlanItem, SkippedItem> {
+    let new_name = format!("{}{}", PREFIX, item.new_name);
+    let new_path = item.new_path.with_file_name(&new_name);
+
+    let stamp = match fs::symlink_metadata(&path) {
+        Ok(meta) => FileStamp::of(&meta),
+        Err(error) => return Err(skipped(path, error)),
+    };
+
+    if new_path.exists() {
+        let error = io::Error::new(io::ErrorKind::AlreadyExists, "companion name taken");
+        return Err(skipped(path, error));
+    }
+
+    Ok(RenamePlanItem {
+        created: item.created,
+        source: item.source,
+        name: format!("{}{}", PREFIX, item.name),
+        new_name,
+        path,
+        new_path,
+        stamp,
+        replaces: item.replaces,
+    })
+}
+
+fn skipped(path: PathBuf, error: io::Error) -> SkippedItem {
+    SkippedItem {
+        path,
+        reason: error.into(),
+    }
+}
+
+/// Deletes the AppleDouble companions left behind at the old paths of
+/// `items`.
+pub fn remove(items: &[&RenamePlanItem]) -> io::Result<()> {
+    for item in items {
+        if let Some(companion) = companion(&item.path) {
+            fs::remove_file(companion)?;
+        }
+    }
+
+    Ok(())
+}
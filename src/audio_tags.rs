@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Most of an ID3v2 tag or a FLAC metadata block crtime reads.
+const TAG_READ_LIMIT: u64 = 1024 * 1024;
+
+/// Common tags of an audio file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// Track number, without the total some files add after a slash.
+    pub track: Option<u32>,
+}
+
+impl Tags {
+    fn set(&mut self, key: &str, value: String) {
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+
+        if value.is_empty() {
+            return;
+        }
+
+        let field = match key {
+            "artist" => &mut self.artist,
+            "album" => &mut self.album,
+            "title" => &mut self.title,
+            "track" => {
+                let number = value.split('/').next().unwrap_or("").trim();
+                self.track = self.track.or_else(|| number.parse().ok());
+                return;
+            }
+            _ => return,
+        };
+
+        if field.is_none() {
+            *field = Some(value.to_owned());
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Tags::default()
+    }
+}
+
+/// Reads the tags of the MP3 or FLAC file at `path`, from ID3v2, ID3v1 or
+/// Vorbis comments, if it has any.
+pub fn read(path: &Path) -> io::Result<Option<Tags>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 4];
+
+    if file.read(&mut magic)? < 4 {
+        return Ok(None);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut tags = Tags::default();
+
+    match &magic {
+        b"fLaC" => read_flac(&mut file, &mut tags)?,
+        [b'I', b'D', b'3', _] => read_id3v2(&mut file, &mut tags)?,
+        _ => {}
+    }
+
+    if tags.is_empty() {
+        read_id3v1(&mut file, &mut tags)?;
+    }
+
+    Ok(if tags.is_empty() { None } else { Some(tags) })
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, &b| (size << 7) | usize::from(b & 0x7f))
+}
+
+fn read_id3v2(file: &mut File, tags: &mut Tags) -> io::Result<()> {
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+
+    let version = header[3];
+    let size = syncsafe(&header[6..10]);
+    let mut data = Vec::new();
+    file.take((size as u64).min(TAG_READ_LIMIT))
+        .read_to_end(&mut data)?;
+
+    let mut pos = 0;
+
+    // An extended header comes first if flagged.
+    if header[5] & 0x40 != 0 && data.len() >= 4 {
+        pos = match version {
+            4 => syncsafe(&data[..4]),
+            _ => u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize + 4,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    while pos + header_len <= data.len() {
+        let id = &data[pos..pos + id_len];
+
+        if id[0] == 0 {
+            break;
+        }
+
+        let size_bytes = &data[pos + id_len..pos + id_len + if version == 2 { 3 } else { 4 }];
+        let size = match version {
+            2 => size_bytes
+                .iter()
+                .fold(0, |size, &b| (size << 8) | usize::from(b)),
+            3 => u32::from_be_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]])
+                as usize,
+            _ => syncsafe(size_bytes),
+        };
+        let body = match data.get(pos + header_len..pos + header_len + size) {
+            Some(body) => body,
+            None => break,
+        };
+
+        let key = match id {
+            b"TPE1" | b"TP1" => "artist",
+            b"TALB" | b"TAL" => "album",
+            b"TIT2" | b"TT2" => "title",
+            b"TRCK" | b"TRK" => "track",
+            _ => "",
+        };
+
+        if let Some(text) = id3_text(body).filter(|_| !key.is_empty()) {
+            tags.set(key, text);
+        }
+
+        pos += header_len + size;
+    }
+
+    Ok(())
+}
+
+/// Decodes an ID3v2 text frame, keeping its first value.
+fn id3_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+
+    let utf16 = |text: &[u8], big_endian: bool| {
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|pair| {
+                if big_endian {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                }
+            })
+            .take_while(|&unit| unit != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match encoding {
+        0 => text
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| char::from(b))
+            .collect(),
+        1 => match text {
+            [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+            [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+            _ => utf16(text, false),
+        },
+        2 => utf16(text, true),
+        3 => {
+            let text = text.split(|&b| b == 0).next().unwrap_or(&[]);
+            String::from_utf8_lossy(text).into_owned()
+        }
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+fn read_id3v1(file: &mut File, tags: &mut Tags) -> io::Result<()> {
+    let mut tag = [0; 128];
+
+    if file.seek(SeekFrom::End(-128)).is_err() || file.read_exact(&mut tag).is_err() {
+        return Ok(());
+    }
+
+    if &tag[..3] != b"TAG" {
+        return Ok(());
+    }
+
+    let latin1 = |field: &[u8]| -> String {
+        field
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| char::from(b))
+            .collect()
+    };
+
+    tags.set("title", latin1(&tag[3..33]));
+    tags.set("artist", latin1(&tag[33..63]));
+    tags.set("album", latin1(&tag[63..93]));
+
+    // ID3v1.1 keeps the track number at the end of the comment.
+    if tag[125] == 0 && tag[126] != 0 {
+        tags.track = Some(u32::from(tag[126]));
+    }
+
+    Ok(())
+}
+
+fn read_flac(file: &mut File, tags: &mut Tags) -> io::Result<()> {
+    const VORBIS_COMMENT: u8 = 4;
+
+    file.seek(SeekFrom::Start(4))?;
+
+    loop {
+        let mut header = [0; 4];
+
+        if file.read_exact(&mut header).is_err() {
+            return Ok(());
+        }
+
+        let last = header[0] & 0x80 != 0;
+        let len = u64::from(u32::from_be_bytes([0, header[1], header[2], header[3]]));
+
+        if header[0] & 0x7f == VORBIS_COMMENT {
+            let mut block = Vec::new();
+            file.take(len.min(TAG_READ_LIMIT)).read_to_end(&mut block)?;
+            read_vorbis_comment(&block, tags);
+            return Ok(());
+        }
+
+        if last {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Current(len as i64))?;
+    }
+}
+
+fn read_vorbis_comment(block: &[u8], tags: &mut Tags) {
+    let u32_at = |pos: usize| {
+        block
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    let mut pos = match u32_at(0) {
+        Some(vendor_len) => 4 + vendor_len,
+        None => return,
+    };
+    let count = match u32_at(pos) {
+        Some(count) => count,
+        None => return,
+    };
+    pos += 4;
+
+    for _ in 0..count {
+        let len = match u32_at(pos) {
+            Some(len) => len,
+            None => return,
+        };
+        let comment = match block.get(pos + 4..pos + 4 + len) {
+            Some(comment) => String::from_utf8_lossy(comment),
+            None => return,
+        };
+        pos += 4 + len;
+
+        if let Some((key, value)) = comment.split_once('=') {
+            let key = match key.to_ascii_uppercase().as_str() {
+                "ARTIST" => "artist",
+                "ALBUM" => "album",
+                "TITLE" => "title",
+                "TRACKNUMBER" => "track",
+                _ => continue,
+            };
+
+            tags.set(key, value.to_owned());
+        }
+    }
+}
@@ -0,0 +1,510 @@
+//! Renaming in directories on other storage than the local filesystem,
+//! named by URLs such as `sftp://host/photos`.
+//!
+//! Remote files are planned like local ones, from what a listing tells
+//! about them: names, sizes and the times the storage keeps. Templates and
+//! sources reading the contents of files are not available. The journal of
+//! a remote directory is kept locally, see `state_dir`.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use crate::appledouble;
+use crate::interrupt;
+use crate::journal::{self, Journal, Op, RunKind};
+use crate::retry::RetryPolicy;
+use crate::s3::S3;
+use crate::sftp::Sftp;
+use crate::source::{self, Source, SourceChain, Strategy};
+use crate::stamp::{FileStamp, StaleReason};
+use crate::template;
+use crate::throttle;
+use crate::transfer::TransferMode;
+use crate::webdav::WebDav;
+use crate::{
+    drop_unchanged, is_of_age, is_of_kind, is_partial, resolve_collisions_by, ApplyOptions,
+    ErrorPolicy, FsItemError, Plan, PlanOptions, RenameFailure, RenameOutcome, RenamePlanItem,
+    RenameSkip, SkippedItem, INTERNAL_PREFIX,
+};
+
+/// How a remote directory is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Sftp,
+    S3,
+    /// WebDAV over plain HTTP.
+    Dav,
+    /// WebDAV over HTTPS.
+    Davs,
+    /// A phone or camera connected over USB, which files are imported from
+    /// rather than renamed on.
+    Mtp,
+}
+
+/// A remote directory, parsed from `scheme://[user@]host[:port]/path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub scheme: Scheme,
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// The path on the host, without the leading `/`.
+    pub path: String,
+}
+
+impl Url {
+    /// The remote directory `dir` names, or `None` for a local directory.
+    pub fn parse(dir: &Path) -> Result<Option<Url>, String> {
+        let text = match dir.to_str() {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let (scheme, rest) = match text.split_once("://") {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+
+        let scheme = match scheme {
+            "sftp" => Scheme::Sftp,
+            "s3" => Scheme::S3,
+            "dav" => Scheme::Dav,
+            "davs" => Scheme::Davs,
+            "mtp" => Scheme::Mtp,
+            _ => {
+                return Err(format!(
+                    "Unsupported kind of remote directory {}://",
+                    scheme
+                ))
+            }
+        };
+
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_owned()), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host, Some(port)),
+                Err(_) => return Err(format!("Invalid port in {}", text)),
+            },
+            None => (host, None),
+        };
+
+        if host.is_empty() {
+            return Err(format!("No host in {}", text));
+        }
+
+        Ok(Some(Url {
+            scheme,
+            user,
+            host: host.to_owned(),
+            port,
+            path: path.trim_end_matches('/').to_owned(),
+        }))
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self.scheme {
+            Scheme::Sftp => "sftp",
+            Scheme::S3 => "s3",
+            Scheme::Dav => "dav",
+            Scheme::Davs => "davs",
+            Scheme::Mtp => "mtp",
+        };
+
+        write!(f, "{}://", scheme)?;
+
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+
+        write!(f, "{}", self.host)?;
+
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+
+        write!(f, "/{}", self.path)
+    }
+}
+
+/// A file in a remote directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    /// The name relative to the directory, with `/` between directories.
+    pub name: String,
+    pub len: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// The creation time, where the storage keeps one.
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// Storage holding a remote directory that files can be renamed in.
+///
+/// Names are relative to that directory, with `/` between directories.
+pub trait Backend {
+    /// The files directly in the directory, leaving out subdirectories.
+    fn list(&mut self) -> io::Result<Vec<RemoteFile>>;
+
+    /// What is at `name`, if anything.
+    fn stat(&mut self, name: &str) -> io::Result<Option<RemoteFile>>;
+
+    /// Renames `from` to `to`, creating the directories `to` is in, and
+    /// fails if something is at `to` already.
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()>;
+}
+
+/// Settings for particular kinds of storage.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteOptions {
+    /// The S3 user metadata field, without `x-amz-meta-`, holding the
+    /// creation time of objects, as rclone and s3cmd keep it.
+    pub meta_time: Option<String>,
+    /// Delete files from MTP devices once imported.
+    pub delete_imported: bool,
+}
+
+/// Connects to the storage holding `url`.
+pub fn connect(url: &Url, options: &RemoteOptions) -> io::Result<Box<dyn Backend>> {
+    match url.scheme {
+        Scheme::Sftp => Ok(Box::new(Sftp::connect(url)?)),
+        Scheme::S3 => Ok(Box::new(S3::connect(url, options)?)),
+        Scheme::Dav | Scheme::Davs => Ok(Box::new(WebDav::connect(url)?)),
+        Scheme::Mtp => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "files on devices can only be imported",
+        )),
+    }
+}
+
+/// The local directory keeping the journal of the remote directory `url`,
+/// under `$XDG_STATE_HOME/crtime/remote`, `~/.local/state/crtime/remote`
+/// or `%LOCALAPPDATA%\crtime\remote`. It is created if need be.
+pub fn state_dir(url: &Url) -> io::Result<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")),
+        }
+    };
+    let base = base.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "no home directory to keep the journal in",
+        )
+    })?;
+
+    let url = url.to_string();
+    let readable: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let digest = Sha256::digest(url.as_bytes());
+    let hash: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+
+    let dir = base
+        .join("crtime")
+        .join("remote")
+        .join(format!("{}-{}", readable, hash));
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// Plans the renames of the files in the remote directory `root` like
+/// `crate::plan` does for a local one.
+pub fn plan(backend: &mut dyn Backend, root: &Path, options: &PlanOptions) -> io::Result<Plan> {
+    let started = Instant::now();
+    let mut plan = Plan::default();
+    let files = backend.list()?;
+    plan.timings.scanning = started.elapsed();
+
+    let names: HashSet<String> = files.iter().map(|file| file.name.clone()).collect();
+
+    for file in files {
+        let path = root.join(&file.name);
+
+        match plan_item(&file, root, options) {
+            Ok(item) => plan.items.push(item),
+            Err(FsItemError::ItemIsInternal)
+            | Err(FsItemError::ItemIsAppleDouble)
+            | Err(FsItemError::ItemIsOtherType)
+            | Err(FsItemError::ItemIsOtherAge) => {}
+            Err(reason) => plan.skipped.push(SkippedItem { path, reason }),
+        }
+    }
+
+    plan.items
+        .sort_by(|a, b| a.created.partial_cmp(&b.created).unwrap());
+    template::number(&mut plan.items, options);
+    resolve_collisions_by(&mut plan.items, &mut |path| {
+        let name = relative(root, path);
+
+        // Files straight in the directory are known from the listing.
+        names.contains(&name)
+            || (name.contains('/') && backend.stat(&name).ok().flatten().is_some())
+    });
+    drop_unchanged(&mut plan);
+
+    plan.timings.planning = started.elapsed().saturating_sub(plan.timings.scanning);
+
+    Ok(plan)
+}
+
+fn plan_item(
+    file: &RemoteFile,
+    root: &Path,
+    options: &PlanOptions,
+) -> Result<RenamePlanItem, FsItemError> {
+    let path = root.join(&file.name);
+
+    if file.name.starts_with(INTERNAL_PREFIX) {
+        return Err(FsItemError::ItemIsInternal);
+    }
+
+    if appledouble::is_companion_name(&file.name) {
+        return Err(FsItemError::ItemIsAppleDouble);
+    }
+
+    if !options.include_partial && is_partial(&file.name) {
+        return Err(FsItemError::ItemIsPartial);
+    }
+
+    if file.len < options.min_size {
+        return Err(FsItemError::ItemIsTooSmall);
+    }
+
+    if !is_of_kind(&file.name, options) {
+        return Err(FsItemError::ItemIsOtherType);
+    }
+
+    let (source, created) = resolve(&options.sources, file)?;
+    let created = options
+        .clock
+        .correct(&path, source, created)
+        .and_then(|created| options.offset.apply(created));
+    let created = match created {
+        Some(created) => created,
+        None => return Err(FsItemError::NoTimestamp(source)),
+    };
+
+    if !is_of_age(created, options) {
+        return Err(FsItemError::ItemIsOtherAge);
+    }
+
+    let new_name = options
+        .template
+        .render(&path, &file.name, created, options)?;
+    let new_path = match &options.organize {
+        Some(organize) => root
+            .join(organize.dir(&path, created, options))
+            .join(&new_name),
+        None => root.join(&new_name),
+    };
+
+    Ok(RenamePlanItem {
+        created,
+        source,
+        name: file.name.clone(),
+        new_name,
+        path,
+        new_path,
+        stamp: FileStamp {
+            len: file.len,
+            modified: file.modified.map(SystemTime::from),
+            inode: None,
+        },
+        replaces: false,
+    })
+}
+
+/// The first timestamp of `sources` known for `file`, like
+/// `SourceChain::resolve`, from what the listing tells.
+fn resolve(
+    sources: &SourceChain,
+    file: &RemoteFile,
+) -> Result<(Source, DateTime<Utc>), FsItemError> {
+    let read = |source| {
+        let time = match source {
+            Source::Btime => file.created.ok_or(FsItemError::NoBirthTime)?,
+            Source::Mtime => file.modified.ok_or(FsItemError::NoTimestamp(source))?,
+            Source::Filename => {
+                let name = file.name.rsplit('/').next().unwrap_or(&file.name);
+                source::filename_time(name).ok_or(FsItemError::NoTimestamp(source))?
+            }
+            _ => return Err(FsItemError::NoTimestamp(source)),
+        };
+
+        Ok((source, time))
+    };
+    let mut last_error = FsItemError::NoTimestamp(Source::Btime);
+
+    for step in &sources.0 {
+        let picked = match step {
+            Strategy::Single(source) => read(*source),
+            Strategy::Earliest(sources) => {
+                let times: Vec<_> = sources.iter().map(|&source| read(source)).collect();
+
+                match times.iter().flatten().min_by_key(|(_, time)| *time) {
+                    Some(&picked) => Ok(picked),
+                    None => times
+                        .into_iter()
+                        .last()
+                        .unwrap_or(Err(FsItemError::NoTimestamp(Source::Btime))),
+                }
+            }
+        };
+
+        match picked {
+            Ok(picked) => return Ok(picked),
+            Err(error) => last_error = error,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Performs the planned renames in the remote directory `root` like
+/// `crate::apply`, recording each in `journal`.
+///
+/// `TransferMode::Copy` is not supported here. With
+/// `ErrorPolicy::Rollback`, the renames done are undone by renaming the
+/// files back.
+pub fn apply<'a>(
+    backend: &mut dyn Backend,
+    root: &Path,
+    items: &'a [RenamePlanItem],
+    journal: &mut Journal,
+    options: &ApplyOptions,
+) -> io::Result<RenameOutcome<'a>> {
+    let mut outcome = RenameOutcome {
+        renamed: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+        bytes_copied: 0,
+        interrupted: false,
+        stopped: false,
+        rolled_back: Vec::new(),
+        backed_up: Vec::new(),
+    };
+
+    let run = journal.begin(RunKind::Rename, TransferMode::Move)?;
+
+    for item in items {
+        if interrupt::requested() {
+            outcome.interrupted = true;
+            break;
+        }
+
+        throttle::wait();
+
+        let from = relative(root, &item.path);
+        let to = relative(root, &item.new_path);
+
+        if let Err(reason) = check(backend, &from, &item.stamp) {
+            outcome.skipped.push(RenameSkip { item, reason });
+            continue;
+        }
+
+        match options.retry.run(|| backend.rename(&from, &to)) {
+            Ok(()) => {
+                journal.record(item)?;
+                outcome.renamed.push(item);
+            }
+            Err(reason) => {
+                outcome.failed.push(RenameFailure { item, reason });
+
+                if options.on_error != ErrorPolicy::Continue {
+                    outcome.stopped = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    if outcome.stopped && options.on_error == ErrorPolicy::Rollback {
+        journal.begin(RunKind::Undo(run), TransferMode::Move)?;
+
+        let mut kept = Vec::new();
+
+        while let Some(item) = outcome.renamed.pop() {
+            let undo = journal::item_op(item, TransferMode::Move)
+                .inverse()
+                .expect("renames can be undone");
+
+            match perform(backend, root, &undo, &options.retry) {
+                Ok(()) => {
+                    journal.record_op(&undo)?;
+                    outcome.rolled_back.push(item);
+                }
+                Err(_) => kept.push(item),
+            }
+        }
+
+        kept.reverse();
+        outcome.renamed = kept;
+    }
+
+    journal.flush()?;
+    Ok(outcome)
+}
+
+/// Performs `op` in the remote directory `root`, like
+/// `history::perform` does locally. Only moves can be done remotely.
+pub fn perform(
+    backend: &mut dyn Backend,
+    root: &Path,
+    op: &Op,
+    retry: &RetryPolicy,
+) -> io::Result<()> {
+    throttle::wait();
+
+    match op {
+        Op::Move { from, to } => {
+            let (from, to) = (relative(root, from), relative(root, to));
+            retry.run(|| backend.rename(&from, &to))
+        }
+        Op::Copy { .. } | Op::Remove { .. } => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only renames can be done in remote directories",
+        )),
+    }
+}
+
+/// Compares the remote file `name` with `stamp`, by size and
+/// modification time. A file that cannot be looked at is not taken for
+/// unchanged.
+fn check(backend: &mut dyn Backend, name: &str, stamp: &FileStamp) -> Result<(), StaleReason> {
+    match backend.stat(name) {
+        Ok(Some(file))
+            if file.len == stamp.len && file.modified.map(SystemTime::from) == stamp.modified =>
+        {
+            Ok(())
+        }
+        Ok(Some(_)) => Err(StaleReason::Modified),
+        Ok(None) => Err(StaleReason::Missing),
+        Err(_) => Err(StaleReason::Unknown),
+    }
+}
+
+/// The name of `path` relative to the remote directory `root`.
+fn relative(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let parts: Vec<_> = rel
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+
+    parts.join("/")
+}
@@ -0,0 +1,96 @@
+//! Files a rename replaces, moved aside first so that undo can bring them
+//! back.
+//!
+//! Each run puts them in a folder of its own, named after the time it
+//! started, in `.crtime-backup` next to them unless another directory is
+//! given. The move is recorded in the journal before the rename replacing
+//! the file.
+
+use chrono::Local;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::retry::RetryPolicy;
+use crate::stamp::FileStamp;
+use crate::transfer::{self, TransferMode};
+use crate::ApplyOptions;
+
+/// Name of the backup directory kept next to replaced files.
+pub const DIR_NAME: &str = ".crtime-backup";
+
+/// Where the files replaced in one run go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// Directory holding the folders of the runs, instead of `DIR_NAME`
+    /// next to each file.
+    pub dir: Option<PathBuf>,
+    /// Name of the folder of this run.
+    pub folder: String,
+}
+
+impl Backup {
+    /// Backups of a run starting now.
+    pub fn new(dir: Option<PathBuf>) -> Backup {
+        Backup {
+            dir,
+            folder: Local::now().format("%Y-%m-%d %H.%M.%S").to_string(),
+        }
+    }
+
+    /// Moves the file at `path` into the folder of this run, unless it is
+    /// the file with `stamp` itself, as under another case of its name.
+    /// Returns where it went, `None` when there was nothing to move.
+    pub fn displace(
+        &self,
+        path: &Path,
+        stamp: &FileStamp,
+        retry: &RetryPolicy,
+    ) -> io::Result<Option<PathBuf>> {
+        let meta = match path.symlink_metadata() {
+            Ok(meta) => meta,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        if stamp.inode.is_some() && FileStamp::of(&meta).inode == stamp.inode {
+            return Ok(None);
+        }
+
+        let folder = match (&self.dir, path.parent()) {
+            (Some(dir), _) => dir.join(&self.folder),
+            (None, Some(parent)) => parent.join(DIR_NAME).join(&self.folder),
+            (None, None) => return Ok(None),
+        };
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return Ok(None),
+        };
+
+        // Files of the same name from different directories share the
+        // folder when it is given, so later ones are numbered.
+        let backup = (1..)
+            .map(|n| match n {
+                1 => folder.join(&name),
+                n => folder.join(format!("{} ({})", name, n)),
+            })
+            .find(|backup| backup.symlink_metadata().is_err())
+            .unwrap();
+
+        transfer::transfer(path, &backup, &move_options(retry))?;
+
+        Ok(Some(backup))
+    }
+}
+
+/// Moves a file displaced by `displace` back to `path`.
+pub fn restore(backup: &Path, path: &Path, retry: &RetryPolicy) -> io::Result<()> {
+    transfer::transfer(backup, path, &move_options(retry)).map(|_| ())
+}
+
+fn move_options(retry: &RetryPolicy) -> ApplyOptions {
+    ApplyOptions {
+        retry: *retry,
+        mode: TransferMode::Move,
+        ..ApplyOptions::default()
+    }
+}
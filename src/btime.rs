@@ -0,0 +1,120 @@
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::FsItemError;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+thread_local! {
+    /// `statx` results read ahead by `prefetch`, taken by `birth_time`.
+    static PREFETCHED: std::cell::RefCell<std::collections::HashMap<std::path::PathBuf, io::Result<libc::statx>>> =
+        std::cell::RefCell::new(std::collections::HashMap::new());
+}
+
+/// Reads the birth times of all of `paths` ahead, in batches through
+/// io_uring, for `birth_time` to find later on this thread.
+///
+/// Does nothing where io_uring is not available, and `birth_time` then
+/// asks for each file on its own.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub fn prefetch(paths: Vec<std::path::PathBuf>) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let paths: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+            Some((path, c_path))
+        })
+        .collect();
+    let c_paths: Vec<_> = paths.iter().map(|(_, c_path)| c_path.clone()).collect();
+
+    let results = match crate::uring::statx_all(&c_paths, libc::STATX_BTIME) {
+        Ok(results) => results,
+        Err(_) => return,
+    };
+
+    PREFETCHED.with(|prefetched| {
+        let mut prefetched = prefetched.borrow_mut();
+        prefetched.clear();
+        prefetched.extend(paths.into_iter().map(|(path, _)| path).zip(results));
+    });
+}
+
+/// Creation (birth) time of the file at `path`.
+///
+/// Fails with `FsItemError::NoBirthTime` when the filesystem does not
+/// record one.
+#[cfg(all(target_os = "linux", feature = "statx"))]
+pub fn birth_time(path: &Path, _meta: &Metadata) -> Result<SystemTime, FsItemError> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[cfg(feature = "io-uring")]
+    {
+        let prefetched = PREFETCHED.with(|prefetched| prefetched.borrow_mut().remove(path));
+
+        if let Some(result) = prefetched {
+            return statx_birth_time(&result?);
+        }
+    }
+
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(c_path) => c_path,
+        Err(_) => return Err(FsItemError::NameFailed),
+    };
+
+    let mut buf: libc::statx = unsafe { mem::zeroed() };
+    let ret = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+            libc::STATX_BTIME,
+            &mut buf,
+        )
+    };
+
+    if ret != 0 {
+        return Err(FsItemError::Io(io::Error::last_os_error()));
+    }
+
+    statx_birth_time(&buf)
+}
+
+#[cfg(all(target_os = "linux", feature = "statx"))]
+fn statx_birth_time(buf: &libc::statx) -> Result<SystemTime, FsItemError> {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    if buf.stx_mask & libc::STATX_BTIME == 0 {
+        return Err(FsItemError::NoBirthTime);
+    }
+
+    let btime = buf.stx_btime;
+    let nanos = Duration::new(0, btime.tv_nsec);
+    let time = if btime.tv_sec >= 0 {
+        UNIX_EPOCH + Duration::from_secs(btime.tv_sec as u64) + nanos
+    } else {
+        UNIX_EPOCH - Duration::from_secs(btime.tv_sec.unsigned_abs()) + nanos
+    };
+
+    Ok(time)
+}
+
+/// Creation (birth) time of the file at `path`.
+///
+/// Fails with `FsItemError::NoBirthTime` when the filesystem does not
+/// record one.
+#[cfg(not(all(target_os = "linux", feature = "statx")))]
+pub fn birth_time(_path: &Path, meta: &Metadata) -> Result<SystemTime, FsItemError> {
+    match meta.created() {
+        Ok(time) => Ok(time),
+        Err(ref error) if error.kind() == io::ErrorKind::Unsupported => {
+            Err(FsItemError::NoBirthTime)
+        }
+        Err(error) => Err(FsItemError::Io(error)),
+    }
+}
@@ -0,0 +1,204 @@
+//! Timestamps read from the contents of files, kept in `.crtime-cache` so
+//! that going over a large archive again does not parse every photo
+//! again.
+//!
+//! An entry holds while the file has the size, modification time and
+//! inode it had when read. Looked up by inode too, it follows files that
+//! were renamed since, such as those a previous, interrupted run got to.
+//! Times are kept as the file records them, without an offset where it
+//! has none, so each run still reads them in its own timezone and by its
+//! own `--dst`. The file has a line per entry:
+//!
+//! ```text
+//! exif<TAB>len:seconds.nanos:dev:ino<TAB>2024-03-05T12:10:00<TAB>path
+//! ```
+//!
+//! with `-` for the time of a file found to have none.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::source::Source;
+use crate::stamp::FileStamp;
+
+/// Name of the cache in the directory it is kept for.
+pub const FILE_NAME: &str = ".crtime-cache";
+
+#[derive(Debug, Clone)]
+struct Entry {
+    stamp: FileStamp,
+    recorded: Option<String>,
+    /// Looked up or added since the cache was opened.
+    used: bool,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_path: HashMap<(Source, PathBuf), Entry>,
+    by_inode: HashMap<(Source, (u64, u64)), PathBuf>,
+    /// Changed since the cache was opened.
+    changed: bool,
+}
+
+impl Entries {
+    fn insert(&mut self, source: Source, path: PathBuf, entry: Entry) {
+        if let Some(inode) = entry.stamp.inode {
+            self.by_inode.insert((source, inode), path.clone());
+        }
+
+        self.by_path.insert((source, path), entry);
+    }
+}
+
+/// The cache of one directory, shared by the threads planning it.
+#[derive(Debug)]
+pub struct TimeCache {
+    path: PathBuf,
+    entries: Mutex<Entries>,
+}
+
+impl TimeCache {
+    /// Reads the cache kept in `dir`, or starts an empty one. Lines that
+    /// cannot be read are dropped.
+    pub fn open(dir: &Path) -> io::Result<TimeCache> {
+        let path = dir.join(FILE_NAME);
+        let mut entries = Entries::default();
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        for line in text.lines() {
+            if let Some((source, file, entry)) = parse(line) {
+                entries.insert(source, file, entry);
+            }
+        }
+
+        Ok(TimeCache {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The time `source` had for the file at `path`, `Some(None)` for
+    /// none, if it was read while the file was as `meta` describes it.
+    pub fn get(&self, source: Source, path: &Path, meta: &Metadata) -> Option<Option<String>> {
+        let stamp = FileStamp::of(meta);
+        let mut entries = self.lock();
+
+        let key = (source, path.to_path_buf());
+        let moved_from = match entries.by_path.get(&key) {
+            Some(entry) if entry.stamp == stamp => None,
+            _ => stamp
+                .inode
+                .and_then(|inode| entries.by_inode.get(&(source, inode)))
+                .filter(|from| {
+                    entries
+                        .by_path
+                        .get(&(source, from.to_path_buf()))
+                        .is_some_and(|entry| entry.stamp == stamp)
+                })
+                .cloned(),
+        };
+
+        if let Some(from) = moved_from {
+            let entry = entries.by_path.remove(&(source, from))?;
+            entries.insert(source, path.to_path_buf(), entry);
+            entries.changed = true;
+        }
+
+        let entry = entries
+            .by_path
+            .get_mut(&key)
+            .filter(|entry| entry.stamp == stamp)?;
+        entry.used = true;
+
+        Some(entry.recorded.clone())
+    }
+
+    /// Records the time `source` has for the file at `path`.
+    pub fn put(&self, source: Source, path: &Path, meta: &Metadata, recorded: Option<String>) {
+        if recorded
+            .as_deref()
+            .is_some_and(|text| text.contains(['\n', '\t']))
+        {
+            return;
+        }
+
+        let entry = Entry {
+            stamp: FileStamp::of(meta),
+            recorded,
+            used: true,
+        };
+        let mut entries = self.lock();
+
+        entries.insert(source, path.to_path_buf(), entry);
+        entries.changed = true;
+    }
+
+    /// Writes the cache back if anything was added, keeping the entries
+    /// used since it was opened and those of other files that are still
+    /// there.
+    pub fn save(&self) -> io::Result<()> {
+        let entries = self.lock();
+
+        if !entries.changed {
+            return Ok(());
+        }
+
+        let mut lines: Vec<String> = entries
+            .by_path
+            .iter()
+            .filter(|((_, path), entry)| entry.used || path.exists())
+            .filter_map(|((source, path), entry)| {
+                let path = path.to_str().filter(|path| !path.contains(['\n', '\t']))?;
+                let time = entry.recorded.as_deref().unwrap_or("-");
+
+                Some(format!("{}\t{}\t{}\t{}\n", source, entry.stamp, time, path))
+            })
+            .collect();
+        lines.sort();
+
+        // Written aside first, so an interrupted save leaves the old cache.
+        let temp = self.path.with_file_name(format!("{}.tmp", FILE_NAME));
+        let mut file = File::create(&temp)?;
+        file.write_all(lines.concat().as_bytes())?;
+        file.sync_data()?;
+
+        fs::rename(&temp, &self.path)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn parse(line: &str) -> Option<(Source, PathBuf, Entry)> {
+    let mut fields = line.splitn(4, '\t');
+    let source = fields.next()?.parse().ok()?;
+    let stamp = fields.next()?.parse().ok()?;
+    let recorded = match fields.next()? {
+        "-" => None,
+        text => Some(text.to_owned()),
+    };
+    let path = PathBuf::from(fields.next()?);
+
+    Some((
+        source,
+        path,
+        Entry {
+            stamp,
+            recorded,
+            used: false,
+        },
+    ))
+}
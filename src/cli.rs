@@ -0,0 +1,1950 @@
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::appledouble::{self, AppleDoublePolicy};
+use crate::backend::{self, Backend, RemoteOptions, Scheme, Url};
+use crate::backup::Backup;
+use crate::cache::TimeCache;
+use crate::clock::{ClockSync, SyncRef};
+use crate::color::{ColorChoice, Palette, Style};
+use crate::content::ContentType;
+use crate::dedup;
+use crate::doctor;
+use crate::dst::{self, DstPolicy};
+use crate::event::{Event, OutputFormat};
+use crate::fs_kind::FsKind;
+use crate::guard;
+use crate::hardlink;
+use crate::history::{self, ExportFormat, History};
+use crate::history_db;
+use crate::i18n::{fill, tr};
+use crate::interrupt;
+use crate::journal::{self, Journal, Op, RunKind};
+use crate::lock::DirLock;
+use crate::manifest;
+use crate::mapping;
+use crate::mtp;
+use crate::names;
+use crate::offset::Offset;
+use crate::pager;
+use crate::pick;
+use crate::plan_file::SavedPlan;
+use crate::profile::Timings;
+use crate::prompt;
+use crate::protect;
+use crate::schema;
+use crate::source::{Source, SourceChain, Strategy};
+use crate::space;
+use crate::stats::{Period, Stats};
+use crate::summary::{format_bytes, parse_bytes, Summary};
+use crate::template::Preset;
+use crate::throttle;
+use crate::transfer::TransferMode;
+use crate::verify;
+use crate::{
+    apply, plan, plan_all, resolve_conflicts, ApplyOptions, Conflict, ErrorPolicy, FsItemError,
+    Plan, PlanOptions, RenameOutcome, RenamePlanItem, Resolution, INTERNAL_PREFIX,
+};
+
+/// What to do with the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Rename,
+    Stats {
+        period: Period,
+    },
+    Inspect,
+    Doctor,
+    Resume,
+    Undo,
+    Redo,
+    /// Rename files as listed in a CSV file.
+    Apply,
+    /// Check name prefixes against creation times.
+    Verify,
+    /// Rename files with outdated prefixes.
+    Fix,
+    /// List files whose names sort out of chronological order.
+    CheckOrder,
+    /// Report duplicate files, or move them aside.
+    Dedup {
+        quarantine: bool,
+    },
+    /// Show the names files matching a path or pattern have had.
+    History,
+    /// Export the journal, or one run of it.
+    HistoryExport {
+        format: ExportFormat,
+        run: Option<u64>,
+    },
+    /// Print the JSON Schema of the machine-readable output.
+    Schema,
+}
+
+/// Subcommand names, recognized as the first argument.
+pub(crate) const COMMANDS: &[&str] = &[
+    "rename",
+    "stats",
+    "inspect",
+    "doctor",
+    "resume",
+    "undo",
+    "redo",
+    "history",
+    "apply",
+    "verify",
+    "fix",
+    "dedup",
+    "schema",
+    "check-order",
+];
+
+#[derive(Debug)]
+pub struct Config<'a> {
+    pub command: Command,
+    /// The directory to work in, or the file to inspect.
+    pub dir: &'a Path,
+    /// Further directories to rename in, planned together with `dir`.
+    pub more_dirs: Vec<&'a Path>,
+    pub plan: PlanOptions<'a>,
+    pub apply: ApplyOptions,
+    /// Directories to refuse besides the built-in ones.
+    pub protected: Vec<PathBuf>,
+    pub allow_protected: bool,
+    /// Apply batches of at most this many files without asking.
+    pub confirm_over: Option<usize>,
+    pub color: ColorChoice,
+    /// Line-oriented output without colors, alignment, abbreviations or a
+    /// pager, for screen readers and dumb terminals.
+    pub plain: bool,
+    pub output: OutputFormat,
+    /// Report where the time of the run went.
+    pub profile: bool,
+    /// Choose the files to rename in a fuzzy finder.
+    pub pick: bool,
+    /// Preview the directories as they will look afterwards instead of
+    /// the renames.
+    pub preview_listing: bool,
+    /// Files with known creation times to correct camera clocks by.
+    pub sync_refs: Vec<SyncRef>,
+    /// CSV file of old and new paths for `crtime apply`.
+    pub mapping: Option<&'a Path>,
+    /// Most file operations to do per second.
+    pub throttle: Option<f64>,
+    /// How to read local times made ambiguous by daylight saving time.
+    pub dst: DstPolicy,
+    pub remote: RemoteOptions,
+    /// Keep a `SHA256SUMS` manifest of the renamed files.
+    pub checksums: bool,
+    /// Keep the EXIF times read in a cache in the directory.
+    pub cache: bool,
+    /// SQLite database to copy the history into.
+    pub history_db: Option<&'a Path>,
+    /// Ask how to settle each conflict over a new name.
+    pub ask_conflicts: bool,
+    /// Replace files already at new paths, after moving them aside.
+    pub force: bool,
+}
+
+impl<'a> Config<'a> {
+    pub fn new(args: &[String]) -> Result<Config<'_>, String> {
+        let mut command = None;
+        let mut dir = None;
+        let mut more_dirs = Vec::new();
+        let mut plan = PlanOptions::default();
+        let mut apply = ApplyOptions::default();
+        let mut protected = Vec::new();
+        let mut allow_protected = false;
+        let mut confirm_over = None;
+        let mut color = ColorChoice::default();
+        let mut plain = false;
+        let mut output = OutputFormat::default();
+        let mut period = Period::default();
+        let mut profile = false;
+        let mut pick = false;
+        let mut preview_listing = false;
+        let mut export_format = ExportFormat::default();
+        let mut export_run = None;
+        let mut export = false;
+        let mut sync_refs = Vec::new();
+        let mut mapping = None;
+        let mut quarantine = false;
+        let mut throttle = None;
+        let mut dst = DstPolicy::default();
+        let mut remote = RemoteOptions::default();
+        let mut checksums = false;
+        let mut cache = false;
+        let mut history_db = None;
+        let mut ask_conflicts = false;
+        let mut conflicts_asked = false;
+        let mut force = false;
+        let mut backup_dir = None;
+        let mut args = args.iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dest" => match args.next() {
+                    Some(dest) => plan.dest = Some(Path::new(dest)),
+                    None => return Err(format!("Missing value for {}", arg)),
+                },
+                "--from-csv" => match args.next() {
+                    Some(file) => mapping = Some(Path::new(file)),
+                    None => return Err(format!("Missing value for {}", arg)),
+                },
+                "--source" => plan.sources = parse_value(arg, args.next())?,
+                "--template" => plan.template = parse_value(arg, args.next())?,
+                "--counter-start" => plan.counter_start = Some(parse_value(arg, args.next())?),
+                "--counter-width" => plan.counter_width = parse_value(arg, args.next())?,
+                "--preset" => plan.template = parse_value::<Preset>(arg, args.next())?.template(),
+                "--day-zero" => {
+                    plan.day_zero = match args.next() {
+                        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                            Ok(date) => Some(date),
+                            Err(_) => return Err(format!("Invalid value for {}", arg)),
+                        },
+                        None => return Err(format!("Missing value for {}", arg)),
+                    }
+                }
+                "--organize" => plan.organize = Some(parse_value(arg, args.next())?),
+                "--type" => plan.content_type = Some(parse_value(arg, args.next())?),
+                "--images" => plan.kinds.push(ContentType::Image),
+                "--videos" => plan.kinds.push(ContentType::Video),
+                "--audio" => plan.kinds.push(ContentType::Audio),
+                "--documents" => plan.kinds.push(ContentType::Document),
+                "--older-than" => plan.older_than = Some(parse_age(arg, args.next())?),
+                "--newer-than" => plan.newer_than = Some(parse_age(arg, args.next())?),
+                "--include-partial" => plan.include_partial = true,
+                "--skip-empty" => plan.min_size = plan.min_size.max(1),
+                "--min-size" => {
+                    plan.min_size = args
+                        .next()
+                        .and_then(|value| parse_bytes(value))
+                        .ok_or_else(|| format!("Invalid value for {}", arg))?
+                }
+                "--hardlinks" => plan.hardlinks = parse_value(arg, args.next())?,
+                "--invalid-names" => plan.invalid_names = parse_value(arg, args.next())?,
+                "--ask-conflicts" => {
+                    ask_conflicts = prompt::is_interactive();
+                    conflicts_asked = true;
+                }
+                "--force" => force = true,
+                "--fix-perms" => apply.fix_perms = true,
+                "--backup-dir" => match args.next() {
+                    Some(dir) => backup_dir = Some(PathBuf::from(dir)),
+                    None => return Err(format!("Missing value for {}", arg)),
+                },
+                "--appledouble" => plan.appledouble = parse_value(arg, args.next())?,
+                "--offset" => plan.offset = parse_value(arg, args.next())?,
+                "--dst" => dst = parse_value(arg, args.next())?,
+                #[cfg(feature = "gps-timezone")]
+                "--gps-timezone" => plan.gps_timezone = true,
+                #[cfg(not(feature = "gps-timezone"))]
+                "--gps-timezone" => {
+                    return Err(
+                        "--gps-timezone needs crtime built with the gps-timezone feature"
+                            .to_owned(),
+                    )
+                }
+                "--sync-ref" => sync_refs.push(parse_value(arg, args.next())?),
+                "--recursive" | "-r" => plan.recursive = true,
+                "--flatten" => plan.flatten = true,
+                "--copy" => apply.mode = TransferMode::Copy,
+                "--verify" => apply.verify = true,
+                "--checksums" => checksums = true,
+                "--strip-zone-id" => apply.strip_zone_identifier = true,
+                "--fail-fast" => apply.on_error = ErrorPolicy::FailFast,
+                "--atomic" => apply.on_error = ErrorPolicy::Rollback,
+                "--jobs" | "-j" => apply.jobs = parse_value(arg, args.next())?,
+                "--cache" => cache = true,
+                "--history-db" => match args.next() {
+                    Some(db) => history_db = Some(Path::new(db)),
+                    None => return Err(format!("Missing value for {}", arg)),
+                },
+                "--read-jobs" => plan.read_jobs = parse_value(arg, args.next())?,
+                "--read-timeout" => {
+                    plan.read_timeout = Some(Duration::from_secs(parse_value(arg, args.next())?))
+                }
+                "--delete-imported" => remote.delete_imported = true,
+                "--s3-meta-time" => match args.next() {
+                    Some(field) => remote.meta_time = Some(field.to_owned()),
+                    None => return Err(format!("Missing value for {}", arg)),
+                },
+                "--throttle" => {
+                    let rate: f64 = parse_value(arg, args.next())?;
+
+                    if !(rate > 0.0 && rate.is_finite()) {
+                        return Err(format!("Invalid value for {}", arg));
+                    }
+
+                    throttle = Some(rate);
+                }
+                #[cfg(feature = "exif-write")]
+                "--write-exif" => apply.write_exif = true,
+                #[cfg(not(feature = "exif-write"))]
+                "--write-exif" => {
+                    return Err(
+                        "--write-exif needs crtime built with the exif-write feature".to_owned(),
+                    )
+                }
+                "--retries" => apply.retry.retries = parse_value(arg, args.next())?,
+                "--retry-delay" => {
+                    apply.retry.delay = Duration::from_millis(parse_value(arg, args.next())?)
+                }
+                "--protect" => protected.push(parse_value(arg, args.next())?),
+                "--i-know-what-im-doing" => allow_protected = true,
+                "--confirm-over" => confirm_over = Some(parse_value(arg, args.next())?),
+                "--yes" | "-y" => confirm_over = Some(usize::MAX),
+                "--color" => color = parse_value(arg, args.next())?,
+                "--plain" => plain = true,
+                "--output" => output = parse_value(arg, args.next())?,
+                "--by" => period = parse_value(arg, args.next())?,
+                "--profile" => profile = true,
+                "--pick" => pick = true,
+                "--preview-listing" => preview_listing = true,
+                "--quarantine" => quarantine = true,
+                "--format" => export_format = parse_value(arg, args.next())?,
+                "--run" => export_run = Some(parse_value(arg, args.next())?),
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                name if command.is_none() && dir.is_none() && COMMANDS.contains(&name) => {
+                    command = Some(name)
+                }
+                "export" if command == Some("history") && !export && dir.is_none() => export = true,
+                _ if dir.is_none() => dir = Some(Path::new(arg)),
+                _ if !arg.starts_with('-') => more_dirs.push(Path::new(arg)),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        let dir = match dir {
+            Some(dir) => dir,
+            None if command == Some("schema") => Path::new(""),
+            None => return Err("Not enough arguments".to_owned()),
+        };
+
+        plan.defer_conflicts = ask_conflicts || force;
+        // Copies leave the originals as they are, so they need no write
+        // access.
+        plan.include_protected = apply.fix_perms || apply.mode == TransferMode::Copy;
+
+        if plan.defer_conflicts {
+            apply.backup = Some(Backup::new(backup_dir));
+        } else if backup_dir.is_some() && !conflicts_asked {
+            return Err("--backup-dir needs --force or --ask-conflicts".to_owned());
+        }
+
+        if plan.template.counts_days() && plan.day_zero.is_none() {
+            return Err("{day_num} needs --day-zero".to_owned());
+        }
+
+        let command = match command {
+            Some("stats") => Command::Stats { period },
+            Some("inspect") => Command::Inspect,
+            Some("doctor") => Command::Doctor,
+            Some("resume") => Command::Resume,
+            Some("undo") => Command::Undo,
+            Some("redo") => Command::Redo,
+            Some("history") if export => Command::HistoryExport {
+                format: export_format,
+                run: export_run,
+            },
+            Some("history") => Command::History,
+            Some("verify") => Command::Verify,
+            Some("fix") => Command::Fix,
+            Some("check-order") => Command::CheckOrder,
+            Some("dedup") => Command::Dedup { quarantine },
+            Some("schema") => Command::Schema,
+            Some("apply") if mapping.is_some() => Command::Apply,
+            Some("apply") => return Err("crtime apply needs --from-csv <file>".to_owned()),
+            _ => Command::Rename,
+        };
+
+        if !more_dirs.is_empty() && command != Command::Rename {
+            return Err("Only renaming takes several directories".to_owned());
+        }
+
+        Ok(Config {
+            command,
+            dir,
+            more_dirs,
+            plan,
+            apply,
+            protected,
+            allow_protected,
+            confirm_over,
+            color,
+            plain,
+            output,
+            profile,
+            pick,
+            preview_listing,
+            sync_refs,
+            mapping,
+            throttle,
+            dst,
+            remote,
+            checksums,
+            cache,
+            history_db,
+            ask_conflicts,
+            force,
+        })
+    }
+
+    /// `dir` followed by `more_dirs`.
+    fn roots(&self) -> Vec<&'a Path> {
+        std::iter::once(self.dir)
+            .chain(self.more_dirs.iter().copied())
+            .collect()
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    match value.map(|value| value.parse()) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(format!("Invalid value for {}", flag)),
+    }
+}
+
+/// A length of time like `30d`, as `--offset` takes them but not negative.
+fn parse_age(flag: &str, value: Option<&String>) -> Result<chrono::Duration, String> {
+    match parse_value(flag, value)? {
+        Offset(age) if age >= chrono::Duration::zero() => Ok(age),
+        _ => Err(format!("Invalid value for {}", flag)),
+    }
+}
+
+pub fn run(mut config: Config) -> Result<(), Box<dyn Error>> {
+    if !config.sync_refs.is_empty() {
+        config.plan.clock = ClockSync::measure(&config.sync_refs, &config.plan.sources)?;
+    }
+
+    if let Some(rate) = config.throttle {
+        throttle::set(rate);
+    }
+
+    dst::set(config.dst);
+
+    if config.cache {
+        config.plan.cache = Some(Arc::new(TimeCache::open(config.dir)?));
+    }
+
+    let cache = config.plan.cache.clone();
+    let (dir, db) = (config.dir, config.history_db);
+    let result = match config.command {
+        Command::Rename => rename(config),
+        Command::Stats { period } => stats(config, period),
+        Command::Inspect => inspect(config),
+        Command::Doctor => doctor(config),
+        Command::Resume => resume(config),
+        Command::Undo => travel(config, true),
+        Command::Redo => travel(config, false),
+        Command::Apply => apply_mapping(config),
+        Command::Verify => verify(config),
+        Command::Fix => fix(config),
+        Command::CheckOrder => check_order(config),
+        Command::Dedup { quarantine } => dedup(config, quarantine),
+        Command::History => show_history(config),
+        Command::HistoryExport { format, run } => export_history(config, format, run),
+        Command::Schema => {
+            print!("{}", schema::JSON_SCHEMA);
+            Ok(())
+        }
+    };
+
+    // Kept even when the command failed, for the times read before.
+    if let Some(cache) = cache {
+        cache.save()?;
+    }
+
+    if let Some(db) = db {
+        match Url::parse(dir)? {
+            Some(url) => {
+                let state_dir = backend::state_dir(&url)?;
+                history_db::sync(db, &state_dir, Some(&url.to_string()))?
+            }
+            None => history_db::sync(db, dir, None)?,
+        }
+    }
+
+    result
+}
+
+fn doctor(config: Config) -> Result<(), Box<dyn Error>> {
+    fn yes_no(value: Option<bool>) -> &'static str {
+        match value {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        }
+    }
+
+    let caps = doctor::probe(config.dir)?;
+    let fs_name = match caps.fs_kind {
+        FsKind::Fat => "FAT",
+        FsKind::ExFat => "exFAT",
+        FsKind::Ntfs => "NTFS",
+        FsKind::Other => "other",
+    };
+
+    println!("{}\n", fill("Directory: {}", &[&config.dir.display()]));
+    println!("  Filesystem       {}", fs_name);
+    println!("  Writable         {}", yes_no(Some(caps.writable)));
+    println!("  Birth times      {}", yes_no(caps.birth_time));
+    println!("  Case sensitive   {}", yes_no(caps.case_sensitive));
+
+    match caps.name_max {
+        Some(max) => println!("  Max name length  {} bytes", max),
+        None => println!("  Max name length  unknown"),
+    }
+
+    println!("  Extended attrs   {}", yes_no(caps.xattr));
+
+    let mut notes = Vec::new();
+
+    if !caps.writable {
+        notes.push("Files cannot be renamed here: the directory is not writable.".to_owned());
+    }
+
+    if caps.birth_time == Some(false) {
+        notes.push(
+            "The default btime source will skip every file; use e.g. --source exif,mtime."
+                .to_owned(),
+        );
+    }
+
+    if matches!(caps.fs_kind, FsKind::Fat | FsKind::ExFat) {
+        notes.push("File times are rounded to 2 seconds on this filesystem.".to_owned());
+    }
+
+    if names::has_windows_rules(caps.fs_kind) {
+        notes.push(
+            "Characters like : and ? in new names are replaced, see --invalid-names.".to_owned(),
+        );
+    }
+
+    if caps.case_sensitive == Some(false) {
+        notes.push("Names differing only in case refer to the same file.".to_owned());
+    }
+
+    if let Some(max) = caps.name_max {
+        notes.push(format!(
+            "New names longer than {} bytes will fail to apply.",
+            max
+        ));
+    }
+
+    if !notes.is_empty() {
+        println!();
+
+        for note in notes {
+            println!("- {}", note);
+        }
+    }
+
+    Ok(())
+}
+
+fn inspect(config: Config) -> Result<(), Box<dyn Error>> {
+    const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f UTC";
+
+    let path = config.dir;
+    let meta = fs::symlink_metadata(path)?;
+    let fs_kind = FsKind::detect(path);
+    let chain = &config.plan.sources;
+
+    println!("File: {}\n", path.display());
+
+    for &source in &Source::ALL {
+        match source.read(path, &meta, fs_kind) {
+            Ok(time) => println!("  {:<9} {}", source, time.format(TIME_FORMAT)),
+            Err(error) => println!("  {:<9} - ({})", source, error),
+        }
+    }
+
+    println!("\nSource chain: {}", chain);
+
+    match chain.resolve(path, &meta, fs_kind) {
+        Ok((source, time)) => {
+            println!("Picked: {} {}", source, time.format(TIME_FORMAT));
+
+            let adjusted = config
+                .plan
+                .clock
+                .correct(path, source, time)
+                .and_then(|time| config.plan.offset.apply(time));
+
+            match adjusted {
+                Some(adjusted) if adjusted == time => {}
+                Some(adjusted) => println!("Adjusted: {}", adjusted.format(TIME_FORMAT)),
+                None => println!("Adjusted: out of range"),
+            }
+        }
+        Err(error) => println!("Picked: nothing ({})", error),
+    }
+
+    Ok(())
+}
+
+fn stats(config: Config, period: Period) -> Result<(), Box<dyn Error>> {
+    const BAR_WIDTH: usize = 40;
+
+    let plan = plan(config.dir, &config.plan)?;
+    let stats = Stats::new(&plan, period);
+    let largest = stats.histogram.values().cloned().max().unwrap_or(0);
+
+    println!("{}", fill("Directory: {}", &[&config.dir.display()]));
+    println!("\nCreation times:");
+
+    for (label, count) in &stats.histogram {
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest));
+        println!("{:<10} {:<width$} {}", label, bar, count, width = BAR_WIDTH);
+    }
+
+    println!("\nExtensions:");
+
+    let mut extensions: Vec<_> = stats.extensions.iter().collect();
+    extensions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+    for (extension, count) in extensions {
+        let extension = if extension.is_empty() {
+            "(none)"
+        } else {
+            extension
+        };
+
+        println!("{:<10} {}", extension, count);
+    }
+
+    if stats.unreadable > 0 {
+        println!(
+            "\n{} files without a readable creation time",
+            stats.unreadable
+        );
+    }
+
+    Ok(())
+}
+
+/// Lists prefixed files whose names no longer match their creation times.
+fn verify(config: Config) -> Result<(), Box<dyn Error>> {
+    let palette = palette(&config);
+    let plan = plan(config.dir, &config.plan)?;
+    let report = verify::check(&plan);
+
+    println!("{}", fill("Directory: {}", &[&config.dir.display()]));
+
+    for mismatch in &report.mismatches {
+        let item = mismatch.item;
+
+        println!(
+            "Mismatch: {}{}: {} says {}",
+            subdir(&item.path, config.dir, !config.plain),
+            item.name,
+            item.source,
+            palette.paint(Style::Warning, mismatch.expected)
+        );
+    }
+
+    for skipped in &report.unresolved {
+        println!(
+            "Unresolved: {}: {} [{}]",
+            skipped.path.display(),
+            palette.paint(Style::Warning, &skipped.reason.to_string()),
+            skipped.reason.code()
+        );
+    }
+
+    println!(
+        "\n{} prefixed files checked, {} mismatched, {} unresolved.",
+        report.checked,
+        report.mismatches.len(),
+        report.unresolved.len()
+    );
+
+    if report.mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Names of {} files do not match their creation times",
+            report.mismatches.len()
+        )
+        .into())
+    }
+}
+
+/// Lists the files whose names sort before files created earlier, failing
+/// if there are any.
+fn check_order(config: Config) -> Result<(), Box<dyn Error>> {
+    let palette = palette(&config);
+    let plan = plan(config.dir, &config.plan)?;
+    let out_of_order = verify::out_of_order(&plan.items);
+
+    println!("{}", fill("Directory: {}", &[&config.dir.display()]));
+
+    for item in &out_of_order {
+        println!(
+            "Out of order: {}{}: created {}",
+            subdir(&item.path, config.dir, !config.plain),
+            item.name,
+            palette.paint(
+                Style::Warning,
+                &item.created.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+            )
+        );
+    }
+
+    println!(
+        "\n{} files checked, {} out of order.",
+        plan.items.len(),
+        out_of_order.len()
+    );
+
+    if out_of_order.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Names of {} files are out of the order of their creation times",
+            out_of_order.len()
+        )
+        .into())
+    }
+}
+
+fn fix(config: Config) -> Result<(), Box<dyn Error>> {
+    check_protected(&config)?;
+
+    let started = Instant::now();
+    let plan = verify::fix(plan(config.dir, &config.plan)?);
+    let planning = started.elapsed();
+
+    execute(config, plan, planning)
+}
+
+fn dedup(config: Config, quarantine: bool) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut plan = plan(config.dir, &config.plan)?;
+    let quarantine_dir = config.dir.join(dedup::QUARANTINE_DIR);
+    plan.items
+        .retain(|item| !item.path.starts_with(&quarantine_dir));
+    let groups = dedup::groups(&plan.items)?;
+    let planning = started.elapsed();
+
+    if quarantine {
+        check_protected(&config)?;
+
+        let plan = dedup::quarantine(plan, &groups, config.dir);
+        return execute(config, plan, planning);
+    }
+
+    println!("{}", fill("Directory: {}", &[&config.dir.display()]));
+
+    let mut wasted = 0;
+
+    for group in &groups {
+        let kept = &plan.items[group[0]];
+
+        println!(
+            "\nDuplicates of {}{} ({}):",
+            subdir(&kept.path, config.dir, !config.plain),
+            kept.name,
+            format_bytes(kept.stamp.len)
+        );
+
+        for &i in &group[1..] {
+            let item = &plan.items[i];
+            println!(
+                "  {}{}",
+                subdir(&item.path, config.dir, !config.plain),
+                item.name
+            );
+            wasted += item.stamp.len;
+        }
+    }
+
+    let duplicates: usize = groups.iter().map(|group| group.len() - 1).sum();
+
+    println!(
+        "\n{} duplicate files in {} groups, {} to free.",
+        duplicates,
+        groups.len(),
+        format_bytes(wasted)
+    );
+
+    Ok(())
+}
+
+/// Refuses to work in protected directories unless told otherwise.
+fn check_protected(config: &Config) -> Result<(), Box<dyn Error>> {
+    if !config.allow_protected {
+        let protected = guard::protected_dirs(&config.protected);
+
+        for dir in config.roots() {
+            guard::check(dir, &protected)?;
+        }
+
+        if let Some(dest) = config.plan.dest {
+            guard::check(dest, &protected)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn export_history(
+    config: Config,
+    format: ExportFormat,
+    run: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let history = History::load(config.dir)?;
+    let runs: Vec<_> = history
+        .runs()
+        .iter()
+        .filter(|r| run.is_none_or(|id| r.id == id))
+        .collect();
+
+    if let (Some(id), true) = (run, runs.is_empty()) {
+        return Err(format!("No run {} in the journal", id).into());
+    }
+
+    match format {
+        ExportFormat::Json => print!("{}", history::to_json(&runs)),
+        ExportFormat::Csv => print!("{}", history::to_csv(&runs)),
+    }
+
+    Ok(())
+}
+
+/// Lists the names of the files that had a name matching `config.dir`, as
+/// the closest journal above it recorded them.
+fn show_history(config: Config) -> Result<(), Box<dyn Error>> {
+    let palette = palette(&config);
+    let pattern = std::path::absolute(config.dir)?;
+    let (pattern_dir, name) = match (pattern.parent(), pattern.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+        _ => return Err(format!("Not a file path: {}", config.dir.display()).into()),
+    };
+    let journal_dir = pattern_dir
+        .ancestors()
+        .find(|dir| dir.join(journal::JOURNAL_NAME).is_file())
+        .ok_or_else(|| format!("No journal in or above {}", pattern_dir.display()))?;
+
+    let history = History::load(journal_dir)?;
+    let runs = history.runs();
+    // Relative paths in the journal are relative to where crtime ran,
+    // which is often the directory of the journal.
+    let matches = |path: &Path| {
+        [path.to_path_buf(), journal_dir.join(path)]
+            .iter()
+            .filter_map(|path| std::path::absolute(path).ok())
+            .any(|path| {
+                path.parent() == Some(pattern_dir)
+                    && path
+                        .file_name()
+                        .is_some_and(|file| history::glob_match(&name, &file.to_string_lossy()))
+            })
+    };
+    let lineages: Vec<_> = history::lineages(runs)
+        .into_iter()
+        .filter(|lineage| lineage.names.iter().any(|(path, _)| matches(path)))
+        .collect();
+
+    if lineages.is_empty() {
+        return Err(format!("No file named {} in the journal", config.dir.display()).into());
+    }
+
+    let rel = |path: &Path| {
+        let path = path
+            .strip_prefix(journal_dir)
+            .or_else(|_| path.strip_prefix("."))
+            .unwrap_or(path);
+        path.display().to_string()
+    };
+    let describe = |id: u64| {
+        let run = runs.iter().find(|run| run.id == id);
+        let kind = match run.map(|run| run.kind) {
+            Some(RunKind::Undo(target)) => format!(", undoing run {}", target),
+            Some(RunKind::Redo(target)) => format!(", redoing run {}", target),
+            _ => String::new(),
+        };
+        let started = run
+            .and_then(|run| run.started)
+            .map_or(String::new(), |started| {
+                started
+                    .with_timezone(&chrono::Local)
+                    .format(" on %Y-%m-%d %H:%M:%S")
+                    .to_string()
+            });
+
+        palette.paint(Style::Timestamp, &format!("run {}{}{}", id, started, kind))
+    };
+
+    println!("{}", fill("Directory: {}", &[&journal_dir.display()]));
+
+    for lineage in &lineages {
+        println!();
+
+        for (path, run) in &lineage.names {
+            match run {
+                Some(id) => println!("  {} {}  {}", palette.arrow(), rel(path), describe(*id)),
+                None => println!("  {}", rel(path)),
+            }
+        }
+
+        if let Some(id) = lineage.removed {
+            println!("  {} removed  {}", palette.arrow(), describe(id));
+        }
+    }
+
+    Ok(())
+}
+
+/// Undoes the latest run in effect, or redoes the latest one undone.
+fn travel(config: Config, undo: bool) -> Result<(), Box<dyn Error>> {
+    let remote = Url::parse(config.dir)?;
+    let state_dir = match &remote {
+        Some(url) => backend::state_dir(url)?,
+        None => {
+            check_protected(&config)?;
+            config.dir.to_path_buf()
+        }
+    };
+
+    let palette = palette(&config);
+    let _lock = DirLock::acquire(&state_dir)?;
+    let history = History::load(&state_dir)?;
+
+    let (run, ops, kind, verb) = match (undo, history.undoable(), history.redoable()) {
+        (true, Some(run), _) => (run, history.ops_to_undo(run), RunKind::Undo(run.id), "undo"),
+        (false, _, Some(run)) => (run, history.ops_to_redo(run), RunKind::Redo(run.id), "redo"),
+        _ => {
+            let verb = if undo { "undo" } else { "redo" };
+            println!("Nothing to {} in {}.", verb, config.dir.display());
+            return Ok(());
+        }
+    };
+
+    println!("{}", fill("Directory: {}", &[&config.dir.display()]));
+
+    match run.started {
+        Some(started) => println!(
+            "Run {} from {}:",
+            run.id,
+            started
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => println!("Run {}:", run.id),
+    }
+
+    let dir = std::path::absolute(config.dir)?;
+    let rel = |path: &Path| {
+        let path = path
+            .strip_prefix(&dir)
+            .or_else(|_| path.strip_prefix(config.dir))
+            .unwrap_or(path);
+        path.display().to_string()
+    };
+
+    for op in &ops {
+        match op {
+            Op::Move { from, to } => {
+                println!("Move: {} {} {}", rel(from), palette.arrow(), rel(to))
+            }
+            Op::Copy { from, to } => {
+                println!("Copy: {} {} {}", rel(from), palette.arrow(), rel(to))
+            }
+            Op::Remove { path } => println!("Remove: {}", rel(path)),
+        }
+    }
+
+    let confirmed = match config.confirm_over {
+        Some(limit) if ops.len() <= limit => true,
+        _ => prompt::confirm(),
+    };
+
+    if !confirmed {
+        println!("Cancelled.");
+        return Ok(());
+    }
+
+    let mut journal = match remote {
+        Some(_) => Journal::open_remote(&state_dir)?,
+        None => Journal::open(&state_dir)?,
+    };
+    let (done, failure) = match &remote {
+        Some(url) => {
+            let mut backend = connect(url, &config.remote)?;
+            let retry = config.apply.retry;
+
+            history::replay_with(&ops, kind, &mut journal, &mut |op| {
+                backend::perform(backend.as_mut(), config.dir, op, &retry)
+            })?
+        }
+        None if config.apply.fix_perms => {
+            let retry = config.apply.retry;
+
+            history::replay_with(&ops, kind, &mut journal, &mut |op| match op {
+                Op::Move { from, to } => {
+                    protect::unprotected(from, to, || history::perform(op, &retry))
+                }
+                _ => history::perform(op, &retry),
+            })?
+        }
+        None => history::replay(&ops, kind, &mut journal, &config.apply.retry)?,
+    };
+
+    if let Some((op, error)) = failure {
+        let path = match op {
+            Op::Move { from, .. } | Op::Copy { from, .. } => from,
+            Op::Remove { path } => path,
+        };
+
+        println!(
+            "Failed: {}: {}",
+            rel(&path),
+            palette.paint(Style::Error, &error.to_string())
+        );
+    }
+
+    println!(
+        "\nDid {} of {} operations to {} run {}.",
+        done,
+        ops.len(),
+        verb,
+        run.id
+    );
+
+    Ok(())
+}
+
+fn resume(config: Config) -> Result<(), Box<dyn Error>> {
+    check_protected(&config)?;
+
+    let palette = palette(&config);
+    let _lock = DirLock::acquire(config.dir)?;
+
+    let saved = match SavedPlan::load(config.dir) {
+        Ok(saved) => saved,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+            println!("Nothing to resume in {}.", config.dir.display());
+            return Ok(());
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let mode = saved.mode;
+    let progress = saved.progress(config.dir)?;
+
+    println!(
+        "Already renamed: {}",
+        progress.done.len() + progress.changed.len()
+    );
+
+    for (item, reason) in &progress.changed {
+        println!(
+            "Changed: {}: {} [{}]",
+            item.new_path.display(),
+            palette.paint(Style::Warning, &reason.to_string()),
+            reason.code()
+        );
+    }
+
+    let plan = Plan {
+        items: progress.remaining,
+        ..Plan::default()
+    };
+
+    if plan.items.is_empty() {
+        SavedPlan::remove(config.dir)?;
+        println!("Nothing left to rename.");
+        return Ok(());
+    }
+
+    // Saved paths are absolute, so shown relative to the directory made so.
+    let dir = std::path::absolute(config.dir)?;
+    let config = Config {
+        dir: &dir,
+        apply: ApplyOptions {
+            mode,
+            ..config.apply
+        },
+        ..config
+    };
+
+    let mut preview = String::new();
+    write_plan(&mut preview, &config, &plan, &palette)?;
+    show(&config, &preview)?;
+
+    let confirmed = match config.confirm_over {
+        Some(limit) if plan.items.len() <= limit => true,
+        _ => prompt::confirm(),
+    };
+
+    if !confirmed {
+        println!("{}", tr("Renaming cancelled."));
+        return Ok(());
+    }
+
+    let mut journal = Journal::open(config.dir)?;
+    interrupt::install()?;
+
+    let started = Instant::now();
+    let outcome = apply(
+        &plan.items,
+        Some(&mut journal),
+        &config.apply,
+        &mut |_: Event| {},
+    )?;
+    let summary = Summary::new(&plan, &outcome, started.elapsed());
+
+    if !outcome.interrupted {
+        SavedPlan::remove(config.dir)?;
+    }
+
+    print_outcome(&outcome, plan.items.len(), &palette);
+    print_summary(&summary);
+
+    Ok(())
+}
+
+fn rename(config: Config) -> Result<(), Box<dyn Error>> {
+    if let Some(url) = Url::parse(config.dir)? {
+        return match url.scheme {
+            Scheme::Mtp => import(config, url),
+            _ => rename_remote(config, url),
+        };
+    }
+
+    check_protected(&config)?;
+
+    let started = Instant::now();
+    let mut plan = plan_all(&config.roots(), &config.plan)?;
+    let planning = started.elapsed();
+
+    if config.plan.defer_conflicts {
+        resolve_conflicts(&mut plan, &mut |item, conflict| match conflict {
+            Conflict::Existing if config.force => Resolution::Overwrite,
+            _ if config.ask_conflicts => prompt::resolve(item, conflict),
+            _ => Resolution::AddNumber,
+        });
+    }
+
+    execute(config, plan, planning)
+}
+
+/// Connects to the storage holding `url`, naming it in errors.
+fn connect(url: &Url, options: &RemoteOptions) -> Result<Box<dyn Backend>, String> {
+    backend::connect(url, options).map_err(|error| format!("Cannot connect to {}: {}", url, error))
+}
+
+/// Renames in a remote directory, keeping its journal locally.
+fn rename_remote(config: Config, url: Url) -> Result<(), Box<dyn Error>> {
+    if !config.more_dirs.is_empty() || config.plan.dest.is_some() {
+        return Err("Remote directories can only be renamed in on their own".into());
+    }
+
+    if config.apply.mode == TransferMode::Copy {
+        return Err("--copy does not work on remote directories".into());
+    }
+
+    if config.force {
+        return Err("--force does not work on remote directories".into());
+    }
+
+    if config.plan.template.reads_contents()
+        || config.plan.content_type.is_some()
+        || config.plan.gps_timezone
+    {
+        return Err("Remote files cannot be named by their contents".into());
+    }
+
+    let palette = palette(&config);
+    let mut backend = connect(&url, &config.remote)?;
+    let plan = backend::plan(backend.as_mut(), config.dir, &config.plan)?;
+
+    let mut preview = String::new();
+    write_plan(&mut preview, &config, &plan, &palette)?;
+    show(&config, &preview)?;
+
+    let confirmed = match config.confirm_over {
+        Some(limit) if plan.items.len() <= limit => true,
+        _ => prompt::confirm(),
+    };
+
+    if !confirmed {
+        println!("{}", tr("Renaming cancelled."));
+        return Ok(());
+    }
+
+    let state_dir = backend::state_dir(&url)?;
+    let _lock = DirLock::acquire(&state_dir)?;
+    let mut journal = Journal::open_remote(&state_dir)?;
+    interrupt::install()?;
+
+    let started = Instant::now();
+    let outcome = backend::apply(
+        backend.as_mut(),
+        config.dir,
+        &plan.items,
+        &mut journal,
+        &config.apply,
+    )?;
+    let summary = Summary::new(&plan, &outcome, started.elapsed());
+
+    print_outcome(&outcome, plan.items.len(), &palette);
+    print_summary(&summary);
+
+    Ok(())
+}
+
+/// Whether a rename failed for lack of permissions, `EACCES` or `EPERM`,
+/// rather than anything retrying cannot help with.
+fn is_permission_denied(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::PermissionDenied
+}
+
+/// The files of `outcome` that could not be renamed for lack of
+/// permissions.
+fn denied_items(outcome: &RenameOutcome) -> Vec<RenamePlanItem> {
+    outcome
+        .failed
+        .iter()
+        .filter(|err| is_permission_denied(&err.reason))
+        .map(|err| err.item.clone())
+        .collect()
+}
+
+/// Imports the files in a folder of an MTP device into `--dest`. They are
+/// downloaded next to it first, then named and moved like local files, and
+/// deleted from the device if asked once moved.
+fn import(config: Config, url: Url) -> Result<(), Box<dyn Error>> {
+    let dest = match config.plan.dest {
+        Some(dest) if config.more_dirs.is_empty() => dest,
+        _ => return Err("Importing from a device needs --dest and no other directories".into()),
+    };
+
+    if config.apply.mode == TransferMode::Copy {
+        return Err("--copy does not work when importing from a device".into());
+    }
+
+    let sources = import_sources(&config.plan.sources)?;
+    let files = mtp::files(&url.path).map_err(|error| format!("Cannot list {}: {}", url, error))?;
+    let downloads = dest.join(format!("{}-import", INTERNAL_PREFIX));
+    fs::create_dir_all(&downloads)?;
+
+    for file in &files {
+        let path = downloads.join(&file.name);
+
+        // Left over from an import that was cancelled or failed.
+        if fs::metadata(&path).is_ok_and(|metadata| metadata.len() == file.len) {
+            continue;
+        }
+
+        println!("Downloading {}", file.name);
+        mtp::download(file, &path)
+            .map_err(|error| format!("Cannot download {}: {}", file.name, error))?;
+    }
+
+    let config = Config {
+        dir: dest,
+        plan: PlanOptions {
+            sources,
+            ..config.plan
+        },
+        ..config
+    };
+    check_protected(&config)?;
+
+    let delete = config.remote.delete_imported;
+    let started = Instant::now();
+    let plan = plan_all(&[downloads.as_path()], &config.plan)?;
+    let planning = started.elapsed();
+
+    execute(config, plan, planning)?;
+
+    if delete {
+        for file in &files {
+            if !downloads.join(&file.name).exists() {
+                mtp::delete(file).map_err(|error| {
+                    format!("Cannot delete {} from {}: {}", file.name, url, error)
+                })?;
+            }
+        }
+    }
+
+    // Only goes when everything was imported.
+    let _ = fs::remove_dir(&downloads);
+
+    Ok(())
+}
+
+/// The sources naming files imported from a device, for the `chosen` ones.
+///
+/// Downloads have the time they were downloaded as their filesystem times,
+/// so only their contents and names can tell when they were made: EXIF
+/// and names by default, and sources reading the filesystem are refused.
+fn import_sources(chosen: &SourceChain) -> Result<SourceChain, String> {
+    if *chosen == SourceChain::default() {
+        Ok(SourceChain(vec![
+            Strategy::Single(Source::Exif),
+            Strategy::Single(Source::Filename),
+        ]))
+    } else if chosen.reads_filesystem() {
+        Err(
+            "Files downloaded from a device all have the time of the download, \
+             use --source with exif, filename or sidecar"
+                .to_owned(),
+        )
+    } else {
+        Ok(chosen.clone())
+    }
+}
+
+fn apply_mapping(config: Config) -> Result<(), Box<dyn Error>> {
+    check_protected(&config)?;
+
+    let started = Instant::now();
+    let file = config.mapping.expect("apply has a mapping");
+    let plan = mapping::plan(mapping::read_csv(file, config.dir)?);
+    let planning = started.elapsed();
+
+    execute(config, plan, planning)
+}
+
+/// Records what came of applying `items` as run `run` in the history
+/// database, and deletes AppleDouble files and updates checksums after
+/// it, as asked.
+fn after_apply(
+    config: &Config,
+    run: Option<u64>,
+    items: &[RenamePlanItem],
+    outcome: &RenameOutcome,
+) -> Result<(), Box<dyn Error>> {
+    if let (Some(db), Some(run)) = (config.history_db, run) {
+        history_db::record(db, config.dir, run, items, outcome)?;
+    }
+
+    if config.plan.appledouble == AppleDoublePolicy::Delete
+        && config.apply.mode == TransferMode::Move
+    {
+        appledouble::remove(&outcome.renamed)?;
+    }
+
+    if config.checksums {
+        let dir = config.plan.dest.unwrap_or(config.dir);
+        let copied = config.apply.mode == TransferMode::Copy;
+        manifest::update(dir, &outcome.renamed, copied)?;
+    }
+
+    Ok(())
+}
+
+/// Previews `plan`, asks for confirmation and applies it.
+///
+/// AppleDouble companions of the planned files are renamed along with them
+/// or deleted afterwards, as set by `config.plan.appledouble`.
+fn execute(config: Config, mut plan: Plan, planning: Duration) -> Result<(), Box<dyn Error>> {
+    let palette = palette(&config);
+    let ndjson = config.output == OutputFormat::Ndjson;
+
+    if config.pick {
+        let candidates: Vec<_> = plan
+            .items
+            .iter()
+            .map(|item| {
+                let path = item.path.strip_prefix(config.dir).unwrap_or(&item.path);
+                path.display().to_string()
+            })
+            .collect();
+        let chosen = pick::pick(&candidates)?;
+
+        plan.items = std::mem::take(&mut plan.items)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| chosen.contains(i))
+            .map(|(_, item)| item)
+            .collect();
+    }
+
+    if config.plan.appledouble == AppleDoublePolicy::Follow {
+        appledouble::attach(&mut plan);
+    }
+
+    if ndjson {
+        for item in &plan.items {
+            println!("{}", Event::Planned(item).to_json());
+        }
+
+        for skipped in &plan.skipped {
+            println!("{}", Event::NotPlanned(skipped).to_json());
+        }
+    } else {
+        let mut preview = String::new();
+        write_plan(&mut preview, &config, &plan, &palette)?;
+        show(&config, &preview)?;
+    }
+
+    space::check(&plan.items, config.apply.mode)?;
+
+    let confirmed = match config.confirm_over {
+        Some(limit) if plan.items.len() <= limit => true,
+        _ => prompt::confirm(),
+    };
+
+    let mut timings = plan.timings.clone();
+
+    if confirmed {
+        let _locks = config
+            .roots()
+            .into_iter()
+            .map(DirLock::acquire)
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut journal = Journal::open(config.dir)?;
+        SavedPlan::save(config.dir, &plan.items, journal.size()?, config.apply.mode)?;
+        interrupt::install()?;
+
+        let mut on_event = |event: Event| {
+            if ndjson {
+                println!("{}", event.to_json());
+            }
+        };
+
+        let started = Instant::now();
+        let outcome = apply(
+            &plan.items,
+            Some(&mut journal),
+            &config.apply,
+            &mut on_event,
+        )?;
+        timings.applying = started.elapsed();
+        after_apply(&config, journal.current_run(), &plan.items, &outcome)?;
+
+        let summary = Summary::new(&plan, &outcome, planning + timings.applying);
+
+        if !outcome.interrupted {
+            SavedPlan::remove(config.dir)?;
+        }
+
+        if ndjson {
+            if outcome.interrupted {
+                println!("{}", Event::Interrupted.to_json());
+            }
+
+            if outcome.stopped {
+                println!("{}", Event::Stopped.to_json());
+            }
+
+            println!("{}", Event::Summary(&summary).to_json());
+        } else {
+            print_outcome(&outcome, plan.items.len(), &palette);
+            print_summary(&summary);
+
+            // Offered once the user had a chance to fix the permissions,
+            // for as long as some are still denied.
+            let mut denied = denied_items(&outcome);
+
+            while !denied.is_empty()
+                && !outcome.interrupted
+                && prompt::is_interactive()
+                && prompt::ask(tr("Retry them? [y/N] "))
+            {
+                let retried = apply(&denied, Some(&mut journal), &config.apply, &mut on_event)?;
+                after_apply(&config, journal.current_run(), &denied, &retried)?;
+
+                print_outcome(&retried, denied.len(), &palette);
+                denied = denied_items(&retried);
+            }
+        }
+    } else if ndjson {
+        println!("{}", Event::Cancelled.to_json());
+    } else {
+        println!("{}", tr("Renaming cancelled."));
+    }
+
+    if config.profile {
+        if ndjson {
+            println!("{}", Event::Profile(&timings).to_json());
+        } else {
+            print_profile(&timings);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_plan(
+    out: &mut dyn fmt::Write,
+    config: &Config,
+    plan: &Plan,
+    palette: &Palette,
+) -> fmt::Result {
+    for dir in config.roots() {
+        writeln!(out, "{}", fill("Directory: {}", &[&dir.display()]))?;
+    }
+
+    if let Some(dest) = config.plan.dest {
+        writeln!(out, "{}", fill("Destination: {}", &[&dest.display()]))?;
+    }
+
+    let bytes: u64 = plan.items.iter().map(|item| item.stamp.len).sum();
+    writeln!(
+        out,
+        "{}",
+        fill("Files: {} ({})", &[&plan.items.len(), &format_bytes(bytes)])
+    )?;
+
+    if config.preview_listing {
+        write_listing(out, config, plan, palette)?;
+    } else {
+        write_renames(out, config, plan, palette)?;
+    }
+
+    write_warnings(out, plan, palette)
+}
+
+/// The old and new name of every planned file, by target directory when
+/// they go to several.
+fn write_renames(
+    out: &mut dyn fmt::Write,
+    config: &Config,
+    plan: &Plan,
+    palette: &Palette,
+) -> fmt::Result {
+    let verb = match config.apply.mode {
+        TransferMode::Move => tr("Rename"),
+        TransferMode::Copy => tr("Copy"),
+    };
+    let target_dir = config.plan.dest.unwrap_or(config.dir);
+    let rows: Vec<_> = plan
+        .items
+        .iter()
+        .map(|item| {
+            let from = format!(
+                "{}{}",
+                subdir(&item.path, config.dir, !config.plain),
+                item.name
+            );
+            (
+                item,
+                from,
+                subdir(&item.new_path, target_dir, !config.plain),
+            )
+        })
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(_, from, _)| from.chars().count())
+        .max()
+        .filter(|_| !config.plain)
+        .unwrap_or(0)
+        .min(MAX_ALIGN_WIDTH);
+
+    let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+
+    for (i, (_, _, to_dir)) in rows.iter().enumerate() {
+        groups.entry(to_dir).or_default().push(i);
+    }
+
+    let scattered = config.plan.dest.is_some() || config.plan.organize.is_some();
+
+    if scattered && groups.len() > 1 {
+        // Show how the files spread over the destination, one directory
+        // after the other.
+        for (to_dir, rows_in) in &groups {
+            let to_dir = match *to_dir {
+                "" => format!("{}{}", target_dir.display(), std::path::MAIN_SEPARATOR),
+                to_dir => to_dir.to_owned(),
+            };
+            let files = tr(if rows_in.len() == 1 { "file" } else { "files" });
+
+            writeln!(out, "{} ({} {}):", to_dir, rows_in.len(), files)?;
+
+            for &i in rows_in {
+                let (item, from, _) = &rows[i];
+                writeln!(
+                    out,
+                    "  {}: {:<width$} {} {}",
+                    verb,
+                    from,
+                    palette.arrow(),
+                    palette.new_name(&item.name, &item.new_name),
+                    width = width
+                )?;
+            }
+        }
+    } else {
+        for (item, from, to_dir) in &rows {
+            writeln!(
+                out,
+                "{}: {:<width$} {} {}{}",
+                verb,
+                from,
+                palette.arrow(),
+                to_dir,
+                palette.new_name(&item.name, &item.new_name),
+                width = width
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Every directory files go to, with what will be in it afterwards in the
+/// order of names. The new names are highlighted.
+fn write_listing(
+    out: &mut dyn fmt::Write,
+    config: &Config,
+    plan: &Plan,
+    palette: &Palette,
+) -> fmt::Result {
+    let sep = std::path::MAIN_SEPARATOR;
+    let mut dirs: BTreeMap<&Path, BTreeMap<String, String>> = BTreeMap::new();
+
+    for item in &plan.items {
+        let dir = match item.new_path.parent() {
+            Some(dir) => dir,
+            None => continue,
+        };
+
+        dirs.entry(dir).or_insert_with(|| {
+            let entries = fs::read_dir(dir).into_iter().flatten().flatten();
+
+            entries
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    let shown = match entry.file_type() {
+                        Ok(kind) if kind.is_dir() => format!("{}{}", name, sep),
+                        _ => name.clone(),
+                    };
+                    (name, shown)
+                })
+                .filter(|(name, _)| !name.starts_with(INTERNAL_PREFIX))
+                .collect()
+        });
+    }
+
+    if config.apply.mode == TransferMode::Move {
+        for item in &plan.items {
+            let dir = item.path.parent().unwrap_or(Path::new(""));
+
+            if let Some(names) = dirs.get_mut(dir) {
+                names.remove(&item.name);
+            }
+        }
+    }
+
+    for item in &plan.items {
+        if let Some(names) = item.new_path.parent().and_then(|dir| dirs.get_mut(dir)) {
+            names.insert(
+                item.new_name.clone(),
+                palette.new_name(&item.name, &item.new_name),
+            );
+        }
+    }
+
+    for (dir, names) in &dirs {
+        writeln!(out, "{}{}", dir.display(), sep)?;
+
+        for shown in names.values() {
+            writeln!(out, "  {}", shown)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The files left out and what to look out for.
+fn write_warnings(out: &mut dyn fmt::Write, plan: &Plan, palette: &Palette) -> fmt::Result {
+    for skipped in &plan.skipped {
+        let reason = match skipped.reason {
+            FsItemError::HardLink(ref kept) => format!("hard link to {}", kept.display()),
+            ref reason => reason.to_string(),
+        };
+
+        writeln!(
+            out,
+            "{}: {}: {} [{}]",
+            tr("Skip"),
+            skipped.path.display(),
+            palette.paint(Style::Warning, &reason),
+            skipped.reason.code()
+        )?;
+    }
+
+    for group in hardlink::groups(&plan.items) {
+        let paths: Vec<_> = group
+            .iter()
+            .map(|&i| plan.items[i].path.display().to_string())
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            palette.paint(
+                Style::Warning,
+                &fill("Warning: same file renamed as {}", &[&paths.join(", ")])
+            )
+        )?;
+    }
+
+    Ok(())
+}
+
+fn palette(config: &Config) -> Palette {
+    if config.plain {
+        Palette::plain()
+    } else {
+        Palette::new(config.color)
+    }
+}
+
+/// Shows the preview, paged unless the output is `--plain`.
+fn show(config: &Config, preview: &str) -> io::Result<()> {
+    if config.plain {
+        print!("{}", preview);
+        Ok(())
+    } else {
+        pager::show(preview)
+    }
+}
+
+/// Widest old name the preview pads the others to.
+const MAX_ALIGN_WIDTH: usize = 48;
+
+/// Longest relative directory shown in full in the preview.
+const MAX_SUBDIR_WIDTH: usize = 32;
+
+/// Directory of `path` relative to `base`, with a trailing separator, or
+/// nothing when `path` sits right in `base`, or the whole directory when
+/// it lies outside `base`. Long directories are
+/// shortened to their first and last components if `shorten`.
+fn subdir(path: &Path, base: &Path, shorten: bool) -> String {
+    let sep = std::path::MAIN_SEPARATOR;
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return String::new(),
+    };
+    let rel = match parent.strip_prefix(base) {
+        Ok(rel) if rel != Path::new("") => rel,
+        Ok(_) => return String::new(),
+        Err(_) => return format!("{}{}", parent.display(), sep),
+    };
+
+    let full = format!("{}{}", rel.display(), sep);
+    let parts: Vec<_> = rel.iter().map(|part| part.to_string_lossy()).collect();
+
+    if !shorten || full.chars().count() <= MAX_SUBDIR_WIDTH || parts.len() <= 2 {
+        return full;
+    }
+
+    format!(
+        "{}{}…{}{}{}",
+        parts[0],
+        sep,
+        sep,
+        parts[parts.len() - 1],
+        sep
+    )
+}
+
+fn print_outcome(outcome: &RenameOutcome, planned: usize, palette: &Palette) {
+    let processed = outcome.renamed.len()
+        + outcome.rolled_back.len()
+        + outcome.skipped.len()
+        + outcome.failed.len();
+
+    println!("\n{}", tr("Renamed items:"));
+
+    for item in &outcome.renamed {
+        println!(
+            "- {} {} {}",
+            item.name,
+            palette.arrow(),
+            palette.new_name(&item.name, &item.new_name)
+        );
+    }
+
+    if !outcome.skipped.is_empty() {
+        println!("\n{}", tr("Skipped:"));
+
+        for skip in &outcome.skipped {
+            println!(
+                "- {}: {} [{}]",
+                skip.item.name,
+                palette.paint(Style::Warning, &skip.reason.to_string()),
+                skip.reason.code()
+            );
+        }
+    }
+
+    if !outcome.backed_up.is_empty() {
+        println!("\n{}", tr("Replaced files backed up:"));
+
+        for (path, backup) in &outcome.backed_up {
+            println!(
+                "- {} {} {}",
+                path.display(),
+                palette.arrow(),
+                backup.display()
+            );
+        }
+    }
+
+    let (denied, failed): (Vec<_>, Vec<_>) = outcome
+        .failed
+        .iter()
+        .partition(|err| is_permission_denied(&err.reason));
+
+    println!("\n{}", tr("Failed:"));
+
+    for err in failed {
+        println!(
+            "- {} {} {}: {} [{}]",
+            err.item.name,
+            palette.arrow(),
+            err.item.new_name,
+            palette.paint(Style::Error, &err.reason.to_string()),
+            err.code()
+        );
+    }
+
+    // Gathered apart, as the fix is the same for all of them.
+    if !denied.is_empty() {
+        println!("\n{}", tr("Permission denied:"));
+
+        for err in &denied {
+            println!(
+                "- {} {} {}: {}",
+                err.item.path.display(),
+                palette.arrow(),
+                err.item.new_path.display(),
+                palette.paint(Style::Error, &err.reason.to_string())
+            );
+        }
+
+        println!(
+            "\n{}",
+            tr("Fix the permissions of these files or their directories, then retry them.")
+        );
+    }
+
+    if outcome.interrupted {
+        println!(
+            "\n{}",
+            fill("Interrupted after {} of {} files.", &[&processed, &planned])
+        );
+    }
+
+    if !outcome.rolled_back.is_empty() {
+        println!(
+            "\n{}",
+            fill(
+                "Rolled back {} renames after the failure.",
+                &[&outcome.rolled_back.len()]
+            )
+        );
+    }
+
+    if outcome.stopped {
+        println!(
+            "\n{}",
+            fill(
+                "Stopped at the first failure after {} of {} files.",
+                &[&processed, &planned]
+            )
+        );
+    }
+}
+
+fn print_profile(timings: &Timings) {
+    fn ms(time: Duration) -> String {
+        format!("{:>10.3} ms", time.as_secs_f64() * 1000.0)
+    }
+
+    println!("\nProfile:");
+    println!("  {:<10} {}", "scanning", ms(timings.scanning));
+
+    for (source, timing) in &timings.sources {
+        println!(
+            "  {:<10} {} ({} files)",
+            source,
+            ms(timing.time),
+            timing.files
+        );
+    }
+
+    println!("  {:<10} {}", "planning", ms(timings.planning));
+    println!("  {:<10} {}", "applying", ms(timings.applying));
+}
+
+fn print_summary(summary: &Summary) {
+    println!(
+        "\n{}",
+        fill(
+            "{} scanned, {} renamed, {} skipped, {} failed",
+            &[
+                &summary.scanned,
+                &summary.renamed,
+                &summary.skipped_total(),
+                &summary.failed
+            ]
+        )
+    );
+
+    for (reason, count) in &summary.skipped {
+        println!("{}", fill("  {} skipped: {}", &[count, reason]));
+    }
+
+    if summary.unchanged > 0 {
+        println!("{}", fill("{} unchanged", &[&summary.unchanged]));
+    }
+
+    if summary.bytes_copied > 0 {
+        println!(
+            "{}",
+            fill("{} copied", &[&format_bytes(summary.bytes_copied)])
+        );
+    }
+
+    let elapsed = format!("{:.2}", summary.elapsed.as_secs_f64());
+    println!("{}", fill("Done in {} s", &[&elapsed]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_imports_by_what_the_files_tell() {
+        let downloads = std::env::temp_dir().join(format!("crtime-import-{}", std::process::id()));
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(downloads.join("IMG_20240305_121000.jpg"), "").unwrap();
+        fs::write(downloads.join("notes.txt"), "").unwrap();
+
+        let options = PlanOptions {
+            sources: import_sources(&SourceChain::default()).unwrap(),
+            ..PlanOptions::default()
+        };
+        let plan = plan_all(&[downloads.as_path()], &options).unwrap();
+        let names: Vec<_> = plan
+            .items
+            .iter()
+            .map(|item| item.new_name.as_str())
+            .collect();
+
+        assert_eq!(names, ["20240305121000 IMG_20240305_121000.jpg"]);
+        assert_eq!(plan.skipped.len(), 1);
+        assert!(import_sources(&"exif,mtime".parse().unwrap()).is_err());
+
+        fs::remove_dir_all(&downloads).unwrap();
+    }
+}
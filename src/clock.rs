@@ -0,0 +1,111 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::exif::{self, Exif, Ifd};
+use crate::fs_kind::FsKind;
+use crate::source::{self, Source, SourceChain};
+
+/// A file whose true creation time is known, written as
+/// `<file>=<actual-time>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncRef {
+    pub path: PathBuf,
+    pub actual: DateTime<Utc>,
+}
+
+impl FromStr for SyncRef {
+    type Err = ();
+
+    /// Parses `photo.jpg=2024-03-05 12:10:00`; the time may also be given
+    /// as RFC 3339 and is otherwise read as local time.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, time) = s.rsplit_once('=').ok_or(())?;
+
+        if path.is_empty() {
+            return Err(());
+        }
+
+        let actual = source::parse_iso_time(&time.trim().replacen(' ', "T", 1)).ok_or(())?;
+
+        Ok(SyncRef {
+            path: PathBuf::from(path),
+            actual,
+        })
+    }
+}
+
+/// The camera that took a photo, as its EXIF make and model.
+fn camera(path: &Path) -> Option<String> {
+    let exif = Exif::read(path).ok()??;
+    let make = exif
+        .ascii(Ifd::Primary, exif::TAG_MAKE)
+        .unwrap_or("")
+        .trim();
+    let model = exif
+        .ascii(Ifd::Primary, exif::TAG_MODEL)
+        .unwrap_or("")
+        .trim();
+
+    match (make, model) {
+        ("", "") => None,
+        (make, "") => Some(make.to_owned()),
+        ("", model) => Some(model.to_owned()),
+        (make, model) => Some(format!("{} {}", make, model)),
+    }
+}
+
+/// Clock errors measured from reference files, each applying to files
+/// whose time comes from the same source and the same camera.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    deltas: HashMap<(Source, Option<String>), Duration>,
+}
+
+impl ClockSync {
+    /// Measures how far off each reference file's time, as read through
+    /// `sources`, is from its actual time.
+    pub fn measure(refs: &[SyncRef], sources: &SourceChain) -> io::Result<ClockSync> {
+        let mut sync = ClockSync::default();
+
+        for sync_ref in refs {
+            let meta = fs::metadata(&sync_ref.path)?;
+            let fs_kind = FsKind::detect(&sync_ref.path);
+            let (source, recorded) =
+                sources
+                    .resolve(&sync_ref.path, &meta, fs_kind)
+                    .map_err(|error| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Cannot read time of {}: {}", sync_ref.path.display(), error),
+                        )
+                    })?;
+
+            let key = (source, camera(&sync_ref.path));
+            sync.deltas.insert(key, sync_ref.actual - recorded);
+        }
+
+        Ok(sync)
+    }
+
+    /// `time` of the file at `path`, read from `source`, corrected by the
+    /// matching reference, if any.
+    pub fn correct(
+        &self,
+        path: &Path,
+        source: Source,
+        time: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if !self.deltas.keys().any(|(from, _)| *from == source) {
+            return Some(time);
+        }
+
+        match self.deltas.get(&(source, camera(path))) {
+            Some(&delta) => time.checked_add_signed(delta),
+            None => Some(time),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::env;
+use std::io;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+/// The `--color` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Style {
+    Timestamp,
+    Arrow,
+    Warning,
+    Error,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Timestamp => "36",
+            Style::Arrow => "2",
+            Style::Warning => "33",
+            Style::Error => "31",
+        }
+    }
+}
+
+/// Applies styles to terminal output, or leaves text plain when colors
+/// are off.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    enabled: bool,
+    plain: bool,
+}
+
+impl Palette {
+    /// Decides whether to color stdout. `auto` colors terminals unless
+    /// `NO_COLOR` is set or `TERM` is `dumb`.
+    pub fn new(choice: ColorChoice) -> Palette {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && env::var("TERM").ok().as_deref() != Some("dumb")
+                    && io::stdout().is_terminal()
+            }
+        };
+
+        Palette {
+            enabled,
+            plain: false,
+        }
+    }
+
+    /// No colors and nothing but ASCII, for `--plain`.
+    pub fn plain() -> Palette {
+        Palette {
+            enabled: false,
+            plain: true,
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.plain
+    }
+
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Renders `new_name` with the parts that differ from the original
+    /// `name` highlighted, such as the added timestamp and any number
+    /// added to avoid a clash.
+    pub fn new_name(&self, name: &str, new_name: &str) -> String {
+        let chars: Vec<char> = new_name.chars().collect();
+        let added = added_chars(name, &chars);
+        let mut out = String::new();
+        let mut start = 0;
+
+        while start < chars.len() {
+            let end = (start..chars.len())
+                .find(|&i| added[i] != added[start])
+                .unwrap_or(chars.len());
+            let run: String = chars[start..end].iter().collect();
+
+            if added[start] {
+                out.push_str(&self.paint(Style::Timestamp, &run));
+            } else {
+                out.push_str(&run);
+            }
+
+            start = end;
+        }
+
+        out
+    }
+
+    pub fn arrow(&self) -> String {
+        self.paint(Style::Arrow, "->")
+    }
+}
+
+/// For every character of `new`, whether it is not part of `old`, by
+/// the longest common subsequence of the two and preferring later matches.
+fn added_chars(old: &str, new: &[char]) -> Vec<bool> {
+    let old: Vec<char> = old.chars().collect();
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0u16; m + 1]; n + 1];
+
+    for i in 0..n {
+        for j in 0..m {
+            lcs[i + 1][j + 1] = if old[i] == new[j] {
+                lcs[i][j] + 1
+            } else {
+                lcs[i][j + 1].max(lcs[i + 1][j])
+            };
+        }
+    }
+
+    let mut added = vec![true; m];
+    let (mut i, mut j) = (n, m);
+
+    while i > 0 && j > 0 {
+        if old[i - 1] == new[j - 1] {
+            added[j - 1] = false;
+            i -= 1;
+            j -= 1;
+        } else if lcs[i][j - 1] >= lcs[i - 1][j] {
+            j -= 1;
+        } else {
+            i -= 1;
+        }
+    }
+
+    added
+}
@@ -0,0 +1,988 @@
+use chrono::NaiveDate;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::{Chars, FromStr};
+
+use crate::appledouble::AppleDoublePolicy;
+use crate::cli::COMMANDS;
+use crate::color::ColorChoice;
+use crate::content::ContentType;
+use crate::dst::DstPolicy;
+use crate::event::OutputFormat;
+use crate::hardlink::HardlinkPolicy;
+use crate::history::ExportFormat;
+use crate::names::NamePolicy;
+use crate::offset::Offset;
+use crate::organize::Organize;
+use crate::source::SourceChain;
+use crate::summary::parse_bytes;
+use crate::template::{Preset, Template};
+
+/// Name of the config file in the user's config directory.
+pub const CONFIG_NAME: &str = "config.toml";
+
+/// Name of the config file of a directory tree, found in the directory
+/// worked in or one above it.
+pub const DIR_CONFIG_NAME: &str = ".crtime.toml";
+
+/// Options outside the schema that take a value, skipped over when
+/// looking for the directory in the arguments.
+const OTHER_VALUE_FLAGS: &[&str] = &["--config", "--from-csv", "--sync-ref", "--by", "--run"];
+
+/// Short options, with the key of the option each stands for.
+const SHORT_FLAGS: &[(&str, &str)] = &[("-r", "recursive"), ("-j", "jobs"), ("-y", "yes")];
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    /// A flag without a value, set by `true`.
+    Switch,
+    /// A string, accepted if it passes the check.
+    Text(fn(&str) -> bool),
+    Number,
+    /// A number above 0, which may have a fraction.
+    Rate,
+    /// An array of strings, each given as its own flag.
+    List,
+}
+
+fn valid<T: FromStr>(value: &str) -> bool {
+    value.parse::<T>().is_ok()
+}
+
+fn any(_: &str) -> bool {
+    true
+}
+
+fn bytes(value: &str) -> bool {
+    parse_bytes(value).is_some()
+}
+
+/// Every key a config file may set.
+const SCHEMA: &[(&str, Kind)] = &[
+    ("dest", Kind::Text(any)),
+    ("source", Kind::Text(valid::<SourceChain>)),
+    ("template", Kind::Text(valid::<Template>)),
+    ("preset", Kind::Text(valid::<Preset>)),
+    ("day_zero", Kind::Text(valid::<NaiveDate>)),
+    ("counter_start", Kind::Number),
+    ("counter_width", Kind::Number),
+    ("organize", Kind::Text(valid::<Organize>)),
+    ("type", Kind::Text(valid::<ContentType>)),
+    ("images", Kind::Switch),
+    ("videos", Kind::Switch),
+    ("audio", Kind::Switch),
+    ("documents", Kind::Switch),
+    ("include_partial", Kind::Switch),
+    ("skip_empty", Kind::Switch),
+    ("min_size", Kind::Text(bytes)),
+    ("hardlinks", Kind::Text(valid::<HardlinkPolicy>)),
+    ("invalid_names", Kind::Text(valid::<NamePolicy>)),
+    ("appledouble", Kind::Text(valid::<AppleDoublePolicy>)),
+    ("ask_conflicts", Kind::Switch),
+    ("offset", Kind::Text(valid::<Offset>)),
+    ("dst", Kind::Text(valid::<DstPolicy>)),
+    ("older_than", Kind::Text(valid::<Offset>)),
+    ("newer_than", Kind::Text(valid::<Offset>)),
+    ("gps_timezone", Kind::Switch),
+    ("recursive", Kind::Switch),
+    ("flatten", Kind::Switch),
+    ("copy", Kind::Switch),
+    ("verify", Kind::Switch),
+    ("checksums", Kind::Switch),
+    ("strip_zone_id", Kind::Switch),
+    ("fail_fast", Kind::Switch),
+    ("atomic", Kind::Switch),
+    ("jobs", Kind::Number),
+    ("cache", Kind::Switch),
+    ("history_db", Kind::Text(any)),
+    ("force", Kind::Switch),
+    ("fix_perms", Kind::Switch),
+    ("backup_dir", Kind::Text(any)),
+    ("read_jobs", Kind::Number),
+    ("read_timeout", Kind::Number),
+    ("throttle", Kind::Rate),
+    ("s3_meta_time", Kind::Text(any)),
+    ("delete_imported", Kind::Switch),
+    ("write_exif", Kind::Switch),
+    ("retries", Kind::Number),
+    ("retry_delay", Kind::Number),
+    ("protect", Kind::List),
+    ("confirm_over", Kind::Number),
+    ("yes", Kind::Switch),
+    ("color", Kind::Text(valid::<ColorChoice>)),
+    ("plain", Kind::Switch),
+    ("preview_listing", Kind::Switch),
+    ("output", Kind::Text(valid::<OutputFormat>)),
+    ("format", Kind::Text(valid::<ExportFormat>)),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// Default options read from a TOML config file, checked against the
+/// schema.
+///
+/// Each key sets the command-line option of the same name, with `_` for
+/// `-`: `template = "{time} {stem}{ext}"` is `--template`, and
+/// `recursive = true` is `--recursive`. Only plain `key = value` lines are
+/// understood, with strings, whole numbers, booleans and arrays of strings
+/// as values.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    entries: Vec<(&'static str, Value)>,
+}
+
+impl ConfigFile {
+    /// Reads and checks the config file at `path`.
+    ///
+    /// Syntax errors, unknown keys, values of the wrong type and values the
+    /// option would refuse fail with the file and line they are on.
+    pub fn load(path: &Path) -> io::Result<ConfigFile> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |line: usize, message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: line {}: {}", path.display(), line, message),
+            )
+        };
+
+        let mut entries = Vec::new();
+
+        for (line, key, value) in parse(&text).map_err(|(line, message)| invalid(line, message))? {
+            let (name, kind) = match SCHEMA.iter().find(|(name, _)| *name == key) {
+                Some(&entry) => entry,
+                None => {
+                    let message = match suggest(&key) {
+                        Some(name) => format!("unknown key `{}`, did you mean `{}`?", key, name),
+                        None => format!("unknown key `{}`", key),
+                    };
+                    return Err(invalid(line, message));
+                }
+            };
+
+            if entries.iter().any(|(other, _)| *other == name) {
+                return Err(invalid(line, format!("`{}` is set twice", key)));
+            }
+
+            check(name, kind, &value).map_err(|message| invalid(line, message))?;
+
+            entries.push((name, value));
+        }
+
+        Ok(ConfigFile {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// The command-line arguments the file stands for.
+    pub fn args(&self) -> Vec<String> {
+        entry_args(&self.entries)
+    }
+}
+
+/// Fails with a message if `value` does not suit the option `name`.
+fn check(name: &str, kind: Kind, value: &Value) -> Result<(), String> {
+    let expected = match (kind, value) {
+        (Kind::Switch, Value::Bool(_))
+        | (Kind::Number, Value::Int(0..))
+        | (Kind::Rate, Value::Int(1..)) => return Ok(()),
+        (Kind::Rate, Value::Float(x)) if *x > 0.0 && x.is_finite() => return Ok(()),
+        (Kind::Text(check), Value::Str(s)) if !check(s) => {
+            return Err(format!("invalid value for `{}`", name))
+        }
+        (Kind::Text(_), Value::Str(_)) => return Ok(()),
+        (Kind::List, Value::Array(values)) if values.iter().all(|v| matches!(v, Value::Str(_))) => {
+            return Ok(())
+        }
+        (Kind::Switch, _) => "true or false",
+        (Kind::Text(_), _) => "a string",
+        (Kind::Number, _) => "a whole number of at least 0",
+        (Kind::Rate, _) => "a number above 0",
+        (Kind::List, _) => "an array of strings",
+    };
+
+    Err(format!("`{}` must be {}", name, expected))
+}
+
+fn entry_args(entries: &[(&'static str, Value)]) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for (name, value) in entries {
+        let flag = format!("--{}", name.replace('_', "-"));
+
+        match value {
+            Value::Bool(true) => args.push(flag),
+            Value::Bool(false) => {}
+            Value::Str(s) => args.extend(vec![flag, s.clone()]),
+            Value::Int(n) => args.extend(vec![flag, n.to_string()]),
+            Value::Float(x) => args.extend(vec![flag, x.to_string()]),
+            Value::Array(values) => {
+                for value in values {
+                    if let Value::Str(s) = value {
+                        args.extend(vec![flag.clone(), s.clone()]);
+                    }
+                }
+            }
+        }
+    }
+
+    args
+}
+
+/// Where the user's config file is, in `$XDG_CONFIG_HOME/crtime` or
+/// `~/.config/crtime` (`%APPDATA%\crtime` on Windows).
+pub fn user_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        }
+    };
+
+    Some(dir.join("crtime").join(CONFIG_NAME))
+}
+
+/// Where an option was set, from the lowest precedence to the highest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    UserFile(PathBuf),
+    /// The `.crtime.toml` of the directory worked in.
+    DirFile(PathBuf),
+    /// The file given with `--config`.
+    GivenFile(PathBuf),
+    /// `CRTIME_*` environment variables.
+    Environment,
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::UserFile(path) | Origin::DirFile(path) | Origin::GivenFile(path) => {
+                write!(f, "{}", path.display())
+            }
+            Origin::Environment => write!(f, "environment"),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The options set in every place crtime reads them from.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// Options by where they were set, lowest precedence first.
+    layers: Vec<(Origin, Vec<(&'static str, Value)>)>,
+}
+
+impl Settings {
+    /// Gathers the options of the user's config file, the `.crtime.toml`
+    /// of the directory in `args`, the file given with `--config`, the
+    /// environment and the command line `args` itself.
+    pub fn gather(args: &[String]) -> io::Result<Settings> {
+        let mut settings = Settings::default();
+
+        if let Some(path) = user_path().filter(|path| path.is_file()) {
+            let file = ConfigFile::load(&path)?;
+            settings.layers.push((Origin::UserFile(path), file.entries));
+        }
+
+        if let Some(path) = dir_arg(args).and_then(|dir| dir_path(Path::new(dir))) {
+            let file = ConfigFile::load(&path)?;
+            settings.layers.push((Origin::DirFile(path), file.entries));
+        }
+
+        if let Some(path) = given_path(args)? {
+            let file = ConfigFile::load(&path)?;
+            settings
+                .layers
+                .push((Origin::GivenFile(path), file.entries));
+        }
+
+        settings.layers.push((Origin::Environment, env_entries()?));
+        settings
+            .layers
+            .push((Origin::CommandLine, command_line_entries(args)));
+
+        Ok(settings)
+    }
+
+    /// `args` with the options of the config files and the environment put
+    /// before the ones given, so that those take precedence, and `--config`
+    /// taken out.
+    ///
+    /// The files and the environment are merged first, so that `false` in
+    /// one turns off a flag one before it turned on.
+    pub fn args(&self, args: Vec<String>) -> Vec<String> {
+        let mut args = args.into_iter();
+        let mut expanded: Vec<String> = args.next().into_iter().collect();
+
+        let merged: Vec<_> = self
+            .effective(|origin| *origin != Origin::CommandLine)
+            .into_iter()
+            .map(|(name, value, _)| (name, value))
+            .collect();
+        expanded.extend(entry_args(&merged));
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    args.next();
+                }
+                _ => expanded.push(arg),
+            }
+        }
+
+        expanded
+    }
+
+    /// The value of every option the layers `included` set, in the order
+    /// of the schema, with where it was set: the last layer setting it, or
+    /// for lists every layer adding to it.
+    fn effective(
+        &self,
+        included: impl Fn(&Origin) -> bool,
+    ) -> Vec<(&'static str, Value, Vec<&Origin>)> {
+        let mut effective = Vec::new();
+
+        for &(name, kind) in SCHEMA {
+            let set: Vec<_> = self
+                .layers
+                .iter()
+                .filter(|(origin, _)| included(origin))
+                .filter_map(|(origin, entries)| {
+                    let value = entries.iter().find(|(other, _)| *other == name)?;
+                    Some((origin, &value.1))
+                })
+                .collect();
+
+            match (kind, set.last()) {
+                (_, None) => {}
+                (Kind::List, Some(_)) => {
+                    let mut values = Vec::new();
+
+                    for (_, value) in &set {
+                        if let Value::Array(more) = value {
+                            values.extend(more.iter().cloned());
+                        }
+                    }
+
+                    let origins = set.iter().map(|&(origin, _)| origin).collect();
+                    effective.push((name, Value::Array(values), origins));
+                }
+                (_, Some(&(origin, value))) => effective.push((name, value.clone(), vec![origin])),
+            }
+        }
+
+        effective
+    }
+}
+
+/// Lists the effective value of every option set, in config file syntax,
+/// each followed by where it was set.
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value, origins) in self.effective(|_| true) {
+            let origins: Vec<_> = origins.iter().map(|origin| origin.to_string()).collect();
+            writeln!(f, "{} = {}  # {}", name, value, origins.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => {
+                write!(f, "\"")?;
+
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+
+                write!(f, "\"")
+            }
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Array(values) => {
+                write!(f, "[")?;
+
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// The nearest `.crtime.toml` in `dir` or the directories above it.
+///
+/// The search stops at the root of a git repository, the home directory
+/// or the root of the filesystem, whichever comes first.
+pub fn dir_path(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .and_then(|home| PathBuf::from(home).canonicalize().ok());
+    let start = if dir.is_file() { dir.parent()? } else { &dir };
+
+    for dir in start.ancestors() {
+        let path = dir.join(DIR_CONFIG_NAME);
+
+        if path.is_file() {
+            return Some(path);
+        }
+
+        if dir.join(".git").exists() || Some(dir) == home.as_deref() {
+            break;
+        }
+    }
+
+    None
+}
+
+/// The first directory (or file) named in the command line `args`.
+fn dir_arg(args: &[String]) -> Option<&str> {
+    let mut args = args.iter().skip(1);
+    let mut command = None;
+
+    while let Some(arg) = args.next() {
+        let takes_value = match schema_entry(arg) {
+            Some((_, kind)) => !matches!(kind, Kind::Switch),
+            None if arg.starts_with('-') => OTHER_VALUE_FLAGS.contains(&arg.as_str()),
+            None if command.is_none() && COMMANDS.contains(&arg.as_str()) => {
+                command = Some(arg.as_str());
+                false
+            }
+            None if arg == "export" && command == Some("history") => false,
+            None => return Some(arg),
+        };
+
+        if takes_value {
+            args.next();
+        }
+    }
+
+    None
+}
+
+/// The key and kind of the option of the schema the command-line flag
+/// `arg`, long or short, sets.
+fn schema_entry(arg: &str) -> Option<(&'static str, Kind)> {
+    let key = match SHORT_FLAGS.iter().find(|(short, _)| *short == arg) {
+        Some((_, key)) => (*key).to_owned(),
+        None => arg.strip_prefix("--")?.replace('-', "_"),
+    };
+
+    SCHEMA.iter().find(|(name, _)| *name == key).copied()
+}
+
+/// The file given with `--config` in `args`, which must exist.
+fn given_path(args: &[String]) -> io::Result<Option<PathBuf>> {
+    let mut given = None;
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            match args.next() {
+                Some(path) => given = Some(PathBuf::from(path)),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Missing value for --config",
+                    ))
+                }
+            }
+        }
+    }
+
+    match given {
+        Some(path) if !path.is_file() => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: no such file", path.display()),
+        )),
+        given => Ok(given),
+    }
+}
+
+/// Prefix of the environment variables setting options, followed by the
+/// key in capitals, like `CRTIME_TEMPLATE`.
+const ENV_PREFIX: &str = "CRTIME_";
+
+/// Environment variables with the prefix that other parts of crtime read,
+/// which set no option.
+const OTHER_VARS: &[&str] = &["CRTIME_PICKER"];
+
+/// The options set by `CRTIME_*` environment variables.
+///
+/// Flags take `1`, `true` or `yes` to be set and `0`, `false`, `no` or
+/// nothing not to be, and `CRTIME_PROTECT` takes a list of paths like
+/// `PATH` does.
+fn env_entries() -> io::Result<Vec<(&'static str, Value)>> {
+    var_entries(env::vars_os())
+}
+
+/// The options set by the `CRTIME_*` variables among `vars`.
+fn var_entries(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> io::Result<Vec<(&'static str, Value)>> {
+    let mut entries = Vec::new();
+
+    for (var, value) in vars {
+        let var = match var.to_str().filter(|var| var.starts_with(ENV_PREFIX)) {
+            Some(var) if !OTHER_VARS.contains(&var) => var.to_owned(),
+            _ => continue,
+        };
+        let invalid = |message: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", var, message))
+        };
+
+        let key = var[ENV_PREFIX.len()..].to_ascii_lowercase();
+        let (name, kind) = match SCHEMA.iter().find(|(name, _)| *name == key) {
+            Some(&entry) => entry,
+            None => {
+                let message = match suggest(&key) {
+                    Some(name) => format!(
+                        "unknown variable, did you mean {}{}?",
+                        ENV_PREFIX,
+                        name.to_ascii_uppercase()
+                    ),
+                    None => "unknown variable".to_owned(),
+                };
+                return Err(invalid(message));
+            }
+        };
+
+        let value = if let Kind::List = kind {
+            let paths = env::split_paths(&value)
+                .map(|path| path.into_os_string().into_string().map(Value::Str))
+                .collect::<Result<_, _>>();
+            match paths {
+                Ok(paths) => Value::Array(paths),
+                Err(_) => return Err(invalid("not valid UTF-8".to_owned())),
+            }
+        } else {
+            let value = match value.into_string() {
+                Ok(value) => value,
+                Err(_) => return Err(invalid("not valid UTF-8".to_owned())),
+            };
+
+            match (kind, value.to_ascii_lowercase().as_str()) {
+                (Kind::Switch, "1") | (Kind::Switch, "true") | (Kind::Switch, "yes") => {
+                    Value::Bool(true)
+                }
+                (Kind::Switch, "0")
+                | (Kind::Switch, "false")
+                | (Kind::Switch, "no")
+                | (Kind::Switch, "") => Value::Bool(false),
+                (Kind::Number, _) => match value.parse() {
+                    Ok(n) => Value::Int(n),
+                    Err(_) => Value::Str(value),
+                },
+                (Kind::Rate, _) => match value.parse() {
+                    Ok(x) => Value::Float(x),
+                    Err(_) => Value::Str(value),
+                },
+                _ => Value::Str(value),
+            }
+        };
+
+        check(name, kind, &value).map_err(invalid)?;
+        entries.push((name, value));
+    }
+
+    entries.sort_by_key(|&(name, _)| SCHEMA.iter().position(|(other, _)| *other == name));
+
+    Ok(entries)
+}
+
+/// The options of the schema set in the command line `args`. Values the
+/// option refuses are left to the argument parser to report.
+fn command_line_entries(args: &[String]) -> Vec<(&'static str, Value)> {
+    let mut entries: Vec<(&'static str, Value)> = Vec::new();
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        let (name, kind) = match schema_entry(arg) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let value = match kind {
+            Kind::Switch => Value::Bool(true),
+            Kind::Text(_) | Kind::List => match args.next() {
+                Some(value) => Value::Str(value.clone()),
+                None => continue,
+            },
+            Kind::Number => match args.next().and_then(|value| value.parse().ok()) {
+                Some(n) => Value::Int(n),
+                None => continue,
+            },
+            Kind::Rate => match args.next().and_then(|value| value.parse().ok()) {
+                Some(x) => Value::Float(x),
+                None => continue,
+            },
+        };
+
+        match (kind, entries.iter_mut().find(|(other, _)| *other == name)) {
+            (Kind::List, Some((_, Value::Array(values)))) => values.push(value),
+            (Kind::List, None) => entries.push((name, Value::Array(vec![value]))),
+            (_, Some(entry)) => entry.1 = value,
+            (_, None) => entries.push((name, value)),
+        }
+    }
+
+    entries
+}
+
+/// The known key closest to a misspelt `key`, if any is close.
+fn suggest(key: &str) -> Option<&'static str> {
+    SCHEMA
+        .iter()
+        .map(|(name, _)| (distance(key, name), *name))
+        .filter(|&(distance, _)| distance <= 2)
+        .min()
+        .map(|(_, name)| name)
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+type ParseError = (usize, String);
+
+/// Splits TOML `text` into its keys and values, each with its line.
+fn parse(text: &str) -> Result<Vec<(usize, String, Value)>, ParseError> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    let mut entries = Vec::new();
+
+    loop {
+        parser.skip_blank(true);
+
+        let line = parser.line;
+
+        match parser.chars.peek() {
+            None => return Ok(entries),
+            Some('[') => return Err((line, "tables are not supported".to_owned())),
+            Some(_) => {}
+        }
+
+        let key = parser.key()?;
+        parser.skip_blank(false);
+
+        if parser.chars.next() != Some('=') {
+            return Err((line, format!("expected `=` after `{}`", key)));
+        }
+
+        parser.skip_blank(false);
+        let value = parser.value()?;
+        parser.skip_blank(false);
+
+        match parser.chars.peek() {
+            None | Some('\n') => entries.push((line, key, value)),
+            Some(_) => return Err((parser.line, "expected the end of the line".to_owned())),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    /// Skips spaces and comments, and line breaks too with `newlines`.
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+
+            self.chars.next();
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        let mut key = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+
+            key.push(c);
+            self.chars.next();
+        }
+
+        if key.is_empty() {
+            return Err((self.line, "expected a key".to_owned()));
+        }
+
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let line = self.line;
+
+        match self.chars.peek() {
+            Some('"') => self.basic_string().map(Value::Str),
+            Some('\'') => self.literal_string().map(Value::Str),
+            Some('[') => self.array(),
+            _ => {
+                let mut word = String::new();
+
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || c == ',' || c == ']' || c == '#' {
+                        break;
+                    }
+
+                    word.push(c);
+                    self.chars.next();
+                }
+
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => Err((line, "expected a value".to_owned())),
+                    _ => {
+                        let number = word.replace('_', "");
+
+                        number
+                            .parse()
+                            .map(Value::Int)
+                            .or_else(|_| number.parse().map(Value::Float))
+                            .map_err(|_| (line, format!("invalid value `{}`", word)))
+                    }
+                }
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        let line = self.line;
+        let mut s = String::new();
+        self.chars.next();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(kind @ 'u') | Some(kind @ 'U') => {
+                            let len = if kind == 'u' { 4 } else { 8 };
+                            let hex: String = (0..len).filter_map(|_| self.chars.next()).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    (line, format!("invalid escape `\\{}{}`", kind, hex))
+                                })?
+                        }
+                        Some(c) => return Err((line, format!("invalid escape `\\{}`", c))),
+                        None => return Err((line, "unterminated string".to_owned())),
+                    };
+                    s.push(c);
+                }
+                Some('\n') | None => return Err((line, "unterminated string".to_owned())),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        let line = self.line;
+        let mut s = String::new();
+        self.chars.next();
+
+        loop {
+            match self.chars.next() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None => return Err((line, "unterminated string".to_owned())),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        let line = self.line;
+        let mut values = Vec::new();
+        self.chars.next();
+
+        loop {
+            self.skip_blank(true);
+
+            match self.chars.peek() {
+                Some(']') => {
+                    self.chars.next();
+                    return Ok(Value::Array(values));
+                }
+                None => return Err((line, "unterminated array".to_owned())),
+                Some(_) => values.push(self.value()?),
+            }
+
+            self.skip_blank(true);
+
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err((self.line, "expected `,` or `]` in array".to_owned())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        vars.iter()
+            .map(|&(var, value)| (var.into(), value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn leaves_variables_read_elsewhere_alone() {
+        let entries = var_entries(vars(&[
+            ("CRTIME_PICKER", "fzf --multi"),
+            ("CRTIME_RECURSIVE", "1"),
+            ("PATH", "/bin"),
+        ]))
+        .unwrap();
+
+        assert_eq!(entries, vec![("recursive", Value::Bool(true))]);
+    }
+
+    #[test]
+    fn reports_unknown_variables() {
+        let error = var_entries(vars(&[("CRTIME_RECURSIV", "1")])).unwrap_err();
+
+        assert!(error.to_string().contains("did you mean CRTIME_RECURSIVE?"));
+    }
+
+    #[test]
+    fn takes_fractions_of_operations_a_second() {
+        let entries = parse("throttle = 0.5\n").unwrap();
+        assert_eq!(entries[0].2, Value::Float(0.5));
+        assert_eq!(check("throttle", Kind::Rate, &entries[0].2), Ok(()));
+        assert_eq!(
+            entry_args(&[("throttle", Value::Float(0.5))]),
+            ["--throttle", "0.5"]
+        );
+
+        for value in [
+            Value::Int(0),
+            Value::Float(-1.0),
+            Value::Float(f64::INFINITY),
+        ] {
+            assert!(check("throttle", Kind::Rate, &value).is_err());
+        }
+
+        let entries = var_entries(vars(&[("CRTIME_THROTTLE", "2.5")])).unwrap();
+        assert_eq!(entries, vec![("throttle", Value::Float(2.5))]);
+    }
+
+    #[test]
+    fn turns_off_flags_set_before() {
+        let settings = Settings {
+            layers: vec![
+                (
+                    Origin::UserFile(PathBuf::from("config.toml")),
+                    vec![("recursive", Value::Bool(true)), ("jobs", Value::Int(2))],
+                ),
+                (Origin::Environment, vec![("recursive", Value::Bool(false))]),
+                (Origin::CommandLine, Vec::new()),
+            ],
+        };
+
+        let args = settings.args(vec!["crtime".to_owned(), "photos".to_owned()]);
+        assert_eq!(args, ["crtime", "--jobs", "2", "photos"]);
+        assert_eq!(
+            settings.to_string(),
+            "recursive = false  # environment\njobs = 2  # config.toml\n"
+        );
+    }
+
+    #[test]
+    fn finds_the_directory_after_options_with_values() {
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|&arg| arg.to_owned()).collect() };
+
+        assert_eq!(
+            dir_arg(&args(&["crtime", "-j", "2", "photos"])),
+            Some("photos")
+        );
+        assert_eq!(
+            dir_arg(&args(&["crtime", "-r", "-y", "photos"])),
+            Some("photos")
+        );
+        assert_eq!(
+            dir_arg(&args(&[
+                "crtime", "--jobs", "2", "--by", "day", "stats", "photos"
+            ])),
+            Some("photos")
+        );
+        assert_eq!(dir_arg(&args(&["crtime", "--template", "{stem}"])), None);
+    }
+}
@@ -0,0 +1,84 @@
+use infer::MatcherType;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Broad kinds of file contents, told apart by their magic bytes rather
+/// than by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Image,
+    Video,
+    Audio,
+    /// Office documents, e-books and PDFs.
+    Document,
+}
+
+impl FromStr for ContentType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "image" => Ok(ContentType::Image),
+            "video" => Ok(ContentType::Video),
+            "audio" => Ok(ContentType::Audio),
+            "document" => Ok(ContentType::Document),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ContentType {
+    /// Extensions usually given to files of the kind, in lower case.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ContentType::Image => &[
+                "jpg", "jpeg", "jpe", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "heif",
+                "avif", "jxl", "psd", "svg", "dng", "cr2", "cr3", "crw", "nef", "nrw", "arw",
+                "srf", "sr2", "orf", "rw2", "raf", "pef", "srw", "rwl", "3fr", "erf", "kdc", "mrw",
+                "x3f",
+            ],
+            ContentType::Video => &[
+                "mp4", "m4v", "mov", "qt", "avi", "mkv", "webm", "mts", "m2ts", "ts", "3gp", "3g2",
+                "wmv", "flv", "mpg", "mpeg", "vob", "ogv", "insv", "lrv",
+            ],
+            ContentType::Audio => &[
+                "mp3", "m4a", "aac", "flac", "wav", "ogg", "oga", "opus", "wma", "aif", "aiff",
+                "amr",
+            ],
+            ContentType::Document => &[
+                "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv",
+                "ppt", "pptx", "odp", "epub", "mobi", "azw", "azw3", "djvu", "pages", "numbers",
+                "key",
+            ],
+        }
+    }
+
+    /// Whether `name` has one of the usual extensions of the kind.
+    pub fn has_extension(self, name: &str) -> bool {
+        let extension = Path::new(name).extension().and_then(|ext| ext.to_str());
+
+        extension.is_some_and(|ext| {
+            self.extensions()
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+    }
+}
+
+/// The kind of the file at `path`, if its contents are recognized.
+pub fn detect(path: &Path) -> io::Result<Option<ContentType>> {
+    let kind = match infer::get_from_path(path)? {
+        Some(kind) => kind,
+        None => return Ok(None),
+    };
+
+    Ok(match kind.matcher_type() {
+        MatcherType::Image => Some(ContentType::Image),
+        MatcherType::Video => Some(ContentType::Video),
+        MatcherType::Audio => Some(ContentType::Audio),
+        MatcherType::Doc | MatcherType::Book => Some(ContentType::Document),
+        _ if kind.mime_type() == "application/pdf" => Some(ContentType::Document),
+        _ => None,
+    })
+}
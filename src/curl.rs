@@ -0,0 +1,230 @@
+//! HTTP requests through the system's `curl`, for remote storage.
+//!
+//! Options go to `curl` on its standard input in its config file syntax,
+//! so credentials never show up in its command line.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+pub struct Request {
+    config: String,
+    head: bool,
+}
+
+pub struct Response {
+    pub status: u16,
+    /// Header names in lowercase, with their values.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn new(method: &str, url: &str) -> Request {
+        let mut request = Request {
+            config: String::new(),
+            head: method == "HEAD",
+        };
+
+        request.option("url", url);
+
+        if !request.head {
+            request.option("request", method);
+        }
+
+        request
+    }
+
+    /// Sets the long option `name` of `curl` to `value`.
+    pub fn option(&mut self, name: &str, value: &str) -> &mut Request {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        self.config.push_str(&format!("{} = \"{}\"\n", name, value));
+        self
+    }
+
+    /// Turns on the long option `name` of `curl`, which takes no value.
+    pub fn flag(&mut self, name: &str) -> &mut Request {
+        self.config.push_str(name);
+        self.config.push('\n');
+        self
+    }
+
+    pub fn header(&mut self, header: &str) -> &mut Request {
+        self.option("header", header)
+    }
+
+    /// Sends the request. Failing to reach the server is reported as
+    /// `io::ErrorKind::TimedOut`, making it worth a retry.
+    pub fn send(&self) -> io::Result<Response> {
+        let mut config = self.config.clone();
+        config.push_str(if self.head { "head\n" } else { "include\n" });
+
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(config.as_bytes())?;
+
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                message.trim().to_owned(),
+            ));
+        }
+
+        parse_response(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The kind of error the status stands for.
+    pub fn error_kind(&self) -> io::ErrorKind {
+        match self.status {
+            401 | 403 => io::ErrorKind::PermissionDenied,
+            404 => io::ErrorKind::NotFound,
+            // Rate limits and overload, which are worth retrying.
+            429 | 500 | 502 | 503 => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+/// Splits what `curl --include` prints into the final status, headers and
+/// body, past any `100 Continue` or proxy responses before them.
+pub(crate) fn parse_response(text: &str) -> io::Result<Response> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unexpected HTTP response");
+    let mut rest = text;
+
+    loop {
+        let (head, body) = match rest.split_once("\r\n\r\n") {
+            Some(split) => split,
+            None => (rest.trim_end(), ""),
+        };
+        let mut lines = head.split("\r\n");
+        let status: u16 = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(invalid)?;
+
+        if body.starts_with("HTTP/") && (status < 200 || head.contains("onnection established")) {
+            rest = body;
+            continue;
+        }
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned()))
+            .collect();
+
+        return Ok(Response {
+            status,
+            headers,
+            body: body.to_owned(),
+        });
+    }
+}
+
+/// Percent-encodes `text` for a URL, keeping `/` unless `query`.
+pub fn encode(text: &str, query: bool) -> String {
+    let mut out = String::new();
+
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(char::from(byte))
+            }
+            b'/' if !query => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    out
+}
+
+/// Undoes `encode`, keeping what is not validly encoded as it is.
+pub fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_status_headers_and_body() {
+        let response =
+            parse_response("HTTP/1.1 200 OK\r\nContent-Length: 2\r\nETag: \"abc\"\r\n\r\nok")
+                .unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-length"), Some("2"));
+        assert_eq!(response.header("etag"), Some("\"abc\""));
+        assert_eq!(response.body, "ok");
+    }
+
+    #[test]
+    fn skips_interim_and_proxy_responses() {
+        let text = "HTTP/1.1 200 Connection established\r\n\r\n\
+                    HTTP/1.1 100 Continue\r\n\r\n\
+                    HTTP/1.1 404 Not Found\r\nX-Id: 1\r\n\r\nmissing";
+        let response = parse_response(text).unwrap();
+
+        assert_eq!(response.status, 404);
+        assert_eq!(response.header("x-id"), Some("1"));
+        assert_eq!(response.body, "missing");
+    }
+
+    #[test]
+    fn parses_responses_without_body() {
+        let response = parse_response("HTTP/2 204\r\nDate: today\r\n").unwrap();
+
+        assert_eq!(response.status, 204);
+        assert_eq!(response.header("date"), Some("today"));
+        assert_eq!(response.body, "");
+    }
+
+    #[test]
+    fn rejects_other_output() {
+        assert!(parse_response("").is_err());
+        assert!(parse_response("curl: (6) Could not resolve host").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use crate::hash::sha256_file;
+use crate::{resolve_collisions, Plan, RenamePlanItem};
+
+/// Directory in the planned directory duplicates are moved into.
+pub const QUARANTINE_DIR: &str = "duplicates";
+
+/// Indices of items with exactly the same contents, in groups of two or
+/// more, oldest file first.
+///
+/// Only files of the same size are hashed. Hard links to a file already
+/// in a group are left out, as they are not copies.
+pub fn groups(items: &[RenamePlanItem]) -> io::Result<Vec<Vec<usize>>> {
+    let mut by_len: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut inodes = HashSet::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if item.stamp.inode.is_none_or(|inode| inodes.insert(inode)) {
+            by_len.entry(item.stamp.len).or_default().push(i);
+        }
+    }
+
+    let mut groups = Vec::new();
+
+    for candidates in by_len.into_values().filter(|group| group.len() > 1) {
+        let mut by_hash: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+
+        for i in candidates {
+            by_hash
+                .entry(sha256_file(&items[i].path)?)
+                .or_default()
+                .push(i);
+        }
+
+        groups.extend(by_hash.into_values().filter(|group| group.len() > 1));
+    }
+
+    for group in &mut groups {
+        group.sort();
+    }
+
+    groups.sort();
+    Ok(groups)
+}
+
+/// Turns `plan` into one moving every duplicate but the oldest of each
+/// group in `groups` into `QUARANTINE_DIR` under `dir`.
+pub fn quarantine(mut plan: Plan, groups: &[Vec<usize>], dir: &Path) -> Plan {
+    let extra: HashSet<_> = groups.iter().flat_map(|group| &group[1..]).collect();
+    let target_dir = dir.join(QUARANTINE_DIR);
+
+    plan.items = std::mem::take(&mut plan.items)
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| extra.contains(i))
+        .map(|(_, mut item)| {
+            item.new_name = item.name.clone();
+            item.new_path = target_dir.join(&item.name);
+            item
+        })
+        .collect();
+    plan.skipped.clear();
+
+    resolve_collisions(&mut plan.items);
+    plan
+}
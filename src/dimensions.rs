@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes read from the start of a file, enough for every header but
+/// JPEG's.
+const HEADER_LEN: usize = 32;
+
+/// Width and height in pixels of the image at `path`, read from its
+/// header. PNG, JPEG, GIF, BMP and WebP images are understood.
+pub fn read(path: &Path) -> io::Result<Option<(u32, u32)>> {
+    let mut file = File::open(path)?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut file)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+
+    let u16_le = |i: usize| Some(u16::from_le_bytes([*header.get(i)?, *header.get(i + 1)?]));
+    let bytes4 = |i: usize| header.get(i..i + 4).map(|b| [b[0], b[1], b[2], b[3]]);
+    let u32_le = |i: usize| bytes4(i).map(u32::from_le_bytes);
+    let u32_be = |i: usize| bytes4(i).map(u32::from_be_bytes);
+    let u24_le = |i: usize| Some(u32_le(i)? & 0xff_ffff);
+
+    let size = match &header[..] {
+        [0x89, b'P', b'N', b'G', ..] => u32_be(16).zip(u32_be(20)),
+        [0xff, 0xd8, ..] => return read_jpeg(&mut file),
+        [b'G', b'I', b'F', b'8', ..] => u16_le(6).map(u32::from).zip(u16_le(8).map(u32::from)),
+        [b'B', b'M', ..] => u32_le(18).zip(u32_le(22).map(|h| (h as i32).unsigned_abs())),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', b'V', b'P', b'8', kind, ..] => {
+            match kind {
+                b' ' => u16_le(26)
+                    .zip(u16_le(28))
+                    .map(|(w, h)| (u32::from(w & 0x3fff), u32::from(h & 0x3fff))),
+                b'L' => u32_le(21).map(|bits| ((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1)),
+                b'X' => u24_le(24).zip(u24_le(27)).map(|(w, h)| (w + 1, h + 1)),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    Ok(size)
+}
+
+/// Finds the frame header of a JPEG, skipping the segments before it.
+fn read_jpeg(file: &mut File) -> io::Result<Option<(u32, u32)>> {
+    file.seek(SeekFrom::Start(2))?;
+
+    loop {
+        let mut marker = [0; 4];
+
+        if file.read_exact(&mut marker).is_err() || marker[0] != 0xff {
+            return Ok(None);
+        }
+
+        let len = u16::from_be_bytes([marker[2], marker[3]]);
+
+        match marker[1] {
+            // Start of frame, except for DHT, JPG and DAC, which share the
+            // range.
+            0xc0..=0xcf if !matches!(marker[1], 0xc4 | 0xc8 | 0xcc) => {
+                let mut frame = [0; 5];
+                return match file.read_exact(&mut frame) {
+                    Ok(()) => Ok(Some((
+                        u32::from(u16::from_be_bytes([frame[3], frame[4]])),
+                        u32::from(u16::from_be_bytes([frame[1], frame[2]])),
+                    ))),
+                    Err(_) => Ok(None),
+                };
+            }
+            0xda | 0xd9 => return Ok(None),
+            _ => {
+                file.seek(SeekFrom::Current(i64::from(len) - 2))?;
+            }
+        }
+    }
+}
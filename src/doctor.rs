@@ -0,0 +1,153 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::btime;
+use crate::fs_kind::FsKind;
+use crate::FsItemError;
+
+/// What the filesystem of a directory supports. `None` means the probe
+/// could not tell.
+#[derive(Debug)]
+pub struct Capabilities {
+    pub fs_kind: FsKind,
+    pub writable: bool,
+    pub birth_time: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    /// Longest file name allowed, in bytes.
+    pub name_max: Option<u64>,
+    pub xattr: Option<bool>,
+}
+
+/// Temporary file created for probing, removed again on drop.
+struct Probe {
+    path: PathBuf,
+}
+
+impl Probe {
+    fn create(dir: &Path, suffix: &str) -> io::Result<Probe> {
+        let path = dir.join(format!(".crtime-doctor-{}-{}", process::id(), suffix));
+        File::create(&path)?;
+        Ok(Probe { path })
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Probes the filesystem holding `dir` by creating short-lived files in it.
+pub fn probe(dir: &Path) -> io::Result<Capabilities> {
+    let mut caps = Capabilities {
+        fs_kind: FsKind::detect(dir),
+        writable: false,
+        birth_time: None,
+        case_sensitive: None,
+        name_max: name_max(dir),
+        xattr: None,
+    };
+
+    let probe = match Probe::create(dir, "probe") {
+        Ok(probe) => probe,
+        Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => return Ok(caps),
+        Err(error) => return Err(error),
+    };
+
+    caps.writable = true;
+
+    let meta = fs::symlink_metadata(&probe.path)?;
+    caps.birth_time = match btime::birth_time(&probe.path, &meta) {
+        Ok(_) => Some(true),
+        Err(FsItemError::NoBirthTime) => Some(false),
+        Err(_) => None,
+    };
+
+    let upper = probe.path.with_file_name(
+        probe
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_uppercase())
+            .unwrap_or_default(),
+    );
+    caps.case_sensitive = Some(fs::symlink_metadata(&upper).is_err());
+    caps.xattr = xattr(&probe.path);
+
+    Ok(caps)
+}
+
+#[cfg(unix)]
+fn name_max(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let max = unsafe { libc::pathconf(c_dir.as_ptr(), libc::_PC_NAME_MAX) };
+
+    if max > 0 {
+        Some(max as u64)
+    } else {
+        None
+    }
+}
+
+#[cfg(windows)]
+fn name_max(_dir: &Path) -> Option<u64> {
+    Some(255)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn name_max(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Tries setting and removing an extended attribute on `path`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn xattr(path: &Path) -> Option<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let name = CString::new("user.crtime.probe").ok()?;
+    let value = b"1";
+
+    #[cfg(target_os = "linux")]
+    let set = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+
+    #[cfg(target_os = "macos")]
+    let set = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+            0,
+        )
+    };
+
+    if set == 0 {
+        return Some(true);
+    }
+
+    match io::Error::last_os_error().raw_os_error() {
+        Some(libc::ENOTSUP) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn xattr(_path: &Path) -> Option<bool> {
+    None
+}
@@ -0,0 +1,92 @@
+//! Wall-clock times are read in the local timezone when they come without
+//! an offset, as in most EXIF data and file names. Around a change to or
+//! from daylight saving time such a time can be ambiguous, when the
+//! clocks went back and it happened twice, or skipped, when they went
+//! forward. The policy decides which instant these stand for, the same on
+//! every run.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How ambiguous and skipped local times are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DstPolicy {
+    /// The earlier of the instants the time can stand for.
+    #[default]
+    Earliest,
+    /// The later of them.
+    Latest,
+    /// The time read as UTC instead.
+    Utc,
+}
+
+impl FromStr for DstPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "earliest" => Ok(DstPolicy::Earliest),
+            "latest" => Ok(DstPolicy::Latest),
+            "utc" => Ok(DstPolicy::Utc),
+            _ => Err(()),
+        }
+    }
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the policy for the rest of the run.
+pub fn set(policy: DstPolicy) {
+    POLICY.store(policy as u8, Ordering::SeqCst);
+}
+
+pub fn policy() -> DstPolicy {
+    match POLICY.load(Ordering::SeqCst) {
+        1 => DstPolicy::Latest,
+        2 => DstPolicy::Utc,
+        _ => DstPolicy::Earliest,
+    }
+}
+
+/// The instant `naive` stands for in `zone`, by the policy set where the
+/// zone changed its offset around it.
+///
+/// A skipped time is read with the offsets from before and after the
+/// change, the earlier result being the earliest one.
+pub fn to_utc<Tz: TimeZone>(zone: &Tz, naive: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    let local = match zone.from_local_datetime(naive) {
+        LocalResult::Single(time) => return Some(time.with_timezone(&Utc)),
+        local => local,
+    };
+    let policy = policy();
+
+    if policy == DstPolicy::Utc {
+        return Some(naive.and_utc());
+    }
+
+    let (earliest, latest) = match local {
+        // Not necessarily in order, depending on the zone.
+        LocalResult::Ambiguous(one, other) => {
+            let (one, other) = (one.with_timezone(&Utc), other.with_timezone(&Utc));
+            (one.min(other), one.max(other))
+        }
+        _ => {
+            // A day apart, the offsets are surely those on either side.
+            let offset_at = |naive: NaiveDateTime| {
+                let time = zone.from_local_datetime(&naive).earliest()?;
+                Some(time.offset().fix().local_minus_utc())
+            };
+            let before = offset_at(*naive - Duration::days(1))?;
+            let after = offset_at(*naive + Duration::days(1))?;
+            let read = |offset: i32| naive.and_utc() - Duration::seconds(i64::from(offset));
+
+            (read(before.max(after)), read(before.min(after)))
+        }
+    };
+
+    match policy {
+        DstPolicy::Latest => Some(latest),
+        _ => Some(earliest),
+    }
+}
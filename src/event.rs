@@ -0,0 +1,304 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::json;
+use crate::profile::Timings;
+use crate::summary::Summary;
+use crate::{Plan, RenameFailure, RenameOutcome, RenamePlanItem, RenameSkip, SkippedItem};
+
+/// Version of the JSON objects crtime prints, given as their
+/// `schema_version`. It only goes up when a field is removed or changes
+/// its meaning; fields and events may be added without it changing.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Progress of planning and applying, in the order it happens.
+#[derive(Debug)]
+pub enum Event<'a> {
+    Planned(&'a RenamePlanItem),
+    /// A file left out while planning.
+    NotPlanned(&'a SkippedItem),
+    Renamed(&'a RenamePlanItem),
+    /// A planned file left alone while applying.
+    Skipped(&'a RenameSkip<'a>),
+    Failed(&'a RenameFailure<'a>),
+    /// A rename undone after a later failure.
+    RolledBack(&'a RenamePlanItem),
+    Cancelled,
+    Interrupted,
+    /// The batch stopped at its first failure.
+    Stopped,
+    Summary(&'a Summary),
+    Profile(&'a Timings),
+}
+
+/// Receives progress of planning and applying as it happens, for
+/// frontends embedding crtime, such as ones drawing progress bars. Every
+/// method does nothing unless implemented.
+///
+/// Closures taking an `Event` are observers too, told the matching events.
+pub trait Observer {
+    /// A file planned to be renamed, once the plan is complete.
+    fn on_planned(&mut self, _item: &RenamePlanItem) {}
+
+    /// A file left out while planning.
+    fn on_not_planned(&mut self, _skipped: &SkippedItem) {}
+
+    fn on_renamed(&mut self, _item: &RenamePlanItem) {}
+
+    /// A planned file left alone while applying.
+    fn on_skipped(&mut self, _skip: &RenameSkip) {}
+
+    fn on_error(&mut self, _failure: &RenameFailure) {}
+
+    /// A rename undone after a later failure.
+    fn on_rolled_back(&mut self, _item: &RenamePlanItem) {}
+
+    /// The end of applying a plan, with what came of it.
+    fn on_done(&mut self, _outcome: &RenameOutcome) {}
+}
+
+impl<F: FnMut(Event)> Observer for F {
+    fn on_planned(&mut self, item: &RenamePlanItem) {
+        self(Event::Planned(item))
+    }
+
+    fn on_not_planned(&mut self, skipped: &SkippedItem) {
+        self(Event::NotPlanned(skipped))
+    }
+
+    fn on_renamed(&mut self, item: &RenamePlanItem) {
+        self(Event::Renamed(item))
+    }
+
+    fn on_skipped(&mut self, skip: &RenameSkip) {
+        self(Event::Skipped(skip))
+    }
+
+    fn on_error(&mut self, failure: &RenameFailure) {
+        self(Event::Failed(failure))
+    }
+
+    fn on_rolled_back(&mut self, item: &RenamePlanItem) {
+        self(Event::RolledBack(item))
+    }
+}
+
+/// Progress sent over a channel by `plan_with_events` and
+/// `apply_with_events`, owning what it tells about so that it can go to
+/// another thread.
+#[derive(Debug)]
+pub enum Progress {
+    Planned {
+        path: PathBuf,
+        new_path: PathBuf,
+    },
+    /// A file left out while planning.
+    NotPlanned {
+        path: PathBuf,
+        reason: String,
+    },
+    Renamed {
+        path: PathBuf,
+        new_path: PathBuf,
+    },
+    /// A planned file left alone while applying.
+    Skipped {
+        path: PathBuf,
+        reason: String,
+    },
+    Failed {
+        path: PathBuf,
+        new_path: PathBuf,
+        error: String,
+    },
+    /// A rename undone after a later failure.
+    RolledBack {
+        path: PathBuf,
+        new_path: PathBuf,
+    },
+    /// The complete plan, the last event of planning.
+    Plan(io::Result<Plan>),
+    /// The totals of applying, its last event.
+    Done(io::Result<Totals>),
+}
+
+/// What came of applying a plan, counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rolled_back: usize,
+    pub bytes_copied: u64,
+    pub interrupted: bool,
+    pub stopped: bool,
+}
+
+impl Progress {
+    /// The progress `event` tells of, if it is about a single file.
+    pub fn from_event(event: &Event) -> Option<Progress> {
+        let paths = |item: &RenamePlanItem| (item.path.clone(), item.new_path.clone());
+
+        Some(match event {
+            Event::Planned(item) => {
+                let (path, new_path) = paths(item);
+                Progress::Planned { path, new_path }
+            }
+            Event::NotPlanned(skipped) => Progress::NotPlanned {
+                path: skipped.path.clone(),
+                reason: skipped.reason.to_string(),
+            },
+            Event::Renamed(item) => {
+                let (path, new_path) = paths(item);
+                Progress::Renamed { path, new_path }
+            }
+            Event::Skipped(skip) => Progress::Skipped {
+                path: skip.item.path.clone(),
+                reason: skip.reason.to_string(),
+            },
+            Event::Failed(failure) => {
+                let (path, new_path) = paths(failure.item);
+                let error = failure.reason.to_string();
+                Progress::Failed {
+                    path,
+                    new_path,
+                    error,
+                }
+            }
+            Event::RolledBack(item) => {
+                let (path, new_path) = paths(item);
+                Progress::RolledBack { path, new_path }
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl Totals {
+    pub fn new(outcome: &RenameOutcome) -> Totals {
+        Totals {
+            renamed: outcome.renamed.len(),
+            skipped: outcome.skipped.len(),
+            failed: outcome.failed.len(),
+            rolled_back: outcome.rolled_back.len(),
+            bytes_copied: outcome.bytes_copied,
+            interrupted: outcome.interrupted,
+            stopped: outcome.stopped,
+        }
+    }
+}
+
+/// The `--output` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(()),
+        }
+    }
+}
+
+fn path(path: &Path) -> String {
+    json::string(&path.to_string_lossy())
+}
+
+impl<'a> Event<'a> {
+    /// Encodes the event as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let fields = self.fields();
+        format!(r#"{{"schema_version":{},{}"#, SCHEMA_VERSION, &fields[1..])
+    }
+
+    fn fields(&self) -> String {
+        match self {
+            Event::Planned(item) => format!(
+                r#"{{"event":"planned","path":{},"new_path":{},"created":{}}}"#,
+                path(&item.path),
+                path(&item.new_path),
+                json::string(&item.created.to_rfc3339())
+            ),
+            Event::NotPlanned(skipped) => format!(
+                r#"{{"event":"skipped","phase":"plan","path":{},"reason":{},"code":{}}}"#,
+                path(&skipped.path),
+                json::string(&skipped.reason.to_string()),
+                json::string(skipped.reason.code())
+            ),
+            Event::Renamed(item) => format!(
+                r#"{{"event":"renamed","path":{},"new_path":{}}}"#,
+                path(&item.path),
+                path(&item.new_path)
+            ),
+            Event::RolledBack(item) => format!(
+                r#"{{"event":"rolled_back","path":{},"new_path":{}}}"#,
+                path(&item.path),
+                path(&item.new_path)
+            ),
+            Event::Skipped(skip) => format!(
+                r#"{{"event":"skipped","phase":"apply","path":{},"reason":{},"code":{}}}"#,
+                path(&skip.item.path),
+                json::string(&skip.reason.to_string()),
+                json::string(skip.reason.code())
+            ),
+            Event::Failed(failure) => format!(
+                r#"{{"event":"failed","path":{},"new_path":{},"error":{},"code":{}}}"#,
+                path(&failure.item.path),
+                path(&failure.item.new_path),
+                json::string(&failure.reason.to_string()),
+                json::string(failure.code())
+            ),
+            Event::Cancelled => r#"{"event":"cancelled"}"#.to_owned(),
+            Event::Interrupted => r#"{"event":"interrupted"}"#.to_owned(),
+            Event::Stopped => r#"{"event":"stopped"}"#.to_owned(),
+            Event::Summary(summary) => {
+                let skipped: Vec<_> = summary
+                    .skipped
+                    .iter()
+                    .map(|(reason, count)| format!("{}:{}", json::string(reason), count))
+                    .collect();
+
+                format!(
+                    r#"{{"event":"summary","scanned":{},"renamed":{},"skipped":{{{}}},"failed":{},"bytes_copied":{},"elapsed_ms":{}}}"#,
+                    summary.scanned,
+                    summary.renamed,
+                    skipped.join(","),
+                    summary.failed,
+                    summary.bytes_copied,
+                    summary.elapsed.as_millis()
+                )
+            }
+            Event::Profile(timings) => {
+                let sources: Vec<_> = timings
+                    .sources
+                    .iter()
+                    .map(|(source, timing)| {
+                        format!(
+                            r#"{}:{{"ms":{:.3},"files":{}}}"#,
+                            json::string(source.name()),
+                            timing.time.as_secs_f64() * 1000.0,
+                            timing.files
+                        )
+                    })
+                    .collect();
+
+                format!(
+                    r#"{{"event":"profile","scanning_ms":{:.3},"sources":{{{}}},"planning_ms":{:.3},"applying_ms":{:.3}}}"#,
+                    timings.scanning.as_secs_f64() * 1000.0,
+                    sources.join(","),
+                    timings.planning.as_secs_f64() * 1000.0,
+                    timings.applying.as_secs_f64() * 1000.0
+                )
+            }
+        }
+    }
+}
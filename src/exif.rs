@@ -0,0 +1,323 @@
+use chrono::FixedOffset;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Most of a TIFF-based file read when looking for its metadata.
+const TIFF_READ_LIMIT: u64 = 4 * 1024 * 1024;
+
+pub const TAG_MAKE: u16 = 0x010f;
+pub const TAG_MODEL: u16 = 0x0110;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+pub const TAG_GPS_LATITUDE: u16 = 0x0002;
+pub const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+pub const TAG_GPS_LONGITUDE: u16 = 0x0004;
+pub const TAG_GPS_IFD: u16 = 0x8825;
+pub const TAG_ISO: u16 = 0x8827;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+pub const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+pub const TAG_FOCAL_LENGTH: u16 = 0x920a;
+pub const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+pub const TAG_SUB_SEC_TIME_DIGITIZED: u16 = 0x9292;
+pub const TAG_LENS_MODEL: u16 = 0xa434;
+
+/// Directories of tags in an EXIF block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ifd {
+    Primary,
+    Exif,
+    Gps,
+}
+
+/// A tag value, decoded as far as crtime needs it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SRational(Vec<(i32, i32)>),
+    Bytes(Vec<u8>),
+}
+
+/// Metadata tags read from a JPEG or TIFF-based file.
+#[derive(Debug, Default)]
+pub struct Exif {
+    tags: HashMap<(Ifd, u16), Value>,
+}
+
+impl Exif {
+    /// Reads the EXIF block of the file at `path`, if it has one.
+    pub fn read(path: &Path) -> io::Result<Option<Exif>> {
+        let mut file = File::open(path)?;
+        let mut magic = [0; 4];
+
+        if file.read(&mut magic)? < 4 {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+
+        match magic {
+            [0xff, 0xd8, ..] => read_jpeg(&mut file),
+            [b'I', b'I', 42, 0] | [b'M', b'M', 0, 42] => {
+                let mut data = Vec::new();
+                file.take(TIFF_READ_LIMIT).read_to_end(&mut data)?;
+                Ok(Exif::from_tiff(&data))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a TIFF structure, as found in TIFF files or JPEG APP1 segments.
+    pub fn from_tiff(data: &[u8]) -> Option<Exif> {
+        let reader = match data.get(0..2)? {
+            b"II" => Reader {
+                data,
+                big_endian: false,
+            },
+            b"MM" => Reader {
+                data,
+                big_endian: true,
+            },
+            _ => return None,
+        };
+
+        if reader.u16(2)? != 42 {
+            return None;
+        }
+
+        let mut exif = Exif::default();
+
+        exif.read_ifd(&reader, Ifd::Primary, reader.u32(4)?);
+
+        if let Some(offset) = exif.long(Ifd::Primary, TAG_EXIF_IFD) {
+            exif.read_ifd(&reader, Ifd::Exif, offset);
+        }
+
+        if let Some(offset) = exif.long(Ifd::Primary, TAG_GPS_IFD) {
+            exif.read_ifd(&reader, Ifd::Gps, offset);
+        }
+
+        Some(exif)
+    }
+
+    fn read_ifd(&mut self, reader: &Reader, ifd: Ifd, offset: u32) {
+        let offset = offset as usize;
+        let count = match reader.u16(offset) {
+            Some(count) => count as usize,
+            None => return,
+        };
+
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+
+            if let Some((tag, value)) = reader.entry(entry) {
+                self.tags.insert((ifd, tag), value);
+            }
+        }
+    }
+
+    pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&Value> {
+        self.tags.get(&(ifd, tag))
+    }
+
+    pub fn ascii(&self, ifd: Ifd, tag: u16) -> Option<&str> {
+        match self.get(ifd, tag)? {
+            Value::Ascii(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    /// First value of an integer tag.
+    pub fn long(&self, ifd: Ifd, tag: u16) -> Option<u32> {
+        match self.get(ifd, tag)? {
+            Value::Short(values) => values.first().map(|&v| u32::from(v)),
+            Value::Long(values) => values.first().cloned(),
+            _ => None,
+        }
+    }
+
+    /// First value of a rational tag.
+    pub fn rational(&self, ifd: Ifd, tag: u16) -> Option<f64> {
+        match self.get(ifd, tag)? {
+            Value::Rational(values) => match values.first()? {
+                (_, 0) => None,
+                &(n, d) => Some(f64::from(n) / f64::from(d)),
+            },
+            _ => None,
+        }
+    }
+
+    /// Latitude and longitude in degrees, north and east positive.
+    pub fn gps_position(&self) -> Option<(f64, f64)> {
+        let coordinate = |tag, ref_tag, negative| {
+            let degrees = match self.get(Ifd::Gps, tag)? {
+                Value::Rational(parts) if parts.len() == 3 => parts
+                    .iter()
+                    .zip(&[1.0, 60.0, 3600.0])
+                    .map(|(&(n, d), scale)| match d {
+                        0 => None,
+                        d => Some(f64::from(n) / f64::from(d) / scale),
+                    })
+                    .sum::<Option<f64>>()?,
+                _ => return None,
+            };
+
+            match self.ascii(Ifd::Gps, ref_tag)?.trim() {
+                r if r == negative => Some(-degrees),
+                _ => Some(degrees),
+            }
+        };
+
+        let latitude = coordinate(TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S")?;
+        let longitude = coordinate(TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, "W")?;
+
+        Some((latitude, longitude))
+    }
+}
+
+/// The UTC offset recorded for when the photo at `path` was taken.
+pub fn capture_offset(path: &Path) -> Option<FixedOffset> {
+    let exif = Exif::read(path).ok()??;
+    exif.ascii(Ifd::Exif, TAG_OFFSET_TIME_ORIGINAL)?
+        .parse()
+        .ok()
+}
+
+fn read_jpeg(file: &mut File) -> io::Result<Option<Exif>> {
+    const SOS: u8 = 0xda;
+    const EOI: u8 = 0xd9;
+    const APP1: u8 = 0xe1;
+
+    let mut marker = [0; 2];
+    file.read_exact(&mut marker)?;
+
+    loop {
+        let mut byte = [0; 1];
+
+        // Markers start with 0xff, possibly repeated as fill bytes.
+        loop {
+            file.read_exact(&mut byte)?;
+            if byte[0] != 0xff {
+                break;
+            }
+        }
+
+        let marker = byte[0];
+
+        if marker == SOS || marker == EOI {
+            return Ok(None);
+        }
+
+        // Standalone markers without a length.
+        if (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+            continue;
+        }
+
+        let mut len = [0; 2];
+        file.read_exact(&mut len)?;
+        let len = u16::from_be_bytes(len).saturating_sub(2) as u64;
+
+        if marker == APP1 {
+            let mut data = Vec::new();
+            file.by_ref().take(len).read_to_end(&mut data)?;
+
+            if data.starts_with(b"Exif\0\0") {
+                return Ok(Exif::from_tiff(&data[6..]));
+            }
+        } else {
+            file.seek(SeekFrom::Current(len as i64))?;
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(offset, 2)?);
+
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(offset, 4)?);
+
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Decodes the 12-byte IFD entry at `offset`.
+    fn entry(&self, offset: usize) -> Option<(u16, Value)> {
+        let tag = self.u16(offset)?;
+        let kind = self.u16(offset + 2)?;
+        let count = self.u32(offset + 4)? as usize;
+
+        let size = match kind {
+            1 | 2 | 7 => 1,
+            3 => 2,
+            4 | 9 => 4,
+            5 | 10 => 8,
+            _ => return None,
+        };
+
+        let len = count.checked_mul(size)?;
+        let start = if len <= 4 {
+            offset + 8
+        } else {
+            self.u32(offset + 8)? as usize
+        };
+
+        // Check the whole value fits before reading any of it.
+        let raw = self.bytes(start, len)?;
+
+        let value = match kind {
+            2 => {
+                let text = raw.split(|&b| b == 0).next().unwrap_or(&[]);
+                Value::Ascii(String::from_utf8_lossy(text).trim().to_owned())
+            }
+            3 => Value::Short((0..count).filter_map(|i| self.u16(start + i * 2)).collect()),
+            4 => Value::Long((0..count).filter_map(|i| self.u32(start + i * 4)).collect()),
+            5 => Value::Rational(
+                (0..count)
+                    .filter_map(|i| Some((self.u32(start + i * 8)?, self.u32(start + i * 8 + 4)?)))
+                    .collect(),
+            ),
+            9 => Value::Long((0..count).filter_map(|i| self.u32(start + i * 4)).collect()),
+            10 => Value::SRational(
+                (0..count)
+                    .filter_map(|i| {
+                        Some((
+                            self.u32(start + i * 8)? as i32,
+                            self.u32(start + i * 8 + 4)? as i32,
+                        ))
+                    })
+                    .collect(),
+            ),
+            _ => Value::Bytes(raw.to_vec()),
+        };
+
+        Some((tag, value))
+    }
+}
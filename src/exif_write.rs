@@ -0,0 +1,316 @@
+use chrono::{DateTime, Local, Utc};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::exif::{self, Exif, Ifd};
+
+const APP1: u8 = 0xe1;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+/// The contents of the JPEG at `path` with `time` recorded as its EXIF
+/// `DateTimeOriginal`, in local time along with its offset.
+///
+/// Returns `None` for files that are not JPEGs or already have a
+/// `DateTimeOriginal`, which are left as they are.
+pub fn with_date_time_original(path: &Path, time: DateTime<Utc>) -> io::Result<Option<Vec<u8>>> {
+    if let Some(exif) = Exif::read(path)? {
+        if exif
+            .ascii(Ifd::Exif, exif::TAG_DATE_TIME_ORIGINAL)
+            .is_some()
+        {
+            return Ok(None);
+        }
+    }
+
+    let jpeg = fs::read(path)?;
+
+    if !jpeg.starts_with(&[0xff, 0xd8]) {
+        return Ok(None);
+    }
+
+    let local = time.with_timezone(&Local);
+    let date_time = local.format("%Y:%m:%d %H:%M:%S").to_string();
+    let offset = local.format("%:z").to_string();
+    let tags = [
+        (exif::TAG_DATE_TIME_ORIGINAL, date_time.as_str()),
+        (exif::TAG_OFFSET_TIME_ORIGINAL, offset.as_str()),
+    ];
+
+    let (start, end, tiff) = match find_exif(&jpeg) {
+        Some((start, end)) => (start, end, add_tags(&jpeg[start + 10..end], &tags)),
+        None => {
+            let at = after_jfif(&jpeg);
+            (at, at, Some(new_tiff(&tags)))
+        }
+    };
+
+    let tiff = match tiff {
+        Some(tiff) => tiff,
+        None => return Err(invalid("malformed EXIF block")),
+    };
+
+    let len = 2 + EXIF_HEADER.len() + tiff.len();
+
+    if len > usize::from(u16::MAX) {
+        return Err(invalid("EXIF block too large"));
+    }
+
+    let mut updated = Vec::with_capacity(jpeg.len() + len);
+    updated.extend_from_slice(&jpeg[..start]);
+    updated.extend_from_slice(&[0xff, APP1]);
+    updated.extend_from_slice(&(len as u16).to_be_bytes());
+    updated.extend_from_slice(EXIF_HEADER);
+    updated.extend_from_slice(&tiff);
+    updated.extend_from_slice(&jpeg[end..]);
+
+    Ok(Some(updated))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Where a new EXIF segment goes: after the JFIF APP0 segment that has to
+/// come first, if there is one.
+fn after_jfif(jpeg: &[u8]) -> usize {
+    match jpeg.get(2..6) {
+        Some(&[0xff, 0xe0, high, low]) => 4 + usize::from(u16::from_be_bytes([high, low])),
+        _ => 2,
+    }
+}
+
+/// Start and end of the EXIF APP1 segment, marker included.
+fn find_exif(jpeg: &[u8]) -> Option<(usize, usize)> {
+    let mut pos = 2;
+
+    loop {
+        while *jpeg.get(pos)? == 0xff && *jpeg.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+
+        if *jpeg.get(pos)? != 0xff {
+            return None;
+        }
+
+        let marker = *jpeg.get(pos + 1)?;
+
+        if marker == 0xda || marker == 0xd9 {
+            return None;
+        }
+
+        if (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+            pos += 2;
+            continue;
+        }
+
+        let len = usize::from(u16::from_be_bytes([
+            *jpeg.get(pos + 2)?,
+            *jpeg.get(pos + 3)?,
+        ]));
+        let end = pos + 2 + len;
+
+        if marker == APP1 && jpeg.get(pos + 4..end)?.starts_with(EXIF_HEADER) {
+            return Some((pos, end));
+        }
+
+        pos = end;
+    }
+}
+
+/// Byte order of a TIFF structure.
+#[derive(Clone, Copy)]
+struct Order {
+    big_endian: bool,
+}
+
+impl Order {
+    fn u16(self, data: &[u8], offset: usize) -> Option<u16> {
+        let bytes = [*data.get(offset)?, *data.get(offset + 1)?];
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(data.get(offset..offset + 4)?);
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn put_u16(self, out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    fn put_u32(self, out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+}
+
+/// The 12-byte entries of the IFD at `offset`, and the offset of the
+/// next IFD.
+fn read_ifd(data: &[u8], order: Order, offset: usize) -> Option<(Vec<[u8; 12]>, u32)> {
+    let count = usize::from(order.u16(data, offset)?);
+    let mut entries = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let start = offset + 2 + i * 12;
+        let mut entry = [0; 12];
+        entry.copy_from_slice(data.get(start..start + 12)?);
+        entries.push(entry);
+    }
+
+    let next = order.u32(data, offset + 2 + count * 12)?;
+    Some((entries, next))
+}
+
+/// Appends an IFD with `entries` and ASCII `tags` to `out`, returning
+/// its offset. Entries are kept sorted by tag, as TIFF requires.
+fn append_ifd(
+    out: &mut Vec<u8>,
+    order: Order,
+    mut entries: Vec<[u8; 12]>,
+    tags: &[(u16, &str)],
+    next: u32,
+) -> u32 {
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+
+    let offset = out.len();
+    let count = entries.len() + tags.len();
+    let mut data_offset = offset + 2 + count * 12 + 4;
+    let mut data = Vec::new();
+
+    for &(tag, text) in tags {
+        let mut entry = Vec::with_capacity(12);
+        let bytes = text.len() + 1;
+
+        order.put_u16(&mut entry, tag);
+        order.put_u16(&mut entry, TYPE_ASCII);
+        order.put_u32(&mut entry, bytes as u32);
+
+        if bytes <= 4 {
+            let mut inline = text.as_bytes().to_vec();
+            inline.resize(4, 0);
+            entry.extend_from_slice(&inline);
+        } else {
+            order.put_u32(&mut entry, data_offset as u32);
+            data.extend_from_slice(text.as_bytes());
+            data.push(0);
+
+            if bytes % 2 == 1 {
+                data.push(0);
+            }
+
+            data_offset = offset + 2 + count * 12 + 4 + data.len();
+        }
+
+        let mut raw = [0; 12];
+        raw.copy_from_slice(&entry);
+        entries.push(raw);
+    }
+
+    entries.sort_by_key(|entry| order.u16(entry, 0));
+
+    order.put_u16(out, count as u16);
+    for entry in &entries {
+        out.extend_from_slice(entry);
+    }
+    order.put_u32(out, next);
+    out.extend_from_slice(&data);
+
+    offset as u32
+}
+
+/// Adds `tags` to the EXIF IFD of `tiff`.
+///
+/// The new IFDs are appended, so every offset into the existing data,
+/// maker notes included, stays valid.
+fn add_tags(tiff: &[u8], tags: &[(u16, &str)]) -> Option<Vec<u8>> {
+    let order = match tiff.get(0..2)? {
+        b"II" => Order { big_endian: false },
+        b"MM" => Order { big_endian: true },
+        _ => return None,
+    };
+
+    let ifd0_offset = order.u32(tiff, 4)? as usize;
+    let (ifd0, ifd0_next) = read_ifd(tiff, order, ifd0_offset)?;
+    let pointer = ifd0
+        .iter()
+        .position(|entry| order.u16(entry, 0) == Some(exif::TAG_EXIF_IFD));
+
+    let (exif_entries, exif_next) = match pointer {
+        Some(i) => read_ifd(tiff, order, order.u32(&ifd0[i], 8)? as usize)?,
+        None => (Vec::new(), 0),
+    };
+
+    let mut out = tiff.to_vec();
+    let exif_offset = append_ifd(&mut out, order, exif_entries, tags, exif_next);
+
+    let mut offset_bytes = Vec::with_capacity(4);
+    order.put_u32(&mut offset_bytes, exif_offset);
+
+    match pointer {
+        Some(i) => {
+            let at = ifd0_offset + 2 + i * 12 + 8;
+            out[at..at + 4].copy_from_slice(&offset_bytes);
+        }
+        None => {
+            let mut entry = Vec::with_capacity(12);
+            order.put_u16(&mut entry, exif::TAG_EXIF_IFD);
+            order.put_u16(&mut entry, TYPE_LONG);
+            order.put_u32(&mut entry, 1);
+            entry.extend_from_slice(&offset_bytes);
+
+            let mut raw = [0; 12];
+            raw.copy_from_slice(&entry);
+            let mut entries = ifd0;
+            entries.push(raw);
+
+            let ifd0_offset = append_ifd(&mut out, order, entries, &[], ifd0_next);
+            let mut header = Vec::with_capacity(4);
+            order.put_u32(&mut header, ifd0_offset);
+            out[4..8].copy_from_slice(&header);
+        }
+    }
+
+    Some(out)
+}
+
+/// A TIFF structure holding nothing but `tags` in its EXIF IFD.
+fn new_tiff(tags: &[(u16, &str)]) -> Vec<u8> {
+    let order = Order { big_endian: false };
+    let mut out = b"II".to_vec();
+
+    order.put_u16(&mut out, 42);
+    order.put_u32(&mut out, 8);
+
+    let mut ifd0 = Vec::new();
+    order.put_u16(&mut ifd0, 1);
+    order.put_u16(&mut ifd0, exif::TAG_EXIF_IFD);
+    order.put_u16(&mut ifd0, TYPE_LONG);
+    order.put_u32(&mut ifd0, 1);
+    order.put_u32(&mut ifd0, 8 + 18);
+    order.put_u32(&mut ifd0, 0);
+    out.extend_from_slice(&ifd0);
+
+    append_ifd(&mut out, order, Vec::new(), tags, 0);
+    out
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, TimeZone, Utc};
+use std::path::Path;
+
+/// Filesystem families whose timestamps need special treatment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsKind {
+    Fat,
+    ExFat,
+    Ntfs,
+    Other,
+}
+
+impl FsKind {
+    /// Detects the filesystem holding `dir`, falling back to `Other` when
+    /// it cannot be told.
+    pub fn detect(dir: &Path) -> FsKind {
+        detect(dir).unwrap_or(FsKind::Other)
+    }
+
+    /// Like `detect`, for the closest of `dir` and its parents that exists
+    /// yet, for directories a run is still to create.
+    pub fn detect_nearest(dir: &Path) -> FsKind {
+        dir.ancestors()
+            .map(|dir| match dir.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => dir,
+            })
+            .find(|dir| dir.is_dir())
+            .map_or(FsKind::Other, FsKind::detect)
+    }
+
+    /// Brings `time` to the precision the filesystem actually keeps.
+    ///
+    /// FAT and exFAT store timestamps in 2-second steps, and depending on
+    /// the driver the extra precision comes and goes between mounts, so
+    /// these times are floored to an even second to keep names stable.
+    pub fn normalize(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            FsKind::Fat | FsKind::ExFat => {
+                let secs = time.timestamp();
+                Utc.timestamp_opt(secs - secs.rem_euclid(2), 0)
+                    .single()
+                    .unwrap_or(time)
+            }
+            FsKind::Ntfs | FsKind::Other => time,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn detect(dir: &Path) -> Option<FsKind> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    const MSDOS_SUPER_MAGIC: i64 = 0x4d44;
+    const EXFAT_SUPER_MAGIC: i64 = 0x2011_bab0;
+    // Both the old `ntfs` driver and `ntfs3`; NTFS-3G shows as FUSE.
+    const NTFS_SB_MAGIC: i64 = 0x5346_544e;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut buf: libc::statfs = unsafe { mem::zeroed() };
+
+    if unsafe { libc::statfs(c_dir.as_ptr(), &mut buf) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    let kind = match buf.f_type as i64 {
+        MSDOS_SUPER_MAGIC => FsKind::Fat,
+        EXFAT_SUPER_MAGIC => FsKind::ExFat,
+        NTFS_SB_MAGIC => FsKind::Ntfs,
+        _ => FsKind::Other,
+    };
+
+    Some(kind)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn detect(dir: &Path) -> Option<FsKind> {
+    use std::ffi::{CStr, CString};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut buf: libc::statfs = unsafe { mem::zeroed() };
+
+    if unsafe { libc::statfs(c_dir.as_ptr(), &mut buf) } != 0 {
+        return None;
+    }
+
+    let name = unsafe { CStr::from_ptr(buf.f_fstypename.as_ptr()) };
+
+    let kind = match name.to_bytes() {
+        b"msdos" => FsKind::Fat,
+        b"exfat" => FsKind::ExFat,
+        b"ntfs" => FsKind::Ntfs,
+        _ => FsKind::Other,
+    };
+
+    Some(kind)
+}
+
+#[cfg(windows)]
+fn detect(dir: &Path) -> Option<FsKind> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = [0u16; 261];
+
+    if unsafe { GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) } == 0 {
+        return None;
+    }
+
+    let mut name = [0u16; 261];
+    let ok = unsafe {
+        GetVolumeInformationW(
+            root.as_ptr(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            name.as_mut_ptr(),
+            name.len() as u32,
+        )
+    };
+
+    if ok == 0 {
+        return None;
+    }
+
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+    let kind = match String::from_utf16_lossy(&name[..len]).as_str() {
+        "FAT" | "FAT32" => FsKind::Fat,
+        "exFAT" => FsKind::ExFat,
+        "NTFS" => FsKind::Ntfs,
+        _ => FsKind::Other,
+    };
+
+    Some(kind)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    windows
+)))]
+fn detect(_dir: &Path) -> Option<FsKind> {
+    None
+}
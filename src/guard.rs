@@ -0,0 +1,52 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// System directories no one means to batch-rename.
+#[cfg(unix)]
+const SYSTEM_DIRS: &[&str] = &[
+    "/", "/bin", "/boot", "/dev", "/etc", "/lib", "/proc", "/sbin", "/sys", "/usr", "/var",
+];
+
+#[cfg(windows)]
+const SYSTEM_DIRS: &[&str] = &["C:\\", "C:\\Windows", "C:\\Program Files", "C:\\Users"];
+
+#[cfg(not(any(unix, windows)))]
+const SYSTEM_DIRS: &[&str] = &[];
+
+/// Directories crtime refuses to work in: system directories, the home
+/// directory itself and any in `extra`.
+pub fn protected_dirs(extra: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        dirs.push(PathBuf::from(home));
+    }
+
+    dirs.extend(extra.iter().cloned());
+    dirs
+}
+
+/// Fails if `dir` is one of `protected`.
+///
+/// Only the directories themselves are protected, not their subdirectories.
+pub fn check(dir: &Path, protected: &[PathBuf]) -> io::Result<()> {
+    let dir = dir.canonicalize()?;
+
+    let is_protected = protected
+        .iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .any(|path| path == dir);
+
+    if is_protected {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to work in protected directory {} (pass --i-know-what-im-doing to override)",
+                dir.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::RenamePlanItem;
+
+/// What to do with several paths of the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HardlinkPolicy {
+    /// Rename only the first path, by name, and skip the others.
+    #[default]
+    First,
+    /// Rename every path.
+    All,
+}
+
+impl FromStr for HardlinkPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(HardlinkPolicy::First),
+            "all" => Ok(HardlinkPolicy::All),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Indices of items that are paths of the same file, in groups of two or
+/// more sorted by path.
+pub fn groups(items: &[RenamePlanItem]) -> Vec<Vec<usize>> {
+    let mut by_inode: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if let Some(inode) = item.stamp.inode {
+            by_inode.entry(inode).or_default().push(i);
+        }
+    }
+
+    let mut groups: Vec<_> = by_inode
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_by(|&a, &b| items[a].path.cmp(&items[b].path));
+            group
+        })
+        .collect();
+
+    groups.sort();
+    groups
+}
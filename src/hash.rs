@@ -0,0 +1,21 @@
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::blake3;
+
+/// SHA-256 digest of the file at `path`.
+pub fn sha256_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finalize().to_vec())
+}
+
+/// BLAKE3 digest of the file at `path`.
+pub fn blake3_file(path: &Path) -> io::Result<Vec<u8>> {
+    Ok(blake3::hash_reader(File::open(path)?)?.to_vec())
+}
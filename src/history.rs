@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::event::SCHEMA_VERSION;
+use crate::journal::{self, Journal, Op, Run, RunKind};
+use crate::json;
+use crate::retry::RetryPolicy;
+use crate::throttle;
+use crate::transfer::{self, TransferMode};
+use crate::ApplyOptions;
+
+/// The names one file had through the runs of a journal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lineage {
+    /// Each name with the run that gave it, starting with the one the file
+    /// had before any run.
+    pub names: Vec<(PathBuf, Option<u64>)>,
+    /// The run that removed the file, a copy undone.
+    pub removed: Option<u64>,
+}
+
+impl Lineage {
+    pub fn current(&self) -> &Path {
+        &self.names[self.names.len() - 1].0
+    }
+}
+
+/// The lineages of all files `runs` moved, copied or removed, oldest
+/// first. A copy starts a lineage of its own, with the names the original
+/// had before.
+pub fn lineages(runs: &[Run]) -> Vec<Lineage> {
+    let mut lineages: Vec<Lineage> = Vec::new();
+    let mut current: HashMap<PathBuf, usize> = HashMap::new();
+
+    for run in runs {
+        for op in &run.ops {
+            let start = |path: &Path| Lineage {
+                names: vec![(path.to_path_buf(), None)],
+                removed: None,
+            };
+
+            match op {
+                Op::Move { from, to } => {
+                    let index = current.remove(from).unwrap_or_else(|| {
+                        lineages.push(start(from));
+                        lineages.len() - 1
+                    });
+
+                    lineages[index].names.push((to.clone(), Some(run.id)));
+                    current.insert(to.clone(), index);
+                }
+                Op::Copy { from, to } => {
+                    let mut copy = match current.get(from) {
+                        Some(&index) => lineages[index].clone(),
+                        None => start(from),
+                    };
+
+                    copy.names.push((to.clone(), Some(run.id)));
+                    lineages.push(copy);
+                    current.insert(to.clone(), lineages.len() - 1);
+                }
+                Op::Remove { path } => {
+                    if let Some(index) = current.remove(path) {
+                        lineages[index].removed = Some(run.id);
+                    }
+                }
+            }
+        }
+    }
+
+    lineages
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any number of
+/// characters and `?` for one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // Where to go on from after the last `*`, trying it on one more
+    // character each time the rest fails.
+    let mut retry: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                retry = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match retry {
+                Some((star, from)) => {
+                    retry = Some((star, from + 1));
+                    p = star + 1;
+                    n = from + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The runs recorded in a journal arranged as undo and redo stacks.
+#[derive(Debug, Default)]
+pub struct History {
+    runs: Vec<Run>,
+    /// Runs in effect, most recent last.
+    done: Vec<u64>,
+    /// Runs undone and not redone yet, most recently undone last.
+    undone: Vec<u64>,
+    /// Operations done toward undoing or redoing each run by runs that
+    /// stopped short of it, which leave the run where it was.
+    partial: HashMap<u64, Vec<Op>>,
+}
+
+impl History {
+    /// Replays the journal in `dir`.
+    ///
+    /// Like in an editor, a new rename run forgets everything undone
+    /// before it. A run is only undone or redone once all its operations
+    /// are, over however many runs it takes.
+    pub fn load(dir: &Path) -> io::Result<History> {
+        let runs = journal::runs(dir)?;
+        let mut done = Vec::new();
+        let mut undone: Vec<u64> = Vec::new();
+        let mut partial: HashMap<u64, Vec<Op>> = HashMap::new();
+
+        for run in &runs {
+            let (target, needed) = match run.kind {
+                RunKind::Rename if run.ops.is_empty() => continue,
+                RunKind::Rename => {
+                    done.push(run.id);
+                    undone.clear();
+                    continue;
+                }
+                RunKind::Undo(target) | RunKind::Redo(target) => {
+                    match runs.iter().find(|run| run.id == target) {
+                        Some(target) if matches!(run.kind, RunKind::Undo(_)) => {
+                            (target.id, undo_ops(target))
+                        }
+                        Some(target) => (target.id, target.ops.clone()),
+                        None => continue,
+                    }
+                }
+            };
+
+            let performed = partial.entry(target).or_default();
+            performed.extend(run.ops.iter().cloned());
+
+            if !needed.iter().all(|op| performed.contains(op)) {
+                continue;
+            }
+
+            partial.remove(&target);
+
+            if let RunKind::Undo(_) = run.kind {
+                done.retain(|&id| id != target);
+                undone.push(target);
+            } else {
+                undone.retain(|&id| id != target);
+                done.push(target);
+            }
+        }
+
+        Ok(History {
+            runs,
+            done,
+            undone,
+            partial,
+        })
+    }
+
+    /// All recorded runs, oldest first, whether in effect or not.
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+
+    fn run(&self, id: u64) -> Option<&Run> {
+        self.runs.iter().find(|run| run.id == id)
+    }
+
+    /// The run `crtime undo` would undo.
+    pub fn undoable(&self) -> Option<&Run> {
+        self.done.last().and_then(|&id| self.run(id))
+    }
+
+    /// The run `crtime redo` would do again.
+    pub fn redoable(&self) -> Option<&Run> {
+        self.undone.last().and_then(|&id| self.run(id))
+    }
+
+    /// The operations undoing `run` that are left to do.
+    pub fn ops_to_undo(&self, run: &Run) -> Vec<Op> {
+        self.left(run, undo_ops(run))
+    }
+
+    /// The operations redoing `run` that are left to do.
+    pub fn ops_to_redo(&self, run: &Run) -> Vec<Op> {
+        self.left(run, run.ops.clone())
+    }
+
+    fn left(&self, run: &Run, ops: Vec<Op>) -> Vec<Op> {
+        match self.partial.get(&run.id) {
+            Some(performed) => ops
+                .into_iter()
+                .filter(|op| !performed.contains(op))
+                .collect(),
+            None => ops,
+        }
+    }
+}
+
+/// Formats for `crtime history export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Name of a run's kind and the id of the run it targets, if any.
+fn kind_fields(kind: RunKind) -> (&'static str, Option<u64>) {
+    match kind {
+        RunKind::Rename => ("rename", None),
+        RunKind::Undo(target) => ("undo", Some(target)),
+        RunKind::Redo(target) => ("redo", Some(target)),
+    }
+}
+
+/// Name of an operation and the paths it involves.
+fn op_fields(op: &Op) -> (&'static str, &Path, Option<&Path>) {
+    match op {
+        Op::Move { from, to } => ("move", from, Some(to)),
+        Op::Copy { from, to } => ("copy", from, Some(to)),
+        Op::Remove { path } => ("remove", path, None),
+    }
+}
+
+/// `runs` as a JSON array of runs, each with its operations.
+pub fn to_json(runs: &[&Run]) -> String {
+    let runs: Vec<_> = runs
+        .iter()
+        .map(|run| {
+            let (kind, target) = kind_fields(run.kind);
+            let ops: Vec<_> = run
+                .ops
+                .iter()
+                .map(|op| {
+                    let (name, from, to) = op_fields(op);
+                    let to = to.map_or("null".to_owned(), |to| {
+                        json::string(&to.display().to_string())
+                    });
+                    format!(
+                        r#"{{"op":{},"from":{},"to":{}}}"#,
+                        json::string(name),
+                        json::string(&from.display().to_string()),
+                        to
+                    )
+                })
+                .collect();
+
+            format!(
+                r#"{{"schema_version":{},"id":{},"kind":{},"target":{},"started":{},"ops":[{}]}}"#,
+                SCHEMA_VERSION,
+                run.id,
+                json::string(kind),
+                target.map_or("null".to_owned(), |target| target.to_string()),
+                run.started
+                    .map_or("null".to_owned(), |time| json::string(&time.to_rfc3339())),
+                ops.join(",")
+            )
+        })
+        .collect();
+
+    format!("[{}]\n", runs.join(","))
+}
+
+/// `runs` as CSV with one row per operation.
+pub fn to_csv(runs: &[&Run]) -> String {
+    let mut out = "run,kind,target,started,op,from,to\n".to_owned();
+
+    for run in runs {
+        let (kind, target) = kind_fields(run.kind);
+        let target = target.map_or(String::new(), |target| target.to_string());
+        let started = run.started.map_or(String::new(), |time| time.to_rfc3339());
+
+        for op in &run.ops {
+            let (name, from, to) = op_fields(op);
+            let to = to.map_or(String::new(), |to| to.display().to_string());
+
+            out.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                run.id,
+                kind,
+                target,
+                started,
+                name,
+                csv_field(&from.display().to_string()),
+                csv_field(&to)
+            ));
+        }
+    }
+
+    out
+}
+
+/// Quotes a CSV field if it needs it.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// The operations undoing `run`, in the order to perform them.
+pub fn undo_ops(run: &Run) -> Vec<Op> {
+    run.ops.iter().rev().filter_map(Op::inverse).collect()
+}
+
+/// Performs `op`, never replacing an existing file.
+pub fn perform(op: &Op, retry: &RetryPolicy) -> io::Result<()> {
+    throttle::wait();
+
+    let (from, to, mode) = match op {
+        Op::Move { from, to } => (from, to, TransferMode::Move),
+        Op::Copy { from, to } => (from, to, TransferMode::Copy),
+        Op::Remove { path } => return retry.run(|| fs::remove_file(path)),
+    };
+
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+
+    let options = ApplyOptions {
+        retry: *retry,
+        mode,
+        ..ApplyOptions::default()
+    };
+
+    transfer::transfer(from, to, &options).map(drop)
+}
+
+/// Performs `ops` as a run of `kind` recorded in `journal`, stopping at
+/// the first failure. Returns how many were performed, and the failure.
+pub fn replay(
+    ops: &[Op],
+    kind: RunKind,
+    journal: &mut Journal,
+    retry: &RetryPolicy,
+) -> io::Result<(usize, Option<(Op, io::Error)>)> {
+    replay_with(ops, kind, journal, &mut |op| perform(op, retry))
+}
+
+/// Like `replay`, performing each operation with `perform`.
+pub fn replay_with(
+    ops: &[Op],
+    kind: RunKind,
+    journal: &mut Journal,
+    perform: &mut dyn FnMut(&Op) -> io::Result<()>,
+) -> io::Result<(usize, Option<(Op, io::Error)>)> {
+    journal.begin(kind, TransferMode::Move)?;
+
+    let mut done = 0;
+    let mut failure = None;
+
+    for op in ops {
+        match perform(op) {
+            Ok(()) => {
+                journal.record_op(op)?;
+                done += 1;
+            }
+            Err(error) => {
+                failure = Some((op.clone(), error));
+                break;
+            }
+        }
+    }
+
+    journal.flush()?;
+    Ok((done, failure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn step(from: &Path, to: &Path) -> Op {
+        Op::Move {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn undoes_runs_only_once_all_their_ops_are_undone() {
+        let dir = std::env::temp_dir().join(format!("crtime-history-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let [a, b, x, y] = ["a", "b", "x", "y"].map(|name| dir.join(name));
+        let (a_x, b_y) = (step(&a, &x), step(&b, &y));
+
+        let mut journal = Journal::open(&dir).unwrap();
+        let run = journal.begin(RunKind::Rename, TransferMode::Move).unwrap();
+        journal.record_op(&a_x).unwrap();
+        journal.record_op(&b_y).unwrap();
+        journal
+            .begin(RunKind::Undo(run), TransferMode::Move)
+            .unwrap();
+
+        let history = History::load(&dir).unwrap();
+        let undoable = history.undoable().map(|run| run.id);
+        assert_eq!(undoable, Some(run));
+        assert_eq!(history.ops_to_undo(history.undoable().unwrap()).len(), 2);
+
+        journal
+            .begin(RunKind::Undo(run), TransferMode::Move)
+            .unwrap();
+        journal.record_op(&step(&y, &b)).unwrap();
+
+        let history = History::load(&dir).unwrap();
+        let left = history.ops_to_undo(history.undoable().unwrap());
+        assert_eq!(left, vec![step(&x, &a)]);
+
+        journal
+            .begin(RunKind::Undo(run), TransferMode::Move)
+            .unwrap();
+        journal.record_op(&step(&x, &a)).unwrap();
+
+        let history = History::load(&dir).unwrap();
+        assert!(history.undoable().is_none());
+        assert_eq!(history.redoable().map(|run| run.id), Some(run));
+        assert_eq!(
+            history.ops_to_redo(history.redoable().unwrap()),
+            vec![a_x, b_y]
+        );
+
+        drop(journal);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! A copy of the history in an SQLite database, through the system's
+//! `sqlite3`, for questions the journal of a directory cannot answer
+//! easily, like what a file was called years ago and in which directory.
+//!
+//! The journal stays what undo and redo go by. The database gathers the
+//! runs of every directory it was used with, each run once however often
+//! it is brought up to date:
+//!
+//! - `runs` – `dir`, `run`, `kind`, `target`, `started`, one row per run
+//!   in the journal of `dir`;
+//! - `ops` – `dir`, `run`, `seq`, `op`, `from_path`, `to_path`, the file
+//!   operations of each run in the order they happened;
+//! - `items` – `dir`, `run`, `path`, `new_path`, `created`, `source`,
+//!   `outcome`, `reason`, the files a rename run planned and what came of
+//!   each: `renamed`, `rolled_back`, `skipped`, `failed` or `not_reached`.
+//!
+//! Paths of local files are stored absolute, and those of a remote
+//! directory as its URL, in `dir`, and URLs below it. For example, the
+//! original name of a file:
+//!
+//! ```sql
+//! SELECT path FROM items WHERE new_path = '/photos/20240305121000.jpg';
+//! ```
+
+use std::io::{self, Write};
+use std::path::{self, Path};
+use std::process::{Command, Stdio};
+
+use crate::journal::{self, Op, RunKind};
+use crate::{RenameOutcome, RenamePlanItem};
+
+const TABLES: &str = "\
+CREATE TABLE IF NOT EXISTS runs (
+  dir TEXT NOT NULL, run INTEGER NOT NULL, kind TEXT NOT NULL,
+  target INTEGER, started TEXT,
+  PRIMARY KEY (dir, run));
+CREATE TABLE IF NOT EXISTS ops (
+  dir TEXT NOT NULL, run INTEGER NOT NULL, seq INTEGER NOT NULL,
+  op TEXT NOT NULL, from_path TEXT NOT NULL, to_path TEXT,
+  PRIMARY KEY (dir, run, seq));
+CREATE TABLE IF NOT EXISTS items (
+  dir TEXT NOT NULL, run INTEGER NOT NULL, path TEXT NOT NULL,
+  new_path TEXT NOT NULL, created TEXT NOT NULL, source TEXT NOT NULL,
+  outcome TEXT NOT NULL, reason TEXT,
+  PRIMARY KEY (dir, run, path));
+CREATE INDEX IF NOT EXISTS ops_to ON ops (to_path);
+CREATE INDEX IF NOT EXISTS items_new_path ON items (new_path);
+";
+
+/// Adds the runs in the journal kept in `dir` that `db` does not have yet,
+/// and the operations they did since.
+///
+/// The journal is of `dir` itself, whose paths are absolute or, in runs
+/// recorded before they were, taken relative to `dir`, unless it is of
+/// the remote directory at `url`, whose paths are URLs already.
+pub fn sync(db: &Path, dir: &Path, url: Option<&str>) -> io::Result<()> {
+    let runs = journal::runs(dir)?;
+
+    if runs.is_empty() {
+        return Ok(());
+    }
+
+    let base = path::absolute(dir)?;
+    let resolve = |path: &Path| match url {
+        Some(_) => text(&path.to_string_lossy()),
+        None => text(&base.join(path).to_string_lossy()),
+    };
+    let dir = match url {
+        Some(url) => text(url),
+        None => text(&base.to_string_lossy()),
+    };
+    let mut sql = String::new();
+
+    for run in &runs {
+        let (kind, target) = match run.kind {
+            RunKind::Rename => ("rename", None),
+            RunKind::Undo(target) => ("undo", Some(target)),
+            RunKind::Redo(target) => ("redo", Some(target)),
+        };
+
+        sql.push_str(&format!(
+            "INSERT OR IGNORE INTO runs VALUES ({}, {}, {}, {}, {});\n",
+            dir,
+            run.id,
+            text(kind),
+            target.map_or("NULL".to_owned(), |target| target.to_string()),
+            run.started
+                .map_or("NULL".to_owned(), |started| text(&started.to_rfc3339())),
+        ));
+
+        for (seq, op) in run.ops.iter().enumerate() {
+            let (name, from, to) = match op {
+                Op::Move { from, to } => ("move", from, Some(to)),
+                Op::Copy { from, to } => ("copy", from, Some(to)),
+                Op::Remove { path } => ("remove", path, None),
+            };
+
+            sql.push_str(&format!(
+                "INSERT OR IGNORE INTO ops VALUES ({}, {}, {}, {}, {}, {});\n",
+                dir,
+                run.id,
+                seq,
+                text(name),
+                resolve(from),
+                to.map_or("NULL".to_owned(), |to| resolve(to)),
+            ));
+        }
+    }
+
+    execute(db, &sql)
+}
+
+/// Records the files rename run `run` of `dir` planned and what came of
+/// them.
+pub fn record(
+    db: &Path,
+    dir: &Path,
+    run: u64,
+    items: &[RenamePlanItem],
+    outcome: &RenameOutcome,
+) -> io::Result<()> {
+    let dir = absolute(dir)?;
+    let mut sql = String::new();
+
+    for item in items {
+        let is = |other: &&RenamePlanItem| std::ptr::eq(*other, item);
+        let (result, reason) = if outcome.rolled_back.iter().any(is) {
+            ("rolled_back", None)
+        } else if outcome.renamed.iter().any(is) {
+            ("renamed", None)
+        } else if let Some(skip) = outcome.skipped.iter().find(|skip| is(&skip.item)) {
+            ("skipped", Some(skip.reason.to_string()))
+        } else if let Some(failure) = outcome.failed.iter().find(|failure| is(&failure.item)) {
+            ("failed", Some(failure.reason.to_string()))
+        } else {
+            ("not_reached", None)
+        };
+
+        sql.push_str(&format!(
+            "INSERT OR REPLACE INTO items VALUES ({}, {}, {}, {}, {}, {}, {}, {});\n",
+            dir,
+            run,
+            absolute(&item.path)?,
+            absolute(&item.new_path)?,
+            text(&item.created.to_rfc3339()),
+            text(&item.source.to_string()),
+            text(result),
+            reason.map_or("NULL".to_owned(), |reason| text(&reason)),
+        ));
+    }
+
+    execute(db, &sql)
+}
+
+/// Runs `sql` in one transaction, creating the tables first as needed.
+fn execute(db: &Path, sql: &str) -> io::Result<()> {
+    let mut child = match Command::new("sqlite3")
+        .arg("-bail")
+        .arg(db)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "--history-db needs the sqlite3 command",
+            ))
+        }
+        Err(error) => return Err(error),
+    };
+
+    let script = format!("BEGIN;\n{}{}COMMIT;\n", TABLES, sql);
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(script.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "Cannot update {}: {}",
+            db.display(),
+            message.trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// `path` made absolute, as an SQL string.
+fn absolute(path: &Path) -> io::Result<String> {
+    Ok(text(&path::absolute(path)?.to_string_lossy()))
+}
+
+/// `value` as an SQL string.
+fn text(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
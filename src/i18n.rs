@@ -0,0 +1,367 @@
+//! Translations of what crtime says to people, in the language of their
+//! locale.
+//!
+//! Messages are looked up by their English text, as with gettext, so they
+//! read in the code as they always did and stay English where there is no
+//! translation. `{}` marks where [`fill`] puts the arguments, which a
+//! translation takes in the same order. Output meant for programs, like
+//! `--output ndjson`, is never translated.
+//!
+//! To add a language, add a [`Language`] to `LANGUAGES`; to add a message,
+//! wrap it in [`tr`] and add its translations where they are known.
+
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Wording for one language.
+pub struct Language {
+    /// ISO 639-1 code, as locales start with.
+    pub code: &'static str,
+    /// The question asked before renaming, with the default answer.
+    pub question: &'static str,
+    pub yes: &'static [&'static str],
+    pub no: &'static [&'static str],
+    /// English messages and their translations.
+    messages: &'static [(&'static str, &'static str)],
+}
+
+pub const ENGLISH: &Language = &LANGUAGES[0];
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        code: "en",
+        question: "Proceed? [y/N] ",
+        yes: &["y", "yes"],
+        no: &["n", "no"],
+        messages: &[],
+    },
+    Language {
+        code: "cs",
+        question: "Pokračovat? [a/N] ",
+        yes: &["a", "ano"],
+        no: &["n", "ne"],
+        messages: &[
+            ("Directory: {}", "Složka: {}"),
+            ("Destination: {}", "Cíl: {}"),
+            ("Files: {} ({})", "Soubory: {} ({})"),
+            ("Rename", "Přejmenovat"),
+            ("Copy", "Kopírovat"),
+            ("Skip", "Přeskočit"),
+            ("file", "soubor"),
+            ("files", "souborů"),
+            (
+                "Warning: same file renamed as {}",
+                "Pozor: stejný soubor přejmenován jako {}",
+            ),
+            ("Renamed items:", "Přejmenované soubory:"),
+            ("Skipped:", "Přeskočené:"),
+            ("Replaced files backed up:", "Nahrazené soubory zálohovány:"),
+            ("Failed:", "Neúspěšné:"),
+            ("Permission denied:", "Přístup odepřen:"),
+            (
+                "Fix the permissions of these files or their directories, then retry them.",
+                "Opravte oprávnění těchto souborů nebo jejich složek a zkuste je znovu.",
+            ),
+            ("Retry them? [y/N] ", "Zkusit znovu? [a/N] "),
+            (
+                "Interrupted after {} of {} files.",
+                "Přerušeno po {} z {} souborů.",
+            ),
+            (
+                "Rolled back {} renames after the failure.",
+                "Po chybě vráceno přejmenování: {}.",
+            ),
+            (
+                "Stopped at the first failure after {} of {} files.",
+                "Zastaveno u první chyby po {} z {} souborů.",
+            ),
+            (
+                "{} scanned, {} renamed, {} skipped, {} failed",
+                "prohledáno {}, přejmenováno {}, přeskočeno {}, selhalo {}",
+            ),
+            ("  {} skipped: {}", "  přeskočeno {}: {}"),
+            ("{} unchanged", "beze změny {}"),
+            ("{} copied", "zkopírováno {}"),
+            ("Done in {} s", "Hotovo za {} s"),
+            ("Renaming cancelled.", "Přejmenování zrušeno."),
+            ("{}: {} is already there.", "{}: {} už existuje."),
+            (
+                "{}: {} is planned for another file too.",
+                "{}: {} je naplánováno i pro jiný soubor.",
+            ),
+            (
+                "[o]verwrite, [s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[o] přepsat, [s] přeskočit, [a] přidat číslo, [n] nový název? ",
+            ),
+            (
+                "[s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[s] přeskočit, [a] přidat číslo, [n] nový název? ",
+            ),
+            ("New name: ", "Nový název: "),
+        ],
+    },
+    Language {
+        code: "de",
+        question: "Fortfahren? [j/N] ",
+        yes: &["j", "ja"],
+        no: &["n", "nein"],
+        messages: &[
+            ("Directory: {}", "Verzeichnis: {}"),
+            ("Destination: {}", "Ziel: {}"),
+            ("Files: {} ({})", "Dateien: {} ({})"),
+            ("Rename", "Umbenennen"),
+            ("Copy", "Kopieren"),
+            ("Skip", "Überspringen"),
+            ("file", "Datei"),
+            ("files", "Dateien"),
+            (
+                "Warning: same file renamed as {}",
+                "Warnung: dieselbe Datei umbenannt als {}",
+            ),
+            ("Renamed items:", "Umbenannte Dateien:"),
+            ("Skipped:", "Übersprungen:"),
+            ("Replaced files backed up:", "Ersetzte Dateien gesichert:"),
+            ("Failed:", "Fehlgeschlagen:"),
+            ("Permission denied:", "Zugriff verweigert:"),
+            (
+                "Fix the permissions of these files or their directories, then retry them.",
+                "Berechtigungen dieser Dateien oder ihrer Verzeichnisse korrigieren und erneut versuchen.",
+            ),
+            ("Retry them? [y/N] ", "Erneut versuchen? [j/N] "),
+            (
+                "Interrupted after {} of {} files.",
+                "Unterbrochen nach {} von {} Dateien.",
+            ),
+            (
+                "Rolled back {} renames after the failure.",
+                "{} Umbenennungen nach dem Fehler zurückgenommen.",
+            ),
+            (
+                "Stopped at the first failure after {} of {} files.",
+                "Beim ersten Fehler nach {} von {} Dateien angehalten.",
+            ),
+            (
+                "{} scanned, {} renamed, {} skipped, {} failed",
+                "{} durchsucht, {} umbenannt, {} übersprungen, {} fehlgeschlagen",
+            ),
+            ("  {} skipped: {}", "  {} übersprungen: {}"),
+            ("{} unchanged", "{} unverändert"),
+            ("{} copied", "{} kopiert"),
+            ("Done in {} s", "Fertig in {} s"),
+            ("Renaming cancelled.", "Umbenennen abgebrochen."),
+            ("{}: {} is already there.", "{}: {} ist schon vorhanden."),
+            (
+                "{}: {} is planned for another file too.",
+                "{}: {} ist auch für eine andere Datei vorgesehen.",
+            ),
+            (
+                "[o]verwrite, [s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[o] überschreiben, [s] überspringen, [a] Nummer anhängen, [n] neuer Name? ",
+            ),
+            (
+                "[s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[s] überspringen, [a] Nummer anhängen, [n] neuer Name? ",
+            ),
+            ("New name: ", "Neuer Name: "),
+        ],
+    },
+    Language {
+        code: "fr",
+        question: "Continuer ? [o/N] ",
+        yes: &["o", "oui"],
+        no: &["n", "non"],
+        messages: &[
+            ("Directory: {}", "Dossier : {}"),
+            ("Destination: {}", "Destination : {}"),
+            ("Files: {} ({})", "Fichiers : {} ({})"),
+            ("Rename", "Renommer"),
+            ("Copy", "Copier"),
+            ("Skip", "Ignorer"),
+            ("file", "fichier"),
+            ("files", "fichiers"),
+            (
+                "Warning: same file renamed as {}",
+                "Attention : même fichier renommé en {}",
+            ),
+            ("Renamed items:", "Fichiers renommés :"),
+            ("Skipped:", "Ignorés :"),
+            (
+                "Replaced files backed up:",
+                "Fichiers remplacés sauvegardés :",
+            ),
+            ("Failed:", "Échecs :"),
+            ("Permission denied:", "Permission refusée :"),
+            (
+                "Fix the permissions of these files or their directories, then retry them.",
+                "Corrigez les permissions de ces fichiers ou de leurs dossiers, puis réessayez.",
+            ),
+            ("Retry them? [y/N] ", "Réessayer ? [o/N] "),
+            (
+                "Interrupted after {} of {} files.",
+                "Interrompu après {} fichiers sur {}.",
+            ),
+            (
+                "Rolled back {} renames after the failure.",
+                "{} renommages annulés après l’échec.",
+            ),
+            (
+                "Stopped at the first failure after {} of {} files.",
+                "Arrêté au premier échec après {} fichiers sur {}.",
+            ),
+            (
+                "{} scanned, {} renamed, {} skipped, {} failed",
+                "{} analysés, {} renommés, {} ignorés, {} en échec",
+            ),
+            ("  {} skipped: {}", "  {} ignorés : {}"),
+            ("{} unchanged", "{} inchangés"),
+            ("{} copied", "{} copiés"),
+            ("Done in {} s", "Terminé en {} s"),
+            ("Renaming cancelled.", "Renommage annulé."),
+            ("{}: {} is already there.", "{} : {} existe déjà."),
+            (
+                "{}: {} is planned for another file too.",
+                "{} : {} est aussi prévu pour un autre fichier.",
+            ),
+            (
+                "[o]verwrite, [s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[o] écraser, [s] ignorer, [a] ajouter un numéro, [n] nouveau nom ? ",
+            ),
+            (
+                "[s]kip, [a]dd a number or enter a [n]ew name? ",
+                "[s] ignorer, [a] ajouter un numéro, [n] nouveau nom ? ",
+            ),
+            ("New name: ", "Nouveau nom : "),
+        ],
+    },
+    Language {
+        code: "es",
+        question: "¿Continuar? [s/N] ",
+        yes: &["s", "si", "sí"],
+        no: &["n", "no"],
+        messages: &[
+            ("Directory: {}", "Carpeta: {}"),
+            ("Destination: {}", "Destino: {}"),
+            ("Files: {} ({})", "Archivos: {} ({})"),
+            ("Rename", "Renombrar"),
+            ("Copy", "Copiar"),
+            ("Skip", "Omitir"),
+            ("file", "archivo"),
+            ("files", "archivos"),
+            (
+                "Warning: same file renamed as {}",
+                "Aviso: el mismo archivo renombrado como {}",
+            ),
+            ("Renamed items:", "Archivos renombrados:"),
+            ("Skipped:", "Omitidos:"),
+            (
+                "Replaced files backed up:",
+                "Archivos reemplazados respaldados:",
+            ),
+            ("Failed:", "Fallidos:"),
+            ("Permission denied:", "Permiso denegado:"),
+            (
+                "Fix the permissions of these files or their directories, then retry them.",
+                "Corrija los permisos de estos archivos o de sus carpetas y vuelva a intentarlo.",
+            ),
+            ("Retry them? [y/N] ", "¿Reintentar? [s/N] "),
+            (
+                "Interrupted after {} of {} files.",
+                "Interrumpido tras {} de {} archivos.",
+            ),
+            (
+                "Rolled back {} renames after the failure.",
+                "Se deshicieron {} renombrados tras el fallo.",
+            ),
+            (
+                "Stopped at the first failure after {} of {} files.",
+                "Detenido en el primer fallo tras {} de {} archivos.",
+            ),
+            (
+                "{} scanned, {} renamed, {} skipped, {} failed",
+                "{} examinados, {} renombrados, {} omitidos, {} fallidos",
+            ),
+            ("  {} skipped: {}", "  {} omitidos: {}"),
+            ("{} unchanged", "{} sin cambios"),
+            ("{} copied", "{} copiados"),
+            ("Done in {} s", "Hecho en {} s"),
+            ("Renaming cancelled.", "Renombrado cancelado."),
+            ("{}: {} is already there.", "{}: {} ya existe."),
+            (
+                "{}: {} is planned for another file too.",
+                "{}: {} también está previsto para otro archivo.",
+            ),
+            (
+                "[o]verwrite, [s]kip, [a]dd a number or enter a [n]ew name? ",
+                "¿[o] sobrescribir, [s] omitir, [a] añadir un número o [n] nombre nuevo? ",
+            ),
+            (
+                "[s]kip, [a]dd a number or enter a [n]ew name? ",
+                "¿[s] omitir, [a] añadir un número o [n] nombre nuevo? ",
+            ),
+            ("New name: ", "Nombre nuevo: "),
+        ],
+    },
+];
+
+/// Language of the user's locale, e.g. `cs` for `cs_CZ.UTF-8`, the first
+/// set of `LC_ALL`, `LC_MESSAGES` and `LANG`.
+fn locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .chars()
+                .take_while(char::is_ascii_alphabetic)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The language to speak, English unless the locale names one there are
+/// translations for.
+pub fn language() -> &'static Language {
+    static LANGUAGE: OnceLock<&'static Language> = OnceLock::new();
+
+    LANGUAGE.get_or_init(|| {
+        let code = locale();
+
+        LANGUAGES
+            .iter()
+            .find(|language| language.code == code)
+            .unwrap_or(ENGLISH)
+    })
+}
+
+/// `english` in the user's language.
+pub fn tr(english: &'static str) -> &'static str {
+    language()
+        .messages
+        .iter()
+        .find(|(message, _)| *message == english)
+        .map_or(english, |(_, translated)| translated)
+}
+
+/// `english` in the user's language with each `{}` replaced by the next of
+/// `args`.
+pub fn fill(english: &'static str, args: &[&dyn fmt::Display]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut parts = tr(english).split("{}");
+
+    if let Some(first) = parts.next() {
+        out.push_str(first);
+    }
+
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+
+        out.push_str(part);
+    }
+
+    out
+}
@@ -0,0 +1,473 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+
+use crate::transfer::TransferMode;
+use crate::RenamePlanItem;
+
+/// File in the target directory recording completed renames.
+pub const JOURNAL_NAME: &str = ".crtime-journal";
+
+/// First line of a journal, naming the version of its format.
+///
+/// Version 1 is a list of tab-separated records, one per line:
+///
+/// - `run <id> <kind> <target> <time>` starts a run, where `kind` is
+///   `rename`, `undo` or `redo`, `target` the id of the run undone or
+///   redone (`-` for renames) and `time` when the run started, in RFC 3339;
+/// - `move <from> <to>`, `copy <from> <to>` and `remove <path>` record a
+///   file operation of the latest run.
+///
+/// Tabs, newlines and backslashes in paths are escaped as `\t`, `\n` and
+/// `\\`. Paths of local files are absolute, while those recorded before
+/// they were are relative to where crtime ran. Records of unknown types are
+/// ignored, and journals without a header hold `<from> <to>` moves of a
+/// single run.
+pub const HEADER: &str = "# crtime-journal 1";
+
+/// What a run did.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunKind {
+    Rename,
+    /// Undid the run with the given id.
+    Undo(u64),
+    /// Did the run with the given id again after it was undone.
+    Redo(u64),
+}
+
+/// A file operation recorded in the journal.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Removal of a copy made earlier.
+    Remove {
+        path: PathBuf,
+    },
+}
+
+impl Op {
+    /// The operation reverting this one. Removals cannot be reverted.
+    pub fn inverse(&self) -> Option<Op> {
+        match self {
+            Op::Move { from, to } => Some(Op::Move {
+                from: to.clone(),
+                to: from.clone(),
+            }),
+            Op::Copy { to, .. } => Some(Op::Remove { path: to.clone() }),
+            Op::Remove { .. } => None,
+        }
+    }
+
+    /// The operation with its paths made absolute.
+    pub fn absolute(&self) -> io::Result<Op> {
+        Ok(match self {
+            Op::Move { from, to } => Op::Move {
+                from: std::path::absolute(from)?,
+                to: std::path::absolute(to)?,
+            },
+            Op::Copy { from, to } => Op::Copy {
+                from: std::path::absolute(from)?,
+                to: std::path::absolute(to)?,
+            },
+            Op::Remove { path } => Op::Remove {
+                path: std::path::absolute(path)?,
+            },
+        })
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Move { from, to } => write!(f, "move\t{}\t{}", escape(from), escape(to)),
+            Op::Copy { from, to } => write!(f, "copy\t{}\t{}", escape(from), escape(to)),
+            Op::Remove { path } => write!(f, "remove\t{}", escape(path)),
+        }
+    }
+}
+
+/// A run as recorded in the journal.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub id: u64,
+    pub kind: RunKind,
+    pub started: Option<DateTime<Utc>>,
+    pub ops: Vec<Op>,
+}
+
+/// Append-only record of completed renames, used to undo a run.
+///
+/// Every rename is written out as soon as it is recorded, so the journal
+/// stays complete even if the run is interrupted.
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+    next_id: u64,
+    /// The run being recorded, and the mode its renames use.
+    current: Option<(u64, TransferMode)>,
+    /// Whether paths are of local files, recorded absolute so the journal
+    /// can be replayed from any directory.
+    local: bool,
+}
+
+impl Journal {
+    /// Opens the journal in `dir` of renames of local files.
+    pub fn open(dir: &Path) -> io::Result<Journal> {
+        Journal::open_in(dir, true)
+    }
+
+    /// Opens the journal in `dir` of renames in a remote directory, whose
+    /// paths are recorded as they are.
+    pub fn open_remote(dir: &Path) -> io::Result<Journal> {
+        Journal::open_in(dir, false)
+    }
+
+    fn open_in(dir: &Path, local: bool) -> io::Result<Journal> {
+        let path = dir.join(JOURNAL_NAME);
+        let next_id = match read_runs(&path) {
+            Ok(runs) => runs.last().map_or(1, |run| run.id + 1),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => 1,
+            Err(error) => return Err(error),
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", HEADER)?;
+        }
+
+        Ok(Journal {
+            file,
+            next_id,
+            current: None,
+            local,
+        })
+    }
+
+    /// Starts recording a new run, returning its id.
+    pub fn begin(&mut self, kind: RunKind, mode: TransferMode) -> io::Result<u64> {
+        let id = self.next_id;
+        let (kind, target) = match kind {
+            RunKind::Rename => ("rename", "-".to_owned()),
+            RunKind::Undo(target) => ("undo", target.to_string()),
+            RunKind::Redo(target) => ("redo", target.to_string()),
+        };
+        let started = DateTime::<Utc>::from(SystemTime::now()).to_rfc3339();
+
+        writeln!(self.file, "run\t{}\t{}\t{}\t{}", id, kind, target, started)?;
+        self.next_id += 1;
+        self.current = Some((id, mode));
+
+        Ok(id)
+    }
+
+    /// Id of the run being recorded.
+    pub fn current_run(&self) -> Option<u64> {
+        self.current.map(|(id, _)| id)
+    }
+
+    /// Records the rename of `item` in the current run, starting a run of
+    /// moves if none was started.
+    pub fn record(&mut self, item: &RenamePlanItem) -> io::Result<()> {
+        let mode = match self.current {
+            Some((_, mode)) => mode,
+            None => {
+                self.begin(RunKind::Rename, TransferMode::Move)?;
+                TransferMode::Move
+            }
+        };
+
+        self.record_op(&item_op(item, mode))
+    }
+
+    pub fn record_op(&mut self, op: &Op) -> io::Result<()> {
+        match self.local {
+            true => writeln!(self.file, "{}", op.absolute()?),
+            false => writeln!(self.file, "{}", op),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Current size of the journal, marking where the next entry goes.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+}
+
+/// The operation renaming `item` in `mode`.
+pub fn item_op(item: &RenamePlanItem, mode: TransferMode) -> Op {
+    let (from, to) = (item.path.clone(), item.new_path.clone());
+
+    match mode {
+        TransferMode::Move => Op::Move { from, to },
+        TransferMode::Copy => Op::Copy { from, to },
+    }
+}
+
+/// Runs recorded in the journal in `dir`, oldest first.
+pub fn runs(dir: &Path) -> io::Result<Vec<Run>> {
+    match read_runs(&dir.join(JOURNAL_NAME)) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+/// File operations recorded in the journal in `dir` from byte `offset`
+/// on, in the order they happened.
+pub fn read(dir: &Path, offset: u64) -> io::Result<Vec<Op>> {
+    let mut file = match File::open(dir.join(JOURNAL_NAME)) {
+        Ok(file) => file,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut ops = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        if let Some(Record::Op(op)) = parse(&line?) {
+            ops.push(op);
+        }
+    }
+
+    Ok(ops)
+}
+
+enum Record {
+    Run(Run),
+    Op(Op),
+}
+
+fn read_runs(path: &Path) -> io::Result<Vec<Run>> {
+    let mut runs: Vec<Run> = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        match parse(&line?) {
+            Some(Record::Run(run)) => runs.push(run),
+            Some(Record::Op(op)) => match runs.last_mut() {
+                Some(run) => run.ops.push(op),
+                // Journals from before the header recorded a single run.
+                None => runs.push(Run {
+                    id: 0,
+                    kind: RunKind::Rename,
+                    started: None,
+                    ops: vec![op],
+                }),
+            },
+            None => {}
+        }
+    }
+
+    Ok(runs)
+}
+
+fn parse(line: &str) -> Option<Record> {
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let fields: Vec<_> = line.split('\t').collect();
+
+    Some(match fields[..] {
+        ["run", id, kind, target, started] => {
+            let kind = match (kind, target.parse()) {
+                ("rename", _) => RunKind::Rename,
+                ("undo", Ok(target)) => RunKind::Undo(target),
+                ("redo", Ok(target)) => RunKind::Redo(target),
+                _ => return None,
+            };
+
+            Record::Run(Run {
+                id: id.parse().ok()?,
+                kind,
+                started: DateTime::parse_from_rfc3339(started)
+                    .ok()
+                    .map(|time| time.with_timezone(&Utc)),
+                ops: Vec::new(),
+            })
+        }
+        ["move", from, to] => Record::Op(Op::Move {
+            from: unescape(from)?,
+            to: unescape(to)?,
+        }),
+        ["copy", from, to] => Record::Op(Op::Copy {
+            from: unescape(from)?,
+            to: unescape(to)?,
+        }),
+        ["remove", path] => Record::Op(Op::Remove {
+            path: unescape(path)?,
+        }),
+        [from, to] => Record::Op(Op::Move {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        }),
+        _ => return None,
+    })
+}
+
+pub(crate) fn escape(path: &Path) -> String {
+    let mut out = String::new();
+
+    for c in path.display().to_string().chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub(crate) fn unescape(field: &str) -> Option<PathBuf> {
+    let mut out = String::new();
+    let mut chars = field.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            't' => out.push('\t'),
+            'n' => out.push('\n'),
+            _ => return None,
+        }
+    }
+
+    Some(PathBuf::from(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_op(line: &str) -> Option<Op> {
+        match parse(line)? {
+            Record::Op(op) => Some(op),
+            Record::Run(_) => None,
+        }
+    }
+
+    #[test]
+    fn escapes_and_unescapes_paths() {
+        for path in [
+            "plain.jpg",
+            "a\tb.jpg",
+            "line\nbreak",
+            "back\\slash",
+            "\\t literally",
+        ] {
+            let escaped = escape(Path::new(path));
+
+            assert!(!escaped.contains(['\t', '\n']));
+            assert_eq!(unescape(&escaped), Some(PathBuf::from(path)));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_escapes() {
+        assert_eq!(unescape("a\\xb"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+
+    #[test]
+    fn round_trips_ops() {
+        let ops = [
+            Op::Move {
+                from: PathBuf::from("/photos/a\tb.jpg"),
+                to: PathBuf::from("/photos/20240305121000 a\tb.jpg"),
+            },
+            Op::Copy {
+                from: PathBuf::from("/card/new\nline.jpg"),
+                to: PathBuf::from("/photos/x.jpg"),
+            },
+            Op::Remove {
+                path: PathBuf::from("C:\\photos\\x.jpg"),
+            },
+        ];
+
+        for op in ops {
+            assert_eq!(parse_op(&op.to_string()), Some(op));
+        }
+    }
+
+    #[test]
+    fn parses_runs() {
+        let run = match parse("run\t3\tundo\t2\t2024-03-05T12:10:00+00:00") {
+            Some(Record::Run(run)) => run,
+            _ => panic!("not a run"),
+        };
+
+        assert_eq!(run.id, 3);
+        assert_eq!(run.kind, RunKind::Undo(2));
+        assert!(run.started.is_some());
+
+        assert!(matches!(
+            parse("run\t1\trename\t-\t-"),
+            Some(Record::Run(Run {
+                kind: RunKind::Rename,
+                started: None,
+                ..
+            }))
+        ));
+        assert!(parse("run\t1\tundo\t-\t-").is_none());
+    }
+
+    #[test]
+    fn reads_headerless_moves_and_ignores_the_rest() {
+        assert_eq!(
+            parse_op("a.jpg\tb.jpg"),
+            Some(Op::Move {
+                from: PathBuf::from("a.jpg"),
+                to: PathBuf::from("b.jpg"),
+            })
+        );
+        assert!(parse(HEADER).is_none());
+        assert!(parse("future\ta\tb\tc").is_none());
+    }
+
+    #[test]
+    fn records_local_paths_absolute() {
+        let dir = std::env::temp_dir().join(format!("crtime-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let op = Op::Move {
+            from: PathBuf::from("a.jpg"),
+            to: PathBuf::from("b.jpg"),
+        };
+
+        let mut journal = Journal::open(&dir).unwrap();
+        journal.begin(RunKind::Rename, TransferMode::Move).unwrap();
+        journal.record_op(&op).unwrap();
+        let mut remote = Journal::open_remote(&dir).unwrap();
+        remote.record_op(&op).unwrap();
+
+        let ops = read(&dir, 0).unwrap();
+        assert_eq!(ops, vec![op.absolute().unwrap(), op]);
+        assert!(matches!(&ops[0], Op::Move { from, .. } if from.is_absolute()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,51 +1,322 @@
-use chrono::{DateTime, Utc};
-use std::error::Error;
-use std::fs;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::DirEntry;
 use std::io;
-use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
 
-#[derive(Debug)]
-pub struct Config<'a> {
-    pub dir: &'a Path,
-}
+pub mod appledouble;
+pub mod audio_tags;
+pub mod backend;
+pub mod backup;
+mod blake3;
+mod btime;
+pub mod cache;
+mod cli;
+pub mod clock;
+pub mod color;
+pub mod config_file;
+pub mod content;
+mod curl;
+pub mod dedup;
+pub mod dimensions;
+pub mod doctor;
+pub mod dst;
+pub mod event;
+pub mod exif;
+#[cfg(feature = "exif-write")]
+pub mod exif_write;
+pub mod fs_kind;
+pub mod guard;
+pub mod hardlink;
+mod hash;
+pub mod history;
+pub mod history_db;
+mod i18n;
+pub mod interrupt;
+pub mod journal;
+mod json;
+pub mod lock;
+pub mod manifest;
+pub mod mapping;
+mod mtp;
+pub mod names;
+pub mod offset;
+pub mod organize;
+mod pager;
+mod pick;
+pub mod plan_file;
+pub mod prefix;
+pub mod profile;
+mod prompt;
+pub mod protect;
+mod reflink;
+pub mod retry;
+mod s3;
+pub mod schema;
+mod sftp;
+pub mod source;
+pub mod space;
+mod sparse;
+pub mod stamp;
+pub mod stats;
+pub mod summary;
+pub mod template;
+pub mod throttle;
+#[cfg(feature = "gps-timezone")]
+pub mod timezone;
+pub mod transfer;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub mod verify;
+mod webdav;
+mod xml;
 
-impl<'a> Config<'a> {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 2 {
-            return Err("Not enough arguments");
-        }
+pub use cli::{run, Config};
 
-        let dir = &args[1];
+use appledouble::AppleDoublePolicy;
+use backup::Backup;
+use cache::TimeCache;
+use clock::ClockSync;
+use content::ContentType;
+use event::{Event, Observer, Progress, Totals};
+use fs_kind::FsKind;
+use hardlink::HardlinkPolicy;
+use journal::{Journal, Op, RunKind};
+use names::{NamePolicy, NameProblem};
+use offset::Offset;
+use organize::Organize;
+use profile::Timings;
+use protect::Protection;
+use retry::RetryPolicy;
+use source::{ReadOptions, Source, SourceChain};
+use stamp::{FileStamp, StaleReason};
+use template::Template;
+use transfer::TransferMode;
 
-        Ok(Config {
-            dir: &Path::new(dir),
-        })
-    }
+/// Prefix of files crtime keeps in the target directory for itself.
+const INTERNAL_PREFIX: &str = ".crtime";
+
+/// Extensions of files other programs are still writing.
+const PARTIAL_EXTENSIONS: &[&str] = &["part", "crdownload", "tmp"];
+
+/// Settings controlling how a plan is built.
+#[derive(Debug, Clone, Default)]
+pub struct PlanOptions<'a> {
+    /// Directory receiving the renamed files, instead of their own one.
+    pub dest: Option<&'a Path>,
+    /// Descend into subdirectories.
+    pub recursive: bool,
+    /// Put files from subdirectories straight into `dest`, or into the
+    /// planned directory itself, instead of keeping them in their own
+    /// subdirectories.
+    pub flatten: bool,
+    /// Where to read each file's creation time from.
+    pub sources: SourceChain,
+    /// Only rename files with this kind of contents.
+    pub content_type: Option<ContentType>,
+    /// Only rename files with an extension usual for one of these kinds,
+    /// unless empty. Unlike `content_type`, nothing is read to tell.
+    pub kinds: Vec<ContentType>,
+    /// Only rename files created at least this long ago.
+    pub older_than: Option<Duration>,
+    /// Only rename files created less than this long ago.
+    pub newer_than: Option<Duration>,
+    /// Rename files that look like they are still being written, such as
+    /// `*.part` downloads.
+    pub include_partial: bool,
+    /// Leave out files smaller than this many bytes, like the empty files
+    /// failed downloads leave.
+    pub min_size: u64,
+    /// Plan read-only and immutable files too, instead of listing them as
+    /// skipped. Moving them takes `ApplyOptions::fix_perms`.
+    pub include_protected: bool,
+    /// How to treat files reachable through several hard links.
+    pub hardlinks: HardlinkPolicy,
+    /// Clock corrections measured from reference files.
+    pub clock: ClockSync,
+    /// Shift applied to every creation time before naming.
+    pub offset: Offset,
+    /// Name photos with a GPS position in the local time of that place
+    /// rather than in UTC. Needs the `gps-timezone` feature.
+    pub gps_timezone: bool,
+    /// Pattern the new names are made from.
+    pub template: Template,
+    /// Sort files into subdirectories of the target directory by time.
+    pub organize: Option<Organize>,
+    /// Day `{day_num}` counts from.
+    pub day_zero: Option<NaiveDate>,
+    /// Number `{uniq}` and `{orig_index}` count from, 1 unless set.
+    pub counter_start: Option<usize>,
+    /// Fewest digits `{uniq}` and `{orig_index}` are padded to, beyond
+    /// those of the largest number.
+    pub counter_width: usize,
+    /// What to do with new names the target filesystem does not allow.
+    pub invalid_names: NamePolicy,
+    pub appledouble: AppleDoublePolicy,
+    /// Leave new paths that are taken as planned, for `resolve_conflicts`
+    /// to settle, instead of numbering them.
+    pub defer_conflicts: bool,
+    /// Files whose timestamps are read at the same time. `0` and `1` both
+    /// mean one after the other.
+    pub read_jobs: usize,
+    /// Longest a source parsing the contents, like EXIF, may take over one
+    /// file before the next source is tried instead.
+    pub read_timeout: Option<std::time::Duration>,
+    /// EXIF times read on earlier runs, and where those of this one go.
+    pub cache: Option<Arc<TimeCache>>,
 }
 
-#[derive(Debug)]
-struct FsItem {
-    created: DateTime<Utc>,
-    name: String,
-    new_name: String,
-    path: PathBuf,
-    new_path: PathBuf,
+/// What to do when a rename fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Carry on with the remaining files and report all failures at the end.
+    #[default]
+    Continue,
+    /// Stop at the first failure.
+    FailFast,
+    /// Stop at the first failure and undo the renames done before it.
+    Rollback,
+}
+
+/// Settings controlling how a plan is applied.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    pub retry: RetryPolicy,
+    pub mode: TransferMode,
+    pub on_error: ErrorPolicy,
+    /// Compare checksums of copied files before keeping them.
+    pub verify: bool,
+    /// Record the creation time as EXIF `DateTimeOriginal` in JPEGs that
+    /// lack one. Needs the `exif-write` feature.
+    pub write_exif: bool,
+    /// Leave out the `Zone.Identifier` stream Windows marks downloaded
+    /// files with when copying them.
+    pub strip_zone_identifier: bool,
+    /// Files renamed at the same time. `0` and `1` both mean one after the
+    /// other.
+    pub jobs: usize,
+    /// Move files a rename would replace aside first, to where this says.
+    /// Without it they are replaced where `RenamePlanItem::replaces` says
+    /// so, and otherwise the rename fails.
+    pub backup: Option<Backup>,
+    /// Lift the read-only attribute or immutable flag of files while
+    /// moving them, putting it back on afterwards.
+    pub fix_perms: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenamePlanItem {
+    pub created: DateTime<Utc>,
+    /// Where `created` was read from.
+    pub source: Source,
+    pub name: String,
+    pub new_name: String,
+    pub path: PathBuf,
+    pub new_path: PathBuf,
+    pub stamp: FileStamp,
+    /// Replace the file at `new_path`, as settled by
+    /// `Resolution::Overwrite`. Otherwise a file found there when renaming
+    /// is left alone and the rename fails.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub replaces: bool,
 }
 
 #[derive(Debug)]
-enum FsItemError {
+pub enum FsItemError {
     Io(io::Error),
+    /// Another path of the same file, given here, is renamed instead.
+    HardLink(PathBuf),
     ItemIsDir,
+    ItemIsInternal,
+    /// An AppleDouble file, handled along with the file it belongs to.
+    ItemIsAppleDouble,
+    ItemIsPartial,
+    /// Smaller than `PlanOptions::min_size`.
+    ItemIsTooSmall,
+    /// Read-only or immutable, see `PlanOptions::include_protected`.
+    ItemIsProtected(Protection),
+    /// The contents are not of the kind asked for.
+    ItemIsOtherType,
+    /// Created earlier or later than asked for.
+    ItemIsOtherAge,
     NameFailed,
-    ParentFailed,
+    /// The new name is not allowed on the filesystem it goes to, or on
+    /// Windows.
+    InvalidName(NameProblem),
+    NoBirthTime,
+    NoTimestamp(Source),
+    /// Left as it is to settle a conflict over its new name.
+    Conflict,
 }
 
+/// A file left out of the plan, and why.
 #[derive(Debug)]
-struct FsItemRenameError<'a> {
-    item: &'a FsItem,
-    reason: io::Error,
+pub struct SkippedItem {
+    pub path: PathBuf,
+    pub reason: FsItemError,
+}
+
+/// Renames planned for a directory, oldest file first.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub items: Vec<RenamePlanItem>,
+    pub skipped: Vec<SkippedItem>,
+    /// Files already at their new path, which are left alone.
+    pub unchanged: Vec<PathBuf>,
+    pub timings: Timings,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenameFailure<'a> {
+    pub item: &'a RenamePlanItem,
+    /// Serialized as its message.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_display"))]
+    pub reason: io::Error,
+}
+
+/// A planned item left alone because the file changed after planning.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenameSkip<'a> {
+    pub item: &'a RenamePlanItem,
+    pub reason: StaleReason,
+}
+
+/// What came of applying a plan. With the `serde` feature it can be
+/// serialized, but not deserialized, as it borrows the planned items.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenameOutcome<'a> {
+    pub renamed: Vec<&'a RenamePlanItem>,
+    pub skipped: Vec<RenameSkip<'a>>,
+    pub failed: Vec<RenameFailure<'a>>,
+    /// Bytes copied in copy mode or for moves across filesystems.
+    pub bytes_copied: u64,
+    pub interrupted: bool,
+    /// The batch ended at a failure, as `ErrorPolicy::FailFast` and
+    /// `ErrorPolicy::Rollback` ask.
+    pub stopped: bool,
+    /// Renames undone again by `ErrorPolicy::Rollback`. Those that could
+    /// not be undone stay in `renamed`.
+    pub rolled_back: Vec<&'a RenamePlanItem>,
+    /// Files renamed ones replaced, with where `ApplyOptions::backup` put
+    /// them.
+    pub backed_up: Vec<(PathBuf, PathBuf)>,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_display<S: serde::Serializer>(
+    value: &dyn fmt::Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
 }
 
 impl std::convert::From<io::Error> for FsItemError {
@@ -54,21 +325,102 @@ impl std::convert::From<io::Error> for FsItemError {
     }
 }
 
-type ItemResult = Result<FsItem, FsItemError>;
+impl fmt::Display for FsItemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsItemError::Io(error) => write!(f, "{}", error),
+            FsItemError::HardLink(_) => write!(f, "hard link to a file renamed elsewhere"),
+            FsItemError::ItemIsDir => write!(f, "item is a directory"),
+            FsItemError::ItemIsInternal => write!(f, "item is a crtime file"),
+            FsItemError::ItemIsAppleDouble => write!(f, "item is an AppleDouble file"),
+            FsItemError::ItemIsOtherType => write!(f, "item has other contents than asked for"),
+            FsItemError::ItemIsOtherAge => write!(f, "item is older or newer than asked for"),
+            FsItemError::ItemIsPartial => write!(f, "item looks like a partial or temporary file"),
+            FsItemError::ItemIsTooSmall => write!(f, "item is empty or too small"),
+            FsItemError::ItemIsProtected(protection) => write!(f, "item is {}", protection),
+            FsItemError::NameFailed => write!(f, "file name is not valid UTF-8"),
+            FsItemError::InvalidName(problem) => write!(f, "new name {}", problem),
+            FsItemError::NoBirthTime => {
+                write!(f, "filesystem does not record file creation times")
+            }
+            FsItemError::NoTimestamp(source) => write!(f, "no {} timestamp", source),
+            FsItemError::Conflict => write!(f, "new name is taken"),
+        }
+    }
+}
+
+impl FsItemError {
+    /// Stable short code of the kind of problem, for scripts to tell
+    /// problems apart by.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FsItemError::Io(error) => error_code(error),
+            FsItemError::HardLink(_) => "E_HARDLINK",
+            FsItemError::ItemIsDir => "E_DIR",
+            FsItemError::ItemIsInternal => "E_INTERNAL",
+            FsItemError::ItemIsAppleDouble => "E_APPLEDOUBLE",
+            FsItemError::ItemIsPartial => "E_PARTIAL",
+            FsItemError::ItemIsTooSmall => "E_TOO_SMALL",
+            FsItemError::ItemIsProtected(_) => "E_PROTECTED",
+            FsItemError::ItemIsOtherType => "E_OTHER_TYPE",
+            FsItemError::ItemIsOtherAge => "E_OTHER_AGE",
+            FsItemError::NameFailed | FsItemError::InvalidName(_) => "E_BAD_NAME",
+            FsItemError::NoBirthTime => "E_NO_BTIME",
+            FsItemError::NoTimestamp(_) => "E_NO_TIMESTAMP",
+            FsItemError::Conflict => "E_CONFLICT",
+        }
+    }
+}
 
-impl FsItem {
-    pub fn new(entry: io::Result<DirEntry>) -> ItemResult {
-        let entry = entry?;
-        let path = entry.path().to_path_buf();
-        let meta = entry.metadata()?;
-        let created = meta.created()?;
-        let created = DateTime::<Utc>::from(created);
+impl RenameFailure<'_> {
+    /// Stable short code of why the rename failed, see `error_code`.
+    pub fn code(&self) -> &'static str {
+        error_code(&self.reason)
+    }
+}
+
+/// Stable short code of the kind of an I/O error, like `E_PERM` for a lack
+/// of permissions or `E_XDEV` for a move to another filesystem.
+pub fn error_code(error: &io::Error) -> &'static str {
+    match error.kind() {
+        io::ErrorKind::NotFound => "E_NOT_FOUND",
+        io::ErrorKind::PermissionDenied => "E_PERM",
+        io::ErrorKind::AlreadyExists => "E_DEST_EXISTS",
+        io::ErrorKind::CrossesDevices => "E_XDEV",
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => "E_NO_SPACE",
+        io::ErrorKind::ReadOnlyFilesystem => "E_READ_ONLY",
+        io::ErrorKind::InvalidFilename => "E_BAD_NAME",
+        io::ErrorKind::ResourceBusy => "E_BUSY",
+        io::ErrorKind::TimedOut => "E_TIMEOUT",
+        // A copy not matching its original.
+        io::ErrorKind::InvalidData => "E_VERIFY",
+        io::ErrorKind::Unsupported => "E_UNSUPPORTED",
+        _ => "E_IO",
+    }
+}
+
+type ItemResult = Result<RenamePlanItem, FsItemError>;
+
+impl RenamePlanItem {
+    /// Plans the rename of `entry` into `target_dir`.
+    pub fn new(
+        entry: &DirEntry,
+        target_dir: &Path,
+        options: &PlanOptions,
+        fs_kind: FsKind,
+        timings: &mut Timings,
+    ) -> ItemResult {
+        let path = entry.path();
+        let started = Instant::now();
+        let meta = entry.metadata();
+        timings.scanning += started.elapsed();
+        let meta = meta?;
 
         if meta.is_dir() {
             return Err(FsItemError::ItemIsDir);
         }
 
-        let name = match path.iter().last() {
+        let name = match path.file_name() {
             Some(last) => match last.to_str() {
                 Some(name) => name,
                 None => return Err(FsItemError::NameFailed),
@@ -76,89 +428,1055 @@ impl FsItem {
             None => return Err(FsItemError::NameFailed),
         };
 
-        let new_name = format!("{} {}", created.format("%Y%m%d%M%S"), name);
+        if name.starts_with(INTERNAL_PREFIX) || name == manifest::FILE_NAME {
+            return Err(FsItemError::ItemIsInternal);
+        }
+
+        if appledouble::is_companion_name(name) {
+            return Err(FsItemError::ItemIsAppleDouble);
+        }
+
+        if !options.include_partial && is_partial(name) {
+            return Err(FsItemError::ItemIsPartial);
+        }
+
+        if meta.len() < options.min_size {
+            return Err(FsItemError::ItemIsTooSmall);
+        }
+
+        if !options.include_protected {
+            if let Some(protection) = protect::protection(&path, &meta) {
+                return Err(FsItemError::ItemIsProtected(protection));
+            }
+        }
+
+        if !is_of_kind(name, options) {
+            return Err(FsItemError::ItemIsOtherType);
+        }
+
+        if let Some(wanted) = options.content_type {
+            if content::detect(&path)? != Some(wanted) {
+                return Err(FsItemError::ItemIsOtherType);
+            }
+        }
+
+        let mut on_read = |source, time| timings.record_source(source, time);
+        let read = ReadOptions {
+            timeout: options.read_timeout,
+            cache: options.cache.as_deref(),
+        };
+        let (source, created) =
+            options
+                .sources
+                .resolve_within(&path, &meta, fs_kind, &read, &mut on_read)?;
+        let created = options
+            .clock
+            .correct(&path, source, created)
+            .and_then(|created| options.offset.apply(created));
+        let created = match created {
+            Some(created) => created,
+            None => return Err(FsItemError::NoTimestamp(source)),
+        };
+
+        if !is_of_age(created, options) {
+            return Err(FsItemError::ItemIsOtherAge);
+        }
+
+        let new_name = options.template.render(&path, name, created, options)?;
 
-        let new_path = match path.parent() {
-            Some(parent) => parent.join(&new_name),
-            None => return Err(FsItemError::ParentFailed),
+        let new_path = match &options.organize {
+            Some(organize) => target_dir
+                .join(organize.dir(&path, created, options))
+                .join(&new_name),
+            None => target_dir.join(&new_name),
         };
 
-        Ok(FsItem {
+        Ok(RenamePlanItem {
             created,
+            source,
             name: name.to_owned(),
             new_name,
             path,
             new_path,
+            stamp: FileStamp::of(&meta),
+            replaces: false,
         })
     }
 
-    pub fn rename(&self) -> Result<&Self, FsItemRenameError> {
-        match fs::rename(&self.path, &self.new_path) {
-            Ok(()) => Ok(self),
-            Err(error) => Err(FsItemRenameError {
+    /// Moves or copies the file to its new path, returning the number of
+    /// bytes copied.
+    pub fn rename(&self, options: &ApplyOptions) -> Result<u64, RenameFailure<'_>> {
+        let result = if options.fix_perms && options.mode == TransferMode::Move {
+            protect::unprotected(&self.path, &self.new_path, || self.transfer(options))
+        } else {
+            self.transfer(options)
+        };
+
+        match result {
+            Ok(copied) => Ok(copied),
+            Err(error) => Err(RenameFailure {
                 item: self,
                 reason: error,
+            }),
+        }
+    }
+
+    #[cfg(feature = "exif-write")]
+    fn transfer(&self, options: &ApplyOptions) -> io::Result<u64> {
+        if options.write_exif && self.source != Source::Exif {
+            if let Some(contents) = exif_write::with_date_time_original(&self.path, self.created)? {
+                return transfer::transfer_with_contents(
+                    &self.path,
+                    &self.new_path,
+                    &contents,
+                    options,
+                    self.replaces,
+                );
+            }
+        }
+
+        self.move_or_copy(options)
+    }
+
+    #[cfg(not(feature = "exif-write"))]
+    fn transfer(&self, options: &ApplyOptions) -> io::Result<u64> {
+        self.move_or_copy(options)
+    }
+
+    fn move_or_copy(&self, options: &ApplyOptions) -> io::Result<u64> {
+        match self.replaces {
+            true => transfer::transfer_replacing(&self.path, &self.new_path, options),
+            false => transfer::transfer(&self.path, &self.new_path, options),
+        }
+    }
+}
+
+/// Whether `name` looks like a download, editor backup or lock file still
+/// in use.
+fn is_partial(name: &str) -> bool {
+    let extension = Path::new(name).extension().and_then(|ext| ext.to_str());
+
+    name.starts_with(".#")
+        || name.ends_with('~')
+        || extension.is_some_and(|ext| {
+            PARTIAL_EXTENSIONS
+                .iter()
+                .any(|partial| ext.eq_ignore_ascii_case(partial))
+        })
+}
+
+/// Whether `name` has an extension of one of `options.kinds`, if any are
+/// asked for.
+pub(crate) fn is_of_kind(name: &str, options: &PlanOptions) -> bool {
+    options.kinds.is_empty() || options.kinds.iter().any(|kind| kind.has_extension(name))
+}
+
+/// Whether a file created at `created` is as old as `options.older_than`
+/// and `options.newer_than` ask, going by the clock now.
+pub(crate) fn is_of_age(created: DateTime<Utc>, options: &PlanOptions) -> bool {
+    let age = Utc::now().signed_duration_since(created);
+
+    options.older_than.is_none_or(|min| age >= min)
+        && options.newer_than.is_none_or(|max| age < max)
+}
+
+/// `created` as written into new names, in `format`.
+pub(crate) fn name_time(
+    path: &Path,
+    created: DateTime<Utc>,
+    options: &PlanOptions,
+    format: &str,
+) -> String {
+    #[cfg(feature = "gps-timezone")]
+    {
+        if options.gps_timezone {
+            if let Some(zone) = timezone::capture_zone(path) {
+                return created.with_timezone(&zone).format(format).to_string();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "gps-timezone"))]
+    let _ = options;
+
+    // With its offset shown, a time can be written in the offset the photo
+    // was taken at without becoming ambiguous.
+    if template::shows_offset(format) {
+        if let Some(offset) = exif::capture_offset(path) {
+            return created.with_timezone(&offset).format(format).to_string();
+        }
+    }
+
+    created.format(format).to_string()
+}
+
+/// Scans `root` and returns the planned renames, oldest file first.
+///
+/// Directories, crtime's own files and files of other kinds or ages than
+/// `options.content_type`, `options.kinds`, `options.older_than` and
+/// `options.newer_than` ask for are passed over silently; every other file that
+/// cannot be planned is listed in `Plan::skipped`. With
+/// `options.recursive`, subdirectories are scanned too, except for the
+/// destination directory. `{uniq}` placeholders are numbered in that
+/// order, and new names that would clash with each other or with files
+/// already present get a number appended.
+pub fn plan(root: &Path, options: &PlanOptions) -> io::Result<Plan> {
+    plan_all(&[root], options)
+}
+
+/// Scans every directory of `roots` and returns one plan for their files,
+/// like `plan` does for one, so that files from different roots are
+/// interleaved by creation time and numbered together.
+pub fn plan_all(roots: &[&Path], options: &PlanOptions) -> io::Result<Plan> {
+    let started = Instant::now();
+    let mut plan = Plan::default();
+    let dest = options.dest.and_then(|dest| dest.canonicalize().ok());
+    let mut dirs: Vec<_> = roots
+        .iter()
+        .rev()
+        .map(|&root| (root, root.to_path_buf(), PathBuf::new()))
+        .collect();
+
+    while let Some((root, dir, rel_dir)) = dirs.pop() {
+        let fs_kind = FsKind::detect(&dir);
+        let target_dir = match options.dest {
+            Some(dest) if options.flatten => dest.to_path_buf(),
+            Some(dest) => dest.join(&rel_dir),
+            None if options.flatten => root.to_path_buf(),
+            None => dir.clone(),
+        };
+        let listed = Instant::now();
+        let entries = dir.read_dir()?.collect::<io::Result<Vec<_>>>()?;
+
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if options.sources.uses(Source::Btime) {
+            btime::prefetch(entries.iter().map(|entry| entry.path()).collect());
+        }
+
+        plan.timings.scanning += listed.elapsed();
+
+        let results = plan_entries(&entries, &target_dir, options, fs_kind, &mut plan.timings);
+
+        for (entry, result) in entries.into_iter().zip(results) {
+            match result {
+                Ok(item) => plan.items.push(item),
+                Err(FsItemError::ItemIsDir) if options.recursive => {
+                    let path = entry.path();
+
+                    if entry
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with(INTERNAL_PREFIX)
+                    {
+                        continue;
+                    }
+
+                    if dest.is_some() && path.canonicalize().ok() == dest {
+                        continue;
+                    }
+
+                    dirs.push((root, path, rel_dir.join(entry.file_name())));
+                }
+                Err(FsItemError::ItemIsDir)
+                | Err(FsItemError::ItemIsInternal)
+                | Err(FsItemError::ItemIsAppleDouble)
+                | Err(FsItemError::ItemIsOtherType)
+                | Err(FsItemError::ItemIsOtherAge) => {}
+                Err(reason) => plan.skipped.push(SkippedItem {
+                    path: entry.path(),
+                    reason,
+                }),
+            }
+        }
+    }
+
+    if options.hardlinks == HardlinkPolicy::First {
+        skip_hardlinks(&mut plan);
+    }
+
+    plan.items
+        .sort_by(|a, b| a.created.partial_cmp(&b.created).unwrap());
+    template::number(&mut plan.items, options);
+    names::check(&mut plan, options);
+
+    if !options.defer_conflicts {
+        resolve_collisions(&mut plan.items);
+    }
+
+    drop_unchanged(&mut plan);
+
+    plan.timings.planning = started
+        .elapsed()
+        .saturating_sub(plan.timings.scanning + plan.timings.extraction());
+
+    Ok(plan)
+}
+
+/// Plans the files of `entries`, in their order, reading their timestamps
+/// on `options.read_jobs` threads taking work from a shared queue, so
+/// slow parsing overlaps with waiting for the disk.
+fn plan_entries(
+    entries: &[DirEntry],
+    target_dir: &Path,
+    options: &PlanOptions,
+    fs_kind: FsKind,
+    timings: &mut Timings,
+) -> Vec<ItemResult> {
+    let plan_one = |entry, timings: &mut Timings| {
+        throttle::wait();
+        RenamePlanItem::new(entry, target_dir, options, fs_kind, timings)
+    };
+
+    if options.read_jobs <= 1 || entries.len() <= 1 {
+        return entries
+            .iter()
+            .map(|entry| plan_one(entry, timings))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<ItemResult>> = entries.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.read_jobs.min(entries.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    let mut timings = Timings::default();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        match entries.get(i) {
+                            Some(entry) => done.push((i, plan_one(entry, &mut timings))),
+                            None => return (done, timings),
+                        }
+                    }
+                })
             })
+            .collect();
+
+        for worker in workers {
+            let (done, worker_timings) = worker.join().unwrap();
+            timings.add(&worker_timings);
+
+            for (i, result) in done {
+                results[i] = Some(result);
+            }
         }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+/// Like `plan_all`, telling `observer` about every file planned or left
+/// out once the plan is complete, say to size a progress bar.
+pub fn plan_all_observed(
+    roots: &[&Path],
+    options: &PlanOptions,
+    observer: &mut dyn Observer,
+) -> io::Result<Plan> {
+    let plan = plan_all(roots, options)?;
+
+    for item in &plan.items {
+        observer.on_planned(item);
+    }
+
+    for skipped in &plan.skipped {
+        observer.on_not_planned(skipped);
     }
+
+    Ok(plan)
 }
 
-fn partition_results<I, T, E>(iter: I) -> (impl Iterator<Item = T>, impl Iterator<Item = E>) where
-    I: Iterator<Item=Result<T, E>>,
-    T: std::fmt::Debug,
-    E: std::fmt::Debug,
-{
-    let (oks, errs): (Vec<_>, Vec<_>) = iter.partition(Result::is_ok);
+/// Plans like `plan_all_observed` on a thread of its own, sending the
+/// progress over the channel returned, which ends with `Progress::Plan`.
+pub fn plan_with_events(
+    roots: Vec<PathBuf>,
+    options: PlanOptions<'static>,
+) -> mpsc::Receiver<Progress> {
+    let (sender, receiver) = mpsc::channel();
 
-    let oks = oks.into_iter().map(Result::unwrap);
-    let errs = errs.into_iter().map(Result::unwrap_err);
+    thread::spawn(move || {
+        let roots: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+        let mut send = |event: Event| {
+            if let Some(progress) = Progress::from_event(&event) {
+                let _ = sender.send(progress);
+            }
+        };
+        let plan = plan_all_observed(&roots, &options, &mut send);
+        let _ = sender.send(Progress::Plan(plan));
+    });
 
-    (oks, errs)
+    receiver
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    println!("Directory: {}", config.dir.display());
+/// Applies `items` like `apply` on a thread of its own, sending the
+/// progress over the channel returned, which ends with `Progress::Done`.
+pub fn apply_with_events(
+    items: Vec<RenamePlanItem>,
+    mut journal: Option<Journal>,
+    options: ApplyOptions,
+) -> mpsc::Receiver<Progress> {
+    let (sender, receiver) = mpsc::channel();
 
-    let dir = config.dir.read_dir()?;
-    let mut items: Vec<_> = dir.map(FsItem::new).filter_map(Result::ok).collect();
+    thread::spawn(move || {
+        let mut send = |event: Event| {
+            if let Some(progress) = Progress::from_event(&event) {
+                let _ = sender.send(progress);
+            }
+        };
+        let totals = apply(&items, journal.as_mut(), &options, &mut send)
+            .map(|outcome| Totals::new(&outcome));
+        let _ = sender.send(Progress::Done(totals));
+    });
 
-    items.sort_by(|a, b| a.created.partial_cmp(&b.created).unwrap());
+    receiver
+}
 
-    for item in &items {
-        println!("Rename: {} -> {}", item.name, item.new_name);
+/// Keeps only the first path of each file with several hard links in the
+/// plan and lists the others as skipped.
+fn skip_hardlinks(plan: &mut Plan) {
+    let mut extra = Vec::new();
+
+    for group in hardlink::groups(&plan.items) {
+        let kept = plan.items[group[0]].path.clone();
+        extra.extend(group[1..].iter().map(|&i| (i, kept.clone())));
     }
 
-    let stdin = io::stdin();
+    extra.sort_by_key(|&(i, _)| std::cmp::Reverse(i));
+
+    for (i, kept) in extra {
+        let item = plan.items.remove(i);
+        plan.skipped.push(SkippedItem {
+            path: item.path,
+            reason: FsItemError::HardLink(kept),
+        });
+    }
+}
+
+/// Gives every item a new path of its own.
+///
+/// Older files keep their names; a later file whose new path is already
+/// taken, either by an earlier item or by a file already there, gets
+/// ` (2)`, ` (3)`, … inserted before its extension.
+pub(crate) fn resolve_collisions(items: &mut [RenamePlanItem]) {
+    resolve_collisions_by(items, &mut |path| path.exists());
+}
+
+/// Like `resolve_collisions`, asking `exists` whether a file is already
+/// at a path, for directories other than local ones.
+pub(crate) fn resolve_collisions_by(
+    items: &mut [RenamePlanItem],
+    exists: &mut dyn FnMut(&Path) -> bool,
+) {
+    let mut taken = HashSet::new();
+
+    for item in items.iter_mut() {
+        if conflict(item, &taken, exists).is_some() {
+            add_number(item, &taken, exists);
+        }
+
+        taken.insert(item.new_path.clone());
+    }
+}
 
-    match stdin.lock().lines().next() {
-        Some(Ok(ref line)) if line == "Y" => {
-            let items = items.iter().map(|item| item.rename());
+/// What a planned new path is already taken by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// A file already there, which is not renamed first.
+    Existing,
+    /// An earlier file of the plan, planned to get the same path.
+    Planned,
+}
 
-            let (oks, errs) = partition_results(items);
+/// How to settle a `Conflict`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replace the file already there, which cannot be undone unless
+    /// `ApplyOptions::backup` moves it aside first. Only settles a
+    /// `Conflict::Existing`.
+    Overwrite,
+    /// Leave the file as it is.
+    Skip,
+    /// Insert ` (2)`, ` (3)`, … before the extension.
+    AddNumber,
+    /// Give it this name instead.
+    Rename(String),
+}
 
-            let oks: Vec<_> = oks.collect();
-            let errs: Vec<_> = errs.collect();
+/// Settles each conflict in a plan built with `PlanOptions::defer_conflicts`
+/// as `choose` decides, going from the oldest file to the newest. Files
+/// renamed to a name that is taken again are asked about again, and
+/// skipped files are listed with `FsItemError::Conflict`.
+pub fn resolve_conflicts(
+    plan: &mut Plan,
+    choose: &mut dyn FnMut(&RenamePlanItem, Conflict) -> Resolution,
+) {
+    let exists = &mut |path: &Path| path.exists();
+    let mut taken = HashSet::new();
+    let mut kept = Vec::new();
 
-            println!("\nRenamed items:");
+    for mut item in std::mem::take(&mut plan.items) {
+        let skipped = loop {
+            let conflict = match conflict(&item, &taken, exists) {
+                Some(conflict) => conflict,
+                None => break false,
+            };
 
-            for item in oks {
-                println!("- {} -> {}", item.name, item.new_name);
+            match choose(&item, conflict) {
+                Resolution::Overwrite if conflict == Conflict::Existing => {
+                    item.replaces = true;
+                    break false;
+                }
+                Resolution::Overwrite | Resolution::AddNumber => {
+                    add_number(&mut item, &taken, exists);
+                    break false;
+                }
+                Resolution::Skip => break true,
+                Resolution::Rename(name) => {
+                    item.new_path = item.new_path.with_file_name(&name);
+                    item.new_name = name;
+                }
             }
+        };
 
-            println!("\nFailed:");
+        if skipped {
+            plan.skipped.push(SkippedItem {
+                path: item.path,
+                reason: FsItemError::Conflict,
+            });
+        } else {
+            taken.insert(item.new_path.clone());
+            kept.push(item);
+        }
+    }
 
-            for err in errs {
-                println!("- {} -> {}: {}",
-                    err.item.name,
-                    err.item.new_name,
-                    err.reason
-                );
+    plan.items = kept;
+    drop_unchanged(plan);
+}
+
+/// Moves the items of `plan` that would be renamed to the path they are
+/// at, once their conflicts are settled, to `Plan::unchanged`.
+pub(crate) fn drop_unchanged(plan: &mut Plan) {
+    let (unchanged, items) = std::mem::take(&mut plan.items)
+        .into_iter()
+        .partition(|item| item.new_path == item.path);
+
+    plan.items = items;
+    plan.unchanged
+        .extend(unchanged.into_iter().map(|item: RenamePlanItem| item.path));
+}
+
+fn conflict(
+    item: &RenamePlanItem,
+    taken: &HashSet<PathBuf>,
+    exists: &mut dyn FnMut(&Path) -> bool,
+) -> Option<Conflict> {
+    if taken.contains(&item.new_path) {
+        Some(Conflict::Planned)
+    } else if item.new_path != item.path && exists(&item.new_path) {
+        Some(Conflict::Existing)
+    } else {
+        None
+    }
+}
+
+/// Inserts the lowest number giving `item` a free new path.
+fn add_number(
+    item: &mut RenamePlanItem,
+    taken: &HashSet<PathBuf>,
+    exists: &mut dyn FnMut(&Path) -> bool,
+) {
+    let name = Path::new(&item.new_name);
+    let stem = name
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(&item.new_name);
+    let extension = name.extension().and_then(|s| s.to_str());
+
+    let (new_name, new_path) = (2..)
+        .map(|n| {
+            let new_name = match extension {
+                Some(extension) => format!("{} ({}).{}", stem, n, extension),
+                None => format!("{} ({})", stem, n),
+            };
+            let new_path = item.new_path.with_file_name(&new_name);
+            (new_name, new_path)
+        })
+        .find(|(_, new_path)| {
+            !taken.contains(new_path) && (*new_path == item.path || !exists(new_path))
+        })
+        .unwrap();
+
+    item.new_name = new_name;
+    item.new_path = new_path;
+}
+
+/// Performs the planned renames, collecting successes and failures.
+///
+/// Files are moved or copied as set by `options.mode`, and transient
+/// failures are retried according to `options.retry`. Files that changed
+/// since planning are skipped. Each
+/// completed rename is recorded in `journal`. The batch stops early,
+/// after the current file, once an interrupt has been requested, and with
+/// `ErrorPolicy::FailFast` at the first failure; the journal keeps every
+/// rename done until then. `ErrorPolicy::Rollback` also undoes them.
+///
+/// With `options.jobs` above one, that many files are renamed at the same
+/// time, see `apply_parallel`.
+///
+/// `observer` is told about every file as soon as it has been handled, and
+/// about the outcome at the end.
+pub fn apply<'a>(
+    items: &'a [RenamePlanItem],
+    mut journal: Option<&mut Journal>,
+    options: &ApplyOptions,
+    observer: &mut dyn Observer,
+) -> io::Result<RenameOutcome<'a>> {
+    let mut outcome = RenameOutcome {
+        renamed: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+        bytes_copied: 0,
+        interrupted: false,
+        stopped: false,
+        rolled_back: Vec::new(),
+        backed_up: Vec::new(),
+    };
+
+    if let Some(journal) = journal.as_mut() {
+        journal.begin(RunKind::Rename, options.mode)?;
+    }
+
+    if options.jobs > 1 {
+        apply_parallel(
+            items,
+            journal.as_deref_mut(),
+            options,
+            observer,
+            &mut outcome,
+        )?;
+    } else {
+        for item in items {
+            if interrupt::requested() {
+                outcome.interrupted = true;
+                break;
+            }
+
+            let handled = handle(item, options);
+
+            if settle(
+                handled,
+                &mut outcome,
+                journal.as_deref_mut(),
+                options,
+                observer,
+            )? {
+                break;
+            }
+        }
+    }
+
+    if outcome.stopped && options.on_error == ErrorPolicy::Rollback {
+        roll_back(&mut outcome, journal.as_deref_mut(), options, observer)?;
+    }
+
+    if let Some(journal) = journal {
+        journal.flush()?;
+    }
+
+    observer.on_done(&outcome);
+
+    Ok(outcome)
+}
+
+/// What became of one planned item.
+enum Handled<'a> {
+    /// Renamed, with the bytes copied and where the file it replaced went.
+    Renamed(&'a RenamePlanItem, u64, Option<PathBuf>),
+    Skipped(RenameSkip<'a>),
+    Failed(RenameFailure<'a>),
+}
+
+/// Renames `item` unless it changed since planning.
+fn handle<'a>(item: &'a RenamePlanItem, options: &ApplyOptions) -> Handled<'a> {
+    throttle::wait();
+
+    if let Err(reason) = item.stamp.check(&item.path) {
+        return Handled::Skipped(RenameSkip { item, reason });
+    }
+
+    let displaced = match &options.backup {
+        Some(backup) if item.new_path != item.path => {
+            match backup.displace(&item.new_path, &item.stamp, &options.retry) {
+                Ok(displaced) => displaced,
+                Err(reason) => return Handled::Failed(RenameFailure { item, reason }),
+            }
+        }
+        _ => None,
+    };
+
+    match item.rename(options) {
+        Ok(copied) => Handled::Renamed(item, copied, displaced),
+        Err(failure) => {
+            if let Some(displaced) = displaced {
+                let _ = backup::restore(&displaced, &item.new_path, &options.retry);
+            }
+
+            Handled::Failed(failure)
+        }
+    }
+}
+
+/// Records `handled` in `outcome` and `journal` and tells `observer`, and
+/// returns whether the batch is to stop here.
+fn settle<'a>(
+    handled: Handled<'a>,
+    outcome: &mut RenameOutcome<'a>,
+    journal: Option<&mut Journal>,
+    options: &ApplyOptions,
+    observer: &mut dyn Observer,
+) -> io::Result<bool> {
+    match handled {
+        Handled::Renamed(item, copied, displaced) => {
+            if let Some(journal) = journal {
+                if let Some(displaced) = &displaced {
+                    journal.record_op(&Op::Move {
+                        from: item.new_path.clone(),
+                        to: displaced.clone(),
+                    })?;
+                }
+
+                journal.record(item)?;
+            }
+
+            if let Some(displaced) = displaced {
+                outcome.backed_up.push((item.new_path.clone(), displaced));
+            }
+            observer.on_renamed(item);
+            outcome.renamed.push(item);
+            outcome.bytes_copied += copied;
+        }
+        Handled::Skipped(skip) => {
+            observer.on_skipped(&skip);
+            outcome.skipped.push(skip);
+        }
+        Handled::Failed(failure) => {
+            observer.on_error(&failure);
+            outcome.failed.push(failure);
+
+            if options.on_error != ErrorPolicy::Continue {
+                outcome.stopped = true;
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Renames `items` on `options.jobs` threads taking work from a shared
+/// queue.
+///
+/// Items touching the same path, as old or new path, form a lane that one
+/// thread works through in plan order, so a file is only moved onto a path
+/// once the file there has moved away. The journal, `outcome` and
+/// `observer` are only touched on the calling thread, in the order the
+/// renames complete.
+fn apply_parallel<'a>(
+    items: &'a [RenamePlanItem],
+    mut journal: Option<&mut Journal>,
+    options: &ApplyOptions,
+    observer: &mut dyn Observer,
+    outcome: &mut RenameOutcome<'a>,
+) -> io::Result<()> {
+    let lanes = lanes(items);
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.min(lanes.len()) {
+            let sender = sender.clone();
+            let (lanes, next, stop) = (&lanes, &next, &stop);
+
+            scope.spawn(move || {
+                while let Some(lane) = lanes.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    for &i in lane {
+                        if stop.load(Ordering::Relaxed) {
+                            return;
+                        }
+
+                        if interrupt::requested() {
+                            let _ = sender.send(None);
+                            return;
+                        }
+
+                        if sender.send(Some(handle(&items[i], options))).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+
+        drop(sender);
+
+        for handled in receiver {
+            let handled = match handled {
+                Some(handled) => handled,
+                None => {
+                    outcome.interrupted = true;
+                    stop.store(true, Ordering::Relaxed);
+                    continue;
+                }
+            };
+
+            // Renames finishing after a stop are still recorded, as they
+            // were done.
+            match settle(handled, outcome, journal.as_deref_mut(), options, observer) {
+                Ok(false) => {}
+                Ok(true) => stop.store(true, Ordering::Relaxed),
+                Err(error) => {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Splits `items` into groups of indices, in plan order, such that no two
+/// groups share a path.
+fn lanes(items: &[RenamePlanItem]) -> Vec<Vec<usize>> {
+    let mut lane_of_path: HashMap<&Path, usize> = HashMap::new();
+    let mut lanes: Vec<Vec<usize>> = Vec::new();
+    // Lanes merged into another, by the index of the one they went into.
+    let mut merged: Vec<usize> = Vec::new();
+
+    let find = |merged: &[usize], mut lane: usize| {
+        while merged[lane] != lane {
+            lane = merged[lane];
+        }
+        lane
+    };
+
+    for (i, item) in items.iter().enumerate() {
+        let paths = [item.path.as_path(), item.new_path.as_path()];
+        let mut found: Vec<usize> = paths
+            .iter()
+            .filter_map(|path| lane_of_path.get(path))
+            .map(|&lane| find(&merged, lane))
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+
+        let lane = match found.split_first() {
+            Some((&first, rest)) => {
+                for &other in rest {
+                    let moved = std::mem::take(&mut lanes[other]);
+                    lanes[first].extend(moved);
+                    merged[other] = first;
+                }
+                lanes[first].sort_unstable();
+                first
             }
+            None => {
+                lanes.push(Vec::new());
+                merged.push(lanes.len() - 1);
+                lanes.len() - 1
+            }
+        };
+
+        lanes[lane].push(i);
+
+        for path in paths {
+            lane_of_path.insert(path, lane);
+        }
+    }
+
+    lanes.retain(|lane| !lane.is_empty());
+    lanes
+}
+
+/// Undoes the renames in `outcome`, newest first, recording them in
+/// `journal` as a run undoing the current one. Renames that cannot be
+/// undone are left in `outcome.renamed`.
+fn roll_back<'a>(
+    outcome: &mut RenameOutcome<'a>,
+    mut journal: Option<&mut Journal>,
+    options: &ApplyOptions,
+    observer: &mut dyn Observer,
+) -> io::Result<()> {
+    if let Some(journal) = journal.as_mut() {
+        if let Some(run) = journal.current_run() {
+            journal.begin(RunKind::Undo(run), options.mode)?;
+        }
+    }
+
+    let mut kept = Vec::new();
+
+    while let Some(item) = outcome.renamed.pop() {
+        let undo = journal::item_op(item, options.mode)
+            .inverse()
+            .expect("renames can be undone");
+
+        match history::perform(&undo, &options.retry) {
+            Ok(()) => {
+                if let Some(journal) = journal.as_mut() {
+                    journal.record_op(&undo)?;
+                }
+
+                let replaced = outcome
+                    .backed_up
+                    .iter()
+                    .position(|(path, _)| *path == item.new_path);
 
-            println!("Ok");
+                // The file replaced goes back too, or stays listed where
+                // it was put.
+                if let Some(index) = replaced {
+                    let (path, displaced) = outcome.backed_up[index].clone();
+                    let restore = Op::Move {
+                        from: displaced,
+                        to: path,
+                    };
+
+                    if history::perform(&restore, &options.retry).is_ok() {
+                        if let Some(journal) = journal.as_mut() {
+                            journal.record_op(&restore)?;
+                        }
+                        outcome.backed_up.remove(index);
+                    }
+                }
+                observer.on_rolled_back(item);
+                outcome.rolled_back.push(item);
+            }
+            Err(_) => kept.push(item),
         }
-        _ => println!("Renaming cancelled."),
     }
 
+    kept.reverse();
+    outcome.renamed = kept;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, new_path: &str) -> RenamePlanItem {
+        RenamePlanItem {
+            created: DateTime::<Utc>::default(),
+            source: Source::Mtime,
+            name: path.to_owned(),
+            new_name: new_path.to_owned(),
+            path: PathBuf::from(path),
+            new_path: PathBuf::from(new_path),
+            stamp: FileStamp {
+                len: 0,
+                modified: None,
+                inode: None,
+            },
+            replaces: false,
+        }
+    }
+
+    fn lanes_of(renames: &[(&str, &str)]) -> Vec<Vec<usize>> {
+        let items: Vec<_> = renames.iter().map(|&(from, to)| item(from, to)).collect();
+        lanes(&items)
+    }
+
+    #[test]
+    fn keeps_unrelated_renames_apart() {
+        assert_eq!(
+            lanes_of(&[("a", "A"), ("b", "B"), ("c", "C")]),
+            vec![vec![0], vec![1], vec![2]]
+        );
+    }
+
+    #[test]
+    fn puts_chained_renames_together() {
+        assert_eq!(
+            lanes_of(&[("b", "c"), ("a", "b"), ("x", "X")]),
+            vec![vec![0, 1], vec![2]]
+        );
+    }
+
+    #[test]
+    fn puts_renames_to_the_same_path_together() {
+        assert_eq!(lanes_of(&[("a", "n"), ("b", "n")]), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn merges_lanes_a_rename_joins() {
+        let lanes = lanes_of(&[("a", "x"), ("b", "y"), ("d", "D"), ("y", "x"), ("e", "E")]);
+
+        assert_eq!(lanes, vec![vec![0, 1, 3], vec![2], vec![4]]);
+    }
+
+    #[test]
+    fn keeps_cycles_in_one_lane() {
+        assert_eq!(
+            lanes_of(&[("a", "tmp"), ("b", "a"), ("tmp", "b")]),
+            vec![vec![0, 1, 2]]
+        );
+    }
+
+    #[test]
+    fn shares_no_path_between_lanes() {
+        let renames = [
+            ("1", "2"),
+            ("3", "4"),
+            ("5", "6"),
+            ("4", "7"),
+            ("8", "1"),
+            ("6", "9"),
+            ("10", "11"),
+            ("9", "3"),
+        ];
+        let lanes = lanes_of(&renames);
+        let mut seen: Vec<usize> = lanes.concat();
+        seen.sort_unstable();
+
+        assert_eq!(seen, (0..renames.len()).collect::<Vec<_>>());
+
+        for (i, lane) in lanes.iter().enumerate() {
+            assert!(lane.windows(2).all(|pair| pair[0] < pair[1]));
+
+            for other in &lanes[i + 1..] {
+                for &a in lane {
+                    for &b in other {
+                        let (a, b) = (renames[a], renames[b]);
+                        assert!(a.0 != b.0 && a.0 != b.1 && a.1 != b.0 && a.1 != b.1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_out_files_already_at_their_new_path() {
+        let mut plan = Plan {
+            items: vec![item("a", "A"), item("b", "b")],
+            ..Plan::default()
+        };
+
+        drop_unchanged(&mut plan);
+
+        assert_eq!(plan.items.len(), 1);
+        assert_eq!(plan.items[0].path, PathBuf::from("a"));
+        assert_eq!(plan.unchanged, vec![PathBuf::from("b")]);
+    }
+
+    #[test]
+    fn numbers_files_apart_from_unchanged_ones() {
+        let mut items = vec![item("b", "n"), item("n", "n")];
+        resolve_collisions_by(&mut items, &mut |path| path == Path::new("n"));
+
+        assert_eq!(items[0].new_path, PathBuf::from("n (2)"));
+        assert_eq!(items[1].new_path, PathBuf::from("n"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// File in the target directory marking a run in progress.
+pub const LOCK_NAME: &str = ".crtime.lock";
+
+/// Advisory lock held on a directory while its files are being renamed.
+///
+/// The lock is taken on the lock file through the operating system, which
+/// lets go of it when the process ends however it does, so a lock file
+/// left behind by a crash does not keep later runs out. The file is
+/// removed again when the guard is dropped.
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl DirLock {
+    pub fn acquire(dir: &Path) -> io::Result<DirLock> {
+        let path = dir.join(LOCK_NAME);
+
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+
+            match try_lock(&file) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} is in use by another crtime run", dir.display()),
+                    ))
+                }
+                Err(error) => return Err(error),
+            }
+
+            // The run before may have removed the file between opening and
+            // locking it, leaving this lock on a file no other run sees.
+            if !is_at(&file, &path) {
+                continue;
+            }
+
+            file.set_len(0)?;
+            writeln!(file, "{}", process::id())?;
+
+            return Ok(DirLock { path, _file: file });
+        }
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the lock on `file` unless another process holds it, returning
+/// whether it was taken.
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    match io::Error::last_os_error() {
+        error if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        error => Err(error),
+    }
+}
+
+#[cfg(windows)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    };
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+
+    let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+    let flags = LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY;
+
+    if unsafe { LockFileEx(file.as_raw_handle() as _, flags, 0, 1, 0, &mut overlapped) } != 0 {
+        return Ok(true);
+    }
+
+    match io::Error::last_os_error() {
+        error if error.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) => Ok(false),
+        error => Err(error),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn try_lock(_file: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Whether `file` is still the one at `path`.
+#[cfg(unix)]
+fn is_at(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), path.metadata()) {
+        (Ok(open), Ok(there)) => open.dev() == there.dev() && open.ino() == there.ino(),
+        _ => false,
+    }
+}
+
+/// A file being removed cannot be opened again here, so it surely is.
+#[cfg(not(unix))]
+fn is_at(_file: &File, _path: &Path) -> bool {
+    true
+}
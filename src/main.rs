@@ -1,11 +1,24 @@
 use std::env;
 use std::process;
 
+use crtime::config_file::Settings;
 use crtime::Config;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    let settings = Settings::gather(&args).unwrap_or_else(|err| {
+        eprintln!("Problem with the configuration: {}", err);
+        process::exit(1);
+    });
+
+    if args.iter().any(|arg| arg == "--show-config") {
+        print!("{}", settings);
+        return;
+    }
+
+    let args = settings.args(args);
+
     let config = Config::new(&args).unwrap_or_else(|err| {
         eprintln!("Problem with parsing arguments: {}", err);
         process::exit(1);
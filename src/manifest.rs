@@ -0,0 +1,63 @@
+//! `SHA256SUMS` manifests of renamed files, in the format `sha256sum`
+//! writes and checks with `sha256sum -c`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::hash;
+use crate::RenamePlanItem;
+
+/// Name of the manifest in the directory it covers.
+pub const FILE_NAME: &str = "SHA256SUMS";
+
+/// Adds the renamed `items` under their new names to the manifest in
+/// `dir`, creating it if need be. Unless `copied`, entries for their old
+/// names are dropped.
+///
+/// Paths are relative to `dir` where they are inside it.
+pub fn update(dir: &Path, items: &[&RenamePlanItem], copied: bool) -> io::Result<()> {
+    let path = dir.join(FILE_NAME);
+    let mut entries = match fs::read_to_string(&path) {
+        Ok(text) => parse(&text),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(error) => return Err(error),
+    };
+
+    for item in items {
+        if !copied {
+            entries.remove(&relative(dir, &item.path));
+        }
+
+        let digest = hash::sha256_file(&item.new_path)?;
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        entries.insert(relative(dir, &item.new_path), hex);
+    }
+
+    let mut text = String::new();
+
+    for (name, hex) in &entries {
+        text.push_str(&format!("{}  {}\n", hex, name));
+    }
+
+    fs::write(path, text)
+}
+
+/// The entries of a manifest by path, ignoring lines that are not ones.
+fn parse(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(hex, _)| hex.len() == 64)
+        .map(|(hex, name)| {
+            // `sha256sum -b` marks names with `*` instead of a space.
+            let name = name.strip_prefix(['*', ' ']).unwrap_or(name);
+            (name.to_owned(), hex.to_owned())
+        })
+        .collect()
+}
+
+fn relative(dir: &Path, path: &Path) -> String {
+    let path = path.strip_prefix(dir).unwrap_or(path);
+    path.to_string_lossy().into_owned()
+}
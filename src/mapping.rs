@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::source::Source;
+use crate::stamp::FileStamp;
+use crate::{drop_unchanged, resolve_collisions, FsItemError, Plan, RenamePlanItem, SkippedItem};
+
+/// First rows recognized as column titles rather than renames.
+const HEADERS: &[[&str; 2]] = &[
+    ["old", "new"],
+    ["old-path", "new-path"],
+    ["old_path", "new_path"],
+    ["from", "to"],
+];
+
+fn invalid(file: &Path, line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: line {}: {}", file.display(), line, message),
+    )
+}
+
+/// Reads the `old-path,new-path` pairs in the CSV file `file`.
+///
+/// Fields may be quoted as usual in CSV, and an optional first row of
+/// column titles such as `old,new` is skipped. Relative paths are taken
+/// relative to `dir`.
+pub fn read_csv(file: &Path, dir: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let text = fs::read_to_string(file)?;
+    let mut pairs = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, (line, fields)) in records(&text)
+        .map_err(|line| invalid(file, line, "unterminated quoted field"))?
+        .into_iter()
+        .enumerate()
+    {
+        let (old, new) = match &fields[..] {
+            [field] if field.is_empty() => continue,
+            [old, new] => (old, new),
+            _ => return Err(invalid(file, line, "expected an old and a new path")),
+        };
+
+        let is_header = HEADERS
+            .iter()
+            .any(|[a, b]| old.eq_ignore_ascii_case(a) && new.eq_ignore_ascii_case(b));
+
+        if i == 0 && is_header {
+            continue;
+        }
+
+        if old.is_empty() || new.is_empty() {
+            return Err(invalid(file, line, "empty path"));
+        }
+
+        let old = dir.join(old);
+
+        if !seen.insert(old.clone()) {
+            return Err(invalid(file, line, "old path listed before"));
+        }
+
+        pairs.push((old, dir.join(new)));
+    }
+
+    Ok(pairs)
+}
+
+/// Splits CSV `text` into records, each with the line it starts on.
+/// Fails with the line of a quoted field left open.
+fn records(text: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let (mut line, mut start) = (1, 1);
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut fields)));
+                line += 1;
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(start);
+    }
+
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((start, fields));
+    }
+
+    Ok(records)
+}
+
+/// Plans renaming each pair's old path to its new path, in order.
+///
+/// Files that cannot be renamed are listed in `Plan::skipped`, and new
+/// paths that would clash get a number appended like in `plan`. As the
+/// new names come from elsewhere, `created` is the modification time.
+pub fn plan(pairs: Vec<(PathBuf, PathBuf)>) -> Plan {
+    let mut plan = Plan::default();
+
+    for (path, new_path) in pairs {
+        match item(path.clone(), new_path) {
+            Ok(item) => plan.items.push(item),
+            Err(reason) => plan.skipped.push(SkippedItem { path, reason }),
+        }
+    }
+
+    resolve_collisions(&mut plan.items);
+    drop_unchanged(&mut plan);
+    plan
+}
+
+fn item(path: PathBuf, new_path: PathBuf) -> Result<RenamePlanItem, FsItemError> {
+    let meta = fs::symlink_metadata(&path)?;
+
+    if meta.is_dir() {
+        return Err(FsItemError::ItemIsDir);
+    }
+
+    let name = |path: &Path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_owned)
+            .ok_or(FsItemError::NameFailed)
+    };
+
+    Ok(RenamePlanItem {
+        created: meta.modified()?.into(),
+        source: Source::Mtime,
+        name: name(&path)?,
+        new_name: name(&new_path)?,
+        stamp: FileStamp::of(&meta),
+        path,
+        new_path,
+        replaces: false,
+    })
+}
@@ -0,0 +1,155 @@
+//! Phones and cameras connected over USB with MTP, through the command-line
+//! tools of libmtp: `mtp-folders`, `mtp-files`, `mtp-getfile` and
+//! `mtp-delfile`, packaged as `mtp-tools` by most distributions.
+//!
+//! MTP has no renames worth relying on, so files are imported from devices
+//! instead: downloaded, then named like local files. The tools talk to the
+//! first device connected.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// A file on a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MtpFile {
+    /// The object ID of the file on the device.
+    pub id: u32,
+    pub name: String,
+    pub len: u64,
+}
+
+/// The files directly in `folder`, like `DCIM/Camera`, or at the top of
+/// the device for an empty `folder`.
+pub fn files(folder: &str) -> io::Result<Vec<MtpFile>> {
+    let parent = match folder.trim_matches('/') {
+        "" => 0,
+        folder => match folder_id(&run("mtp-folders", &[])?, folder) {
+            Some(id) => id,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no folder {} on the device", folder),
+                ))
+            }
+        },
+    };
+
+    Ok(parse_files(&run("mtp-files", &[])?)
+        .into_iter()
+        .filter(|(_, file_parent)| *file_parent == parent)
+        .map(|(file, _)| file)
+        .collect())
+}
+
+/// Downloads `file` to `to`.
+pub fn download(file: &MtpFile, to: &Path) -> io::Result<()> {
+    let to = to.to_string_lossy();
+    run("mtp-getfile", &[&file.id.to_string(), &to]).map(drop)
+}
+
+/// Deletes `file` from the device.
+pub fn delete(file: &MtpFile) -> io::Result<()> {
+    run("mtp-delfile", &["-n", &file.id.to_string()]).map(drop)
+}
+
+/// Runs one of the tools, returning what it prints.
+fn run(program: &str, args: &[&str]) -> io::Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found, it comes with libmtp", program),
+            ),
+            _ => error,
+        })?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            program,
+            message.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Finds `path` in what `mtp-folders` prints: an ID and a tab per folder,
+/// with names indented by two spaces per level of nesting.
+fn folder_id(listing: &str, path: &str) -> Option<u32> {
+    let mut names: Vec<&str> = Vec::new();
+
+    for line in listing.lines() {
+        let (id, name) = match line.split_once('\t') {
+            Some((id, name)) => match id.trim().parse::<u32>() {
+                Ok(id) => (id, name),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        let level = (name.len() - name.trim_start_matches(' ').len()) / 2;
+
+        names.truncate(level);
+        names.push(name.trim());
+
+        if names.join("/") == path {
+            return Some(id);
+        }
+    }
+
+    None
+}
+
+/// The files in what `mtp-files` prints, with the IDs of the folders they
+/// are in.
+fn parse_files(listing: &str) -> Vec<(MtpFile, u32)> {
+    let mut files = Vec::new();
+    let mut current: Option<(MtpFile, u32, bool)> = None;
+
+    for line in listing.lines().map(str::trim) {
+        if let Some(id) = line.strip_prefix("File ID:") {
+            files.extend(current.take().filter(|&(_, _, folder)| !folder));
+
+            current = id.trim().parse().ok().map(|id| {
+                let file = MtpFile {
+                    id,
+                    name: String::new(),
+                    len: 0,
+                };
+                (file, 0, false)
+            });
+            continue;
+        }
+
+        let (file, parent, folder) = match &mut current {
+            Some(current) => current,
+            None => continue,
+        };
+
+        if let Some(name) = line.strip_prefix("Filename:") {
+            file.name = name.trim().to_owned();
+        } else if let Some(len) = line.strip_prefix("File size") {
+            file.len = len
+                .split_whitespace()
+                .next()
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0);
+        } else if let Some(id) = line.strip_prefix("Parent ID:") {
+            *parent = id.trim().parse().unwrap_or(0);
+        } else if let Some(kind) = line.strip_prefix("Filetype:") {
+            *folder = kind.trim() == "Folder";
+        }
+    }
+
+    files.extend(current.filter(|&(_, _, folder)| !folder));
+
+    files
+        .into_iter()
+        .filter(|(file, _, _)| !file.name.is_empty() && !file.name.contains('/'))
+        .map(|(file, parent, _)| (file, parent))
+        .collect()
+}
@@ -0,0 +1,279 @@
+//! New names the filesystem they go to would not take, like names with `:`
+//! or `?` on a memory card or an NTFS drive, which would otherwise only
+//! fail when the files are renamed.
+//!
+//! FAT, exFAT and NTFS, and any filesystem on Windows, follow the rules of
+//! Windows: no `<>:"/\|?*` or control characters, and no dot or space at
+//! the end. Names Windows keeps for devices, like `CON` or `COM1.jpg`, are
+//! avoided on every filesystem, so that files renamed elsewhere can still
+//! be copied to Windows. Names are checked once numbered, before conflicts
+//! are settled, so that names made valid are numbered apart like any
+//! others.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::fs_kind::FsKind;
+use crate::{FsItemError, Plan, PlanOptions, SkippedItem};
+
+/// What to do with new names the target filesystem does not allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamePolicy {
+    /// Replace the characters it does not allow by `_`, drop the dots and
+    /// spaces at the end and add `_` to device names.
+    #[default]
+    Sanitize,
+    /// Leave the file as it is, listed as skipped.
+    Skip,
+}
+
+impl FromStr for NamePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sanitize" => Ok(NamePolicy::Sanitize),
+            "skip" => Ok(NamePolicy::Skip),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Why a filesystem would not take a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameProblem {
+    /// It has a character the filesystem does not allow.
+    Char(char),
+    /// It ends with a dot or space, which Windows drops.
+    Ending(char),
+    /// It is, before any extension, the name of a device on Windows.
+    Reserved(String),
+}
+
+impl fmt::Display for NameProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameProblem::Char(c) => write!(
+                f,
+                "contains {:?}, which the target filesystem does not allow",
+                c
+            ),
+            NameProblem::Ending(c) => write!(
+                f,
+                "ends with {:?}, which the target filesystem does not allow",
+                c
+            ),
+            NameProblem::Reserved(stem) => write!(f, "uses {:?}, a device name on Windows", stem),
+        }
+    }
+}
+
+/// Characters Windows does not allow in names, besides control ones.
+const WINDOWS_INVALID: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names Windows keeps besides `COM` and `LPT` with a digit.
+const RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+/// Whether names on a filesystem of `kind` follow the rules of Windows.
+pub fn has_windows_rules(kind: FsKind) -> bool {
+    cfg!(windows) || matches!(kind, FsKind::Fat | FsKind::ExFat | FsKind::Ntfs)
+}
+
+/// Why a filesystem of `kind` would not take `name`, if it would not.
+pub fn problem(name: &str, kind: FsKind) -> Option<NameProblem> {
+    if has_windows_rules(kind) {
+        if let Some(c) = name.chars().find(|&c| is_invalid(c)) {
+            return Some(NameProblem::Char(c));
+        }
+
+        if let Some(c @ ('.' | ' ')) = name.chars().last() {
+            return Some(NameProblem::Ending(c));
+        }
+    }
+
+    reserved_stem(name).map(|stem| NameProblem::Reserved(stem.to_owned()))
+}
+
+/// `name` made valid on a filesystem of `kind`.
+pub fn sanitize(name: &str, kind: FsKind) -> String {
+    let mut name = name.to_owned();
+
+    if has_windows_rules(kind) {
+        name = name
+            .chars()
+            .map(|c| if is_invalid(c) { '_' } else { c })
+            .collect();
+
+        name = match name.trim_end_matches(['.', ' ']) {
+            "" => "_".to_owned(),
+            name => name.to_owned(),
+        };
+    }
+
+    if let Some(stem) = reserved_stem(&name) {
+        name.insert(stem.len(), '_');
+    }
+
+    name
+}
+
+fn is_invalid(c: char) -> bool {
+    c.is_control() || WINDOWS_INVALID.contains(&c)
+}
+
+/// The part of `name` before its first dot, without the spaces at its
+/// end, if Windows takes it for a device, as it does whatever the case
+/// and extension.
+fn reserved_stem(name: &str) -> Option<&str> {
+    let stem = name.split('.').next()?.trim_end_matches(' ');
+    let upper = stem.to_ascii_uppercase();
+    let numbered = upper
+        .strip_prefix("COM")
+        .or_else(|| upper.strip_prefix("LPT"))
+        .is_some_and(|n| {
+            matches!(n, "¹" | "²" | "³") || (n.len() == 1 && n.as_bytes()[0].is_ascii_digit())
+        });
+
+    if RESERVED.contains(&upper.as_str()) || numbered {
+        Some(stem)
+    } else {
+        None
+    }
+}
+
+/// Makes the new names of `plan` valid on the filesystems they go to, or
+/// moves the files with names that are not to the skipped ones, as
+/// `options.invalid_names` says.
+pub(crate) fn check(plan: &mut Plan, options: &PlanOptions) {
+    let mut kinds: HashMap<PathBuf, FsKind> = HashMap::new();
+    let mut invalid = Vec::new();
+
+    for (i, item) in plan.items.iter_mut().enumerate() {
+        let dir = item
+            .new_path
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        let kind = *kinds
+            .entry(dir)
+            .or_insert_with_key(|dir| FsKind::detect_nearest(dir));
+
+        let problem = match problem(&item.new_name, kind) {
+            Some(problem) => problem,
+            None => continue,
+        };
+
+        match options.invalid_names {
+            NamePolicy::Sanitize => {
+                item.new_name = sanitize(&item.new_name, kind);
+                item.new_path.set_file_name(&item.new_name);
+            }
+            NamePolicy::Skip => invalid.push((i, problem)),
+        }
+    }
+
+    let mut skipped: Vec<_> = invalid
+        .into_iter()
+        .rev()
+        .map(|(i, problem)| SkippedItem {
+            path: plan.items.remove(i).path,
+            reason: FsItemError::InvalidName(problem),
+        })
+        .collect();
+    skipped.reverse();
+    plan.skipped.extend(skipped);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_names_alone_elsewhere() {
+        if cfg!(windows) {
+            return;
+        }
+
+        assert_eq!(problem("12:00 a?.jpg", FsKind::Other), None);
+        assert_eq!(sanitize("12:00 a?.jpg", FsKind::Other), "12:00 a?.jpg");
+    }
+
+    #[test]
+    fn finds_what_windows_does_not_allow() {
+        assert_eq!(
+            problem("12:00.jpg", FsKind::ExFat),
+            Some(NameProblem::Char(':'))
+        );
+        assert_eq!(
+            problem("a\u{1}.jpg", FsKind::Fat),
+            Some(NameProblem::Char('\u{1}'))
+        );
+        assert_eq!(
+            problem("name.", FsKind::Ntfs),
+            Some(NameProblem::Ending('.'))
+        );
+        assert_eq!(
+            problem("name ", FsKind::Ntfs),
+            Some(NameProblem::Ending(' '))
+        );
+        assert_eq!(problem("20240305121000 a.jpg", FsKind::Ntfs), None);
+    }
+
+    #[test]
+    fn sanitizes_for_windows() {
+        assert_eq!(
+            sanitize("12:00 <a|b>?.jpg", FsKind::ExFat),
+            "12_00 _a_b__.jpg"
+        );
+        assert_eq!(sanitize("a\"b*c\\d/e.jpg", FsKind::Fat), "a_b_c_d_e.jpg");
+        assert_eq!(sanitize("name. .", FsKind::Ntfs), "name");
+        assert_eq!(sanitize("...", FsKind::Ntfs), "_");
+    }
+
+    #[test]
+    fn finds_device_names_anywhere() {
+        for name in [
+            "CON",
+            "con.jpg",
+            "Nul.tar.gz",
+            "PRN .txt",
+            "aux",
+            "COM1.jpg",
+            "lpt9",
+            "COM¹.txt",
+        ] {
+            assert!(
+                matches!(problem(name, FsKind::Other), Some(NameProblem::Reserved(_))),
+                "{}",
+                name
+            );
+        }
+
+        for name in [
+            "CONSOLE.jpg",
+            "xcon.jpg",
+            "COM10.jpg",
+            "COM.jpg",
+            "LPTx",
+            "aux1.jpg",
+            "a.con",
+        ] {
+            assert_eq!(problem(name, FsKind::Other), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn adjusts_device_names() {
+        assert_eq!(sanitize("CON.jpg", FsKind::Other), "CON_.jpg");
+        assert_eq!(sanitize("nul", FsKind::Other), "nul_");
+        assert_eq!(sanitize("com1.tar.gz", FsKind::Other), "com1_.tar.gz");
+        assert_eq!(sanitize("LPT9 .log", FsKind::Other), "LPT9_ .log");
+        assert_eq!(sanitize("AUX.", FsKind::ExFat), "AUX_");
+        assert_eq!(
+            problem(&sanitize("PRN.txt", FsKind::Ntfs), FsKind::Ntfs),
+            None
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Duration, Utc};
+use std::str::FromStr;
+
+/// A fixed shift applied to resolved timestamps, for clocks set to the
+/// wrong timezone or running late or early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Offset(pub Duration);
+
+impl Offset {
+    /// `time` shifted by the offset, if still representable.
+    pub fn apply(self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        time.checked_add_signed(self.0)
+    }
+}
+
+impl FromStr for Offset {
+    type Err = ();
+
+    /// Parses `+02:00` or `-01:30:15` style hours and minutes (and seconds),
+    /// or a number with one of the units `s`, `m`, `h` and `d`, such as
+    /// `-36m`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, rest) = match s.as_bytes().first() {
+            Some(b'+') => (1, &s[1..]),
+            Some(b'-') => (-1, &s[1..]),
+            _ => (1, s),
+        };
+
+        let seconds = if rest.contains(':') {
+            let fields = rest
+                .split(':')
+                .map(|field| match field.len() {
+                    1 | 2 => field.parse::<u32>().map(i64::from).map_err(|_| ()),
+                    _ => Err(()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match fields[..] {
+                [h, m] if m < 60 => h * 3600 + m * 60,
+                [h, m, s] if m < 60 && s < 60 => h * 3600 + m * 60 + s,
+                _ => return Err(()),
+            }
+        } else {
+            let unit = match rest.chars().last() {
+                Some('s') => 1,
+                Some('m') => 60,
+                Some('h') => 3600,
+                Some('d') => 86400,
+                _ => return Err(()),
+            };
+            let count: u32 = rest[..rest.len() - 1].parse().map_err(|_| ())?;
+            i64::from(count) * unit
+        };
+
+        Duration::try_seconds(sign * seconds).map(Offset).ok_or(())
+    }
+}
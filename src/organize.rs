@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{name_time, PlanOptions};
+
+/// A level of the directories files are sorted into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Year,
+    Month,
+    Day,
+    /// ISO 8601 week, like `W10`.
+    Week,
+}
+
+/// How files are sorted into directories by creation time, like
+/// `year/month`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Organize(Vec<Unit>);
+
+impl FromStr for Organize {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let units = s
+            .split('/')
+            .map(|unit| match unit {
+                "year" => Ok(Unit::Year),
+                "month" => Ok(Unit::Month),
+                "day" => Ok(Unit::Day),
+                "week" => Ok(Unit::Week),
+                _ => Err(()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Organize(units))
+    }
+}
+
+impl Organize {
+    /// Directory relative to the target directory the file at `path`,
+    /// created at `created`, goes into.
+    ///
+    /// Years are ISO week-numbering years when weeks are used as well, so
+    /// the last days of December can go into week 1 of the next year.
+    pub fn dir(&self, path: &Path, created: DateTime<Utc>, options: &PlanOptions) -> PathBuf {
+        let by_week = self.0.contains(&Unit::Week);
+
+        self.0
+            .iter()
+            .map(|unit| {
+                let format = match unit {
+                    Unit::Year if by_week => "%G",
+                    Unit::Year => "%Y",
+                    Unit::Month => "%m",
+                    Unit::Day => "%d",
+                    Unit::Week => "W%V",
+                };
+
+                name_time(path, created, options, format)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,76 @@
+use std::env;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Prints `text`, through `$PAGER` (`less` by default) when standard
+/// output is a terminal too short to show it whole.
+pub fn show(text: &str) -> io::Result<()> {
+    let fits = match terminal_height() {
+        Some(height) => text.lines().count() < height,
+        None => true,
+    };
+
+    if !io::stdout().is_terminal() || fits || !page(text)? {
+        print!("{}", text);
+    }
+
+    Ok(())
+}
+
+/// Runs the pager on `text`, returning whether there was one to run.
+fn page(text: &str) -> io::Result<bool> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_owned());
+    let mut words = pager.split_whitespace();
+
+    let program = match words.next() {
+        Some("cat") | None => return Ok(false),
+        Some(program) => program,
+    };
+
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+
+    // Like git: quit if it fits after all, keep colors, leave the text on
+    // screen for the confirmation below.
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(text.as_bytes()) {
+            // The user quit the pager before reading everything.
+            Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+
+    child.wait()?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn terminal_height() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_row > 0 => Some(usize::from(size.ws_row)),
+        _ => lines_var(),
+    }
+}
+
+#[cfg(not(unix))]
+fn terminal_height() -> Option<usize> {
+    lines_var()
+}
+
+/// Terminal height as exported by some shells.
+fn lines_var() -> Option<usize> {
+    env::var("LINES").ok()?.parse().ok()
+}
@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Fuzzy finders tried when `CRTIME_PICKER` is not set.
+const PICKERS: &[&str] = &["fzf --multi", "sk --multi"];
+
+/// Lets the user choose among `candidates` with a fuzzy finder, returning
+/// the indices of the chosen ones.
+///
+/// The finder is `CRTIME_PICKER` if set, otherwise `fzf` or `sk`. It reads
+/// the candidates one per line and prints the chosen lines; choosing none,
+/// including aborting the finder, yields an empty selection.
+pub fn pick(candidates: &[String]) -> io::Result<Vec<usize>> {
+    let pickers = match env::var("CRTIME_PICKER") {
+        Ok(picker) => vec![picker],
+        Err(_) => PICKERS.iter().map(|picker| picker.to_string()).collect(),
+    };
+
+    for picker in &pickers {
+        let mut words = picker.split_whitespace();
+        let program = match words.next() {
+            Some(program) => program,
+            None => continue,
+        };
+
+        let mut child = match Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            for candidate in candidates.iter().filter(|c| !c.contains('\n')) {
+                writeln!(stdin, "{}", candidate)?;
+            }
+        }
+
+        let output = child.wait_with_output()?;
+        let chosen: HashSet<_> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_owned)
+            .collect();
+
+        return Ok((0..candidates.len())
+            .filter(|&i| chosen.contains(&candidates[i]))
+            .collect());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "--pick needs fzf or sk installed, or CRTIME_PICKER set to a fuzzy finder",
+    ))
+}
@@ -0,0 +1,193 @@
+use chrono::DateTime;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::journal::{self, Op};
+use crate::stamp::StaleReason;
+use crate::transfer::TransferMode;
+use crate::RenamePlanItem;
+
+/// File in the target directory holding the plan of a run until it has
+/// been applied completely.
+pub const PLAN_NAME: &str = ".crtime-plan";
+
+/// Paths are escaped as in the journal since version 2, and taken as they
+/// are in plans of version 1. Like in the journal, they are absolute unless
+/// saved before they were.
+const HEADER: &str = "crtime-plan 2";
+const HEADER_V1: &str = "crtime-plan 1";
+
+/// A plan written out before applying it, so an interrupted run can be
+/// resumed.
+#[derive(Debug)]
+pub struct SavedPlan {
+    /// Length of the journal when the run started; later entries belong to
+    /// the run.
+    pub journal_offset: u64,
+    pub mode: TransferMode,
+    pub items: Vec<RenamePlanItem>,
+}
+
+/// The items of a saved plan, by how far the interrupted run got.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Renamed, and unchanged since.
+    pub done: Vec<RenamePlanItem>,
+    /// Renamed, but changed or gone since.
+    pub changed: Vec<(RenamePlanItem, StaleReason)>,
+    /// Not renamed yet.
+    pub remaining: Vec<RenamePlanItem>,
+}
+
+fn invalid(line: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is damaged at line {}", PLAN_NAME, line),
+    )
+}
+
+impl SavedPlan {
+    /// Writes the plan into `dir`, replacing any saved earlier.
+    pub fn save(
+        dir: &Path,
+        items: &[RenamePlanItem],
+        journal_offset: u64,
+        mode: TransferMode,
+    ) -> io::Result<()> {
+        let mode = match mode {
+            TransferMode::Move => "move",
+            TransferMode::Copy => "copy",
+        };
+
+        let mut out = format!("{}\n{}\t{}\n", HEADER, journal_offset, mode);
+
+        for item in items {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                journal::escape(&std::path::absolute(&item.path)?),
+                journal::escape(&std::path::absolute(&item.new_path)?),
+                item.created.to_rfc3339(),
+                item.source,
+                item.stamp
+            ));
+        }
+
+        let mut file = File::create(dir.join(PLAN_NAME))?;
+        file.write_all(out.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Reads the plan saved in `dir`.
+    pub fn load(dir: &Path) -> io::Result<SavedPlan> {
+        let file = File::open(dir.join(PLAN_NAME))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let escaped = match lines.next().transpose()?.as_deref() {
+            Some(HEADER) => true,
+            Some(HEADER_V1) => false,
+            _ => return Err(invalid(1)),
+        };
+
+        let settings = lines.next().transpose()?.ok_or_else(|| invalid(2))?;
+        let (offset, mode) = settings.split_once('\t').ok_or_else(|| invalid(2))?;
+        let journal_offset = offset.parse().map_err(|_| invalid(2))?;
+        let mode = match mode {
+            "move" => TransferMode::Move,
+            "copy" => TransferMode::Copy,
+            _ => return Err(invalid(2)),
+        };
+
+        let mut items = Vec::new();
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let item = parse_item(&line, escaped).ok_or_else(|| invalid(i + 3))?;
+            items.push(item);
+        }
+
+        Ok(SavedPlan {
+            journal_offset,
+            mode,
+            items,
+        })
+    }
+
+    /// Sorts the items by whether the journal in `dir` records them as
+    /// renamed, checking renamed ones against their stamps.
+    pub fn progress(self, dir: &Path) -> io::Result<Progress> {
+        let mut moved = HashSet::new();
+
+        // Renames rolled back are recorded as moves back or removals.
+        for op in journal::read(dir, self.journal_offset)? {
+            match op {
+                Op::Move { from, to } => {
+                    if !moved.remove(&(to.clone(), from.clone())) {
+                        moved.insert((from, to));
+                    }
+                }
+                Op::Copy { from, to } => {
+                    moved.insert((from, to));
+                }
+                Op::Remove { path } => moved.retain(|(_, to)| *to != path),
+            }
+        }
+
+        let mut progress = Progress::default();
+
+        for item in self.items {
+            if !moved.contains(&(item.path.clone(), item.new_path.clone())) {
+                progress.remaining.push(item);
+                continue;
+            }
+
+            let check = match self.mode {
+                TransferMode::Move => item.stamp.check_contents(&item.new_path),
+                TransferMode::Copy => item.stamp.check(&item.path),
+            };
+
+            match check {
+                Ok(()) => progress.done.push(item),
+                Err(reason) => progress.changed.push((item, reason)),
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// Removes the plan saved in `dir`, once it needs no resuming.
+    pub fn remove(dir: &Path) -> io::Result<()> {
+        match fs::remove_file(dir.join(PLAN_NAME)) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+fn parse_item(line: &str, escaped: bool) -> Option<RenamePlanItem> {
+    let fields: Vec<_> = line.split('\t').collect();
+
+    let (path, new_path, created, source, stamp) = match fields[..] {
+        [path, new_path, created, source, stamp] => (path, new_path, created, source, stamp),
+        _ => return None,
+    };
+
+    let (path, new_path) = match escaped {
+        true => (journal::unescape(path)?, journal::unescape(new_path)?),
+        false => (PathBuf::from(path), PathBuf::from(new_path)),
+    };
+
+    Some(RenamePlanItem {
+        created: DateTime::parse_from_rfc3339(created).ok()?.into(),
+        source: source.parse().ok()?,
+        name: path.file_name()?.to_str()?.to_owned(),
+        new_name: new_path.file_name()?.to_str()?.to_owned(),
+        path,
+        new_path,
+        stamp: stamp.parse().ok()?,
+        replaces: false,
+    })
+}
@@ -0,0 +1,64 @@
+/// Format of the creation time crtime puts in front of file names.
+pub const FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// Format older versions of crtime used by mistake, lacking the hour.
+pub const LEGACY_FORMAT: &str = "%Y%m%d%M%S";
+
+/// Lengths of prefixes in `FORMAT` and `LEGACY_FORMAT` for years 1000 to
+/// 9999.
+const LEN: usize = 14;
+const LEGACY_LEN: usize = 12;
+
+/// Splits `name` into its creation time prefix, in either format, and the
+/// rest of the name, if it has a prefix.
+pub fn split(name: &str) -> Option<(&str, &str)> {
+    let (prefix, rest) = name.split_once(' ')?;
+    let is_prefix =
+        matches!(prefix.len(), LEN | LEGACY_LEN) && prefix.bytes().all(|b| b.is_ascii_digit());
+
+    if is_prefix && !rest.is_empty() {
+        Some((prefix, rest))
+    } else {
+        None
+    }
+}
+
+/// Whether `prefix`, as returned by `split`, is in `LEGACY_FORMAT`.
+pub fn is_legacy(prefix: &str) -> bool {
+    prefix.len() == LEGACY_LEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_prefixed_names() {
+        assert_eq!(
+            split("20240305121000 IMG_1.jpg"),
+            Some(("20240305121000", "IMG_1.jpg"))
+        );
+        assert_eq!(
+            split("20240305121000 a b.jpg"),
+            Some(("20240305121000", "a b.jpg"))
+        );
+    }
+
+    #[test]
+    fn splits_legacy_prefixes() {
+        let (prefix, rest) = split("202403051000 IMG_1.jpg").unwrap();
+
+        assert_eq!((prefix, rest), ("202403051000", "IMG_1.jpg"));
+        assert!(is_legacy(prefix));
+        assert!(!is_legacy("20240305121000"));
+    }
+
+    #[test]
+    fn leaves_other_names() {
+        assert_eq!(split("IMG_1.jpg"), None);
+        assert_eq!(split("2024 holiday.jpg"), None);
+        assert_eq!(split("2024030512100x IMG_1.jpg"), None);
+        assert_eq!(split("20240305121000 "), None);
+        assert_eq!(split("20240305121000.jpg"), None);
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::source::Source;
+
+/// Time spent reading one timestamp source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceTiming {
+    pub time: Duration,
+    /// Files the source was read for.
+    pub files: usize,
+}
+
+/// Where the time of a run went.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// Listing the directory and reading file metadata.
+    pub scanning: Duration,
+    /// Reading timestamps, per source.
+    pub sources: BTreeMap<Source, SourceTiming>,
+    /// Building names and sorting, excluding the above.
+    pub planning: Duration,
+    pub applying: Duration,
+}
+
+impl Timings {
+    pub fn record_source(&mut self, source: Source, time: Duration) {
+        let timing = self.sources.entry(source).or_default();
+        timing.time += time;
+        timing.files += 1;
+    }
+
+    /// Adds the times of `other`, as measured on another thread.
+    pub fn add(&mut self, other: &Timings) {
+        self.scanning += other.scanning;
+        self.planning += other.planning;
+        self.applying += other.applying;
+
+        for (&source, timing) in &other.sources {
+            let total = self.sources.entry(source).or_default();
+            total.time += timing.time;
+            total.files += timing.files;
+        }
+    }
+
+    pub fn extraction(&self) -> Duration {
+        self.sources.values().map(|timing| timing.time).sum()
+    }
+}
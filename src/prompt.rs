@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+
+use crate::i18n::{self, fill, tr, Language, ENGLISH};
+use crate::{Conflict, RenamePlanItem, Resolution};
+
+/// The controlling terminal, read from when stdin is taken by a pipe.
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+
+#[cfg(not(windows))]
+const TERMINAL: &str = "/dev/tty";
+
+/// Interprets an answer, accepting English words in any locale.
+fn parse(line: &str, language: &Language) -> Option<bool> {
+    let answer = line.trim().to_lowercase();
+    let english = ENGLISH;
+
+    if answer.is_empty() {
+        Some(false)
+    } else if language.yes.contains(&answer.as_str()) || english.yes.contains(&answer.as_str()) {
+        Some(true)
+    } else if language.no.contains(&answer.as_str()) || english.no.contains(&answer.as_str()) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Asks whether to go ahead, repeating the question until the answer is
+/// understood. An empty answer or the end of input means no.
+///
+/// The question goes to stderr so it never mixes with machine-readable
+/// output. When stdin is not a terminal, the answer is read from the
+/// controlling terminal if there is one, leaving piped input alone.
+pub fn confirm() -> bool {
+    ask(i18n::language().question)
+}
+
+/// Asks `question`, a yes-or-no question with no as the default, the way
+/// `confirm` does.
+pub fn ask(question: &str) -> bool {
+    let language = i18n::language();
+    let input: Box<dyn BufRead> = match terminal() {
+        Some(terminal) => Box::new(BufReader::new(terminal)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut lines = input.lines();
+
+    loop {
+        eprint!("{}", question);
+        let _ = io::stderr().flush();
+
+        match lines.next() {
+            Some(Ok(line)) => {
+                if let Some(answer) = parse(&line, language) {
+                    return answer;
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Whether there is someone at a terminal to ask.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() || File::open(TERMINAL).is_ok()
+}
+
+/// Asks how to settle `conflict` over the new name of `item`, until the
+/// answer is understood. The end of input skips the file.
+pub fn resolve(item: &RenamePlanItem, conflict: Conflict) -> Resolution {
+    let input: Box<dyn BufRead> = match terminal() {
+        Some(terminal) => Box::new(BufReader::new(terminal)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut lines = input.lines();
+    let mut ask = |question: &str| {
+        eprint!("{}", question);
+        let _ = io::stderr().flush();
+
+        match lines.next() {
+            Some(Ok(line)) => Some(line.trim().to_owned()),
+            _ => None,
+        }
+    };
+
+    let question = match conflict {
+        Conflict::Existing => {
+            eprintln!(
+                "{}",
+                fill("{}: {} is already there.", &[&item.name, &item.new_name])
+            );
+            tr("[o]verwrite, [s]kip, [a]dd a number or enter a [n]ew name? ")
+        }
+        Conflict::Planned => {
+            eprintln!(
+                "{}",
+                fill(
+                    "{}: {} is planned for another file too.",
+                    &[&item.name, &item.new_name]
+                )
+            );
+            tr("[s]kip, [a]dd a number or enter a [n]ew name? ")
+        }
+    };
+
+    loop {
+        let answer = match ask(question) {
+            Some(answer) => answer.to_lowercase(),
+            None => return Resolution::Skip,
+        };
+
+        match answer.as_str() {
+            "o" | "overwrite" if conflict == Conflict::Existing => return Resolution::Overwrite,
+            "s" | "skip" => return Resolution::Skip,
+            "a" | "add" => return Resolution::AddNumber,
+            "n" | "new" => match ask(tr("New name: ")) {
+                Some(name) if !name.is_empty() && !name.contains(['/', '\\']) => {
+                    return Resolution::Rename(name)
+                }
+                Some(_) => {}
+                None => return Resolution::Skip,
+            },
+            _ => {}
+        }
+    }
+}
+
+fn terminal() -> Option<File> {
+    if io::stdin().is_terminal() {
+        return None;
+    }
+
+    File::open(TERMINAL).ok()
+}
@@ -0,0 +1,149 @@
+//! Files protected against being changed: read-only ones, and on Linux
+//! those with the immutable or append-only flag, which cannot even be
+//! renamed.
+//!
+//! They are left out of plans unless asked for, and can have the
+//! protection lifted for the rename and put back on afterwards.
+
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+
+/// How a file is protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    /// Nobody may write to it, or on Windows, it has the read-only
+    /// attribute.
+    ReadOnly,
+    /// The immutable or append-only flag of Linux filesystems, set with
+    /// `chattr`.
+    Immutable,
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protection::ReadOnly => write!(f, "read-only"),
+            Protection::Immutable => write!(f, "immutable"),
+        }
+    }
+}
+
+/// How the file at `path` with `meta` is protected, if it is.
+pub fn protection(path: &Path, meta: &Metadata) -> Option<Protection> {
+    if flags(path).is_some_and(|flags| flags & PROTECTING_FLAGS != 0) {
+        Some(Protection::Immutable)
+    } else if meta.permissions().readonly() {
+        Some(Protection::ReadOnly)
+    } else {
+        None
+    }
+}
+
+/// Runs `rename`, which moves the file at `path` to `new_path`, with its
+/// protection lifted, and puts the protection back on the file at
+/// whichever path it ends up at.
+///
+/// Putting it back can fail without failing the rename, which stands
+/// either way.
+pub fn unprotected<T>(
+    path: &Path,
+    new_path: &Path,
+    rename: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    let meta = path.symlink_metadata()?;
+    let ends_at = |result: &io::Result<T>| if result.is_ok() { new_path } else { path };
+
+    match protection(path, &meta) {
+        None => rename(),
+        Some(Protection::ReadOnly) => {
+            fs::set_permissions(path, writable(&meta))?;
+            let result = rename();
+            let _ = fs::set_permissions(ends_at(&result), meta.permissions());
+            result
+        }
+        Some(Protection::Immutable) => {
+            let saved = flags(path).unwrap_or(0);
+            set_flags(path, saved & !PROTECTING_FLAGS)?;
+            let result = rename();
+            let _ = set_flags(ends_at(&result), saved);
+            result
+        }
+    }
+}
+
+/// The permissions of `meta` with writing allowed to the owner.
+#[cfg(unix)]
+fn writable(meta: &Metadata) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::Permissions::from_mode(meta.permissions().mode() | 0o200)
+}
+
+// Off Unix this only clears the read-only attribute.
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn writable(meta: &Metadata) -> fs::Permissions {
+    let mut permissions = meta.permissions();
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// The immutable and append-only flags.
+const PROTECTING_FLAGS: u32 = 0x10 | 0x20;
+
+/// The inode flags of the file at `path`, as `lsattr` shows them.
+#[cfg(target_os = "linux")]
+fn flags(path: &Path) -> Option<u32> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok()?;
+    let mut flags: libc::c_int = 0;
+
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        return None;
+    }
+
+    Some(flags as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn flags(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Sets the inode flags of the file at `path`, which takes root for the
+/// immutable and append-only ones.
+#[cfg(target_os = "linux")]
+fn set_flags(path: &Path, flags: u32) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    let flags = flags as libc::c_int;
+
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_flags(_path: &Path, _flags: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file flags cannot be changed on this platform",
+    ))
+}
@@ -0,0 +1,114 @@
+use std::io;
+use std::path::Path;
+
+use crate::transfer::same_file;
+
+/// Makes `to` a clone of `from` sharing its blocks, on copy-on-write
+/// filesystems such as Btrfs, XFS and APFS, and returns whether it could.
+///
+/// Cloning takes no time and no space beyond the metadata. Where it is not
+/// supported or `to` already exists, `to` is left as it was, and the caller
+/// copies as usual; `fs::copy` then still uses `copy_file_range(2)` on
+/// Linux, which lets the filesystem copy without going through crtime.
+#[cfg(target_os = "linux")]
+pub fn clone(from: &Path, to: &Path) -> io::Result<bool> {
+    use std::fs::{self, File, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+
+    refuse_same(from, to)?;
+
+    let source = File::open(from)?;
+    let copy = match OpenOptions::new().write(true).create_new(true).open(to) {
+        Ok(copy) => copy,
+        Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(error) => return Err(error),
+    };
+
+    if unsafe { libc::ioctl(copy.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(copy);
+        let _ = fs::remove_file(to);
+
+        return match error.raw_os_error() {
+            Some(libc::EOPNOTSUPP)
+            | Some(libc::EXDEV)
+            | Some(libc::EINVAL)
+            | Some(libc::ENOTTY) => Ok(false),
+            _ => Err(error),
+        };
+    }
+
+    copy.set_permissions(source.metadata()?.permissions())?;
+    Ok(true)
+}
+
+/// Makes `to` a clone of `from` sharing its blocks, on copy-on-write
+/// filesystems such as Btrfs, XFS and APFS, and returns whether it could.
+///
+/// Cloning takes no time and no space beyond the metadata. Where it is not
+/// supported or `to` already exists, `to` is left as it was, and the caller
+/// copies as usual.
+#[cfg(target_os = "macos")]
+pub fn clone(from: &Path, to: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    refuse_same(from, to)?;
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+    };
+
+    if unsafe { libc::clonefile(c_path(from)?.as_ptr(), c_path(to)?.as_ptr(), 0) } != 0 {
+        let error = io::Error::last_os_error();
+
+        return match error.raw_os_error() {
+            Some(libc::ENOTSUP) | Some(libc::EXDEV) | Some(libc::EEXIST) => Ok(false),
+            _ => Err(error),
+        };
+    }
+
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn clone(from: &Path, to: &Path) -> io::Result<bool> {
+    refuse_same(from, to)?;
+    Ok(false)
+}
+
+/// Fails if `from` and `to` are the same file, which a clone would empty.
+fn refuse_same(from: &Path, to: &Path) -> io::Result<()> {
+    if same_file(from, to) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "source and destination are the same file",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn leaves_the_original_and_existing_files_alone() {
+        let dir = std::env::temp_dir().join(format!("crtime-reflink-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (from, to) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&from, "original").unwrap();
+        fs::write(&to, "existing").unwrap();
+
+        assert!(clone(&from, &from).is_err());
+        assert!(!clone(&from, &to).unwrap());
+        assert_eq!(fs::read_to_string(&from).unwrap(), "original");
+        assert_eq!(fs::read_to_string(&to).unwrap(), "existing");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// How often and how patiently to retry operations failing transiently.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first failed attempt.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every following one.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `op`, retrying with exponential backoff while it fails transiently.
+    pub fn run<T, F>(&self, mut op: F) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+    {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+
+        loop {
+            match op() {
+                Err(ref error) if attempt < self.retries && is_transient(error) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Errors that network filesystems report for conditions that clear up on their own.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ResourceBusy
+    )
+}
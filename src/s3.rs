@@ -0,0 +1,323 @@
+//! Amazon S3 and compatible object storage, through the system's `curl`,
+//! which signs the requests with `--aws-sigv4`.
+//!
+//! Buckets have no renames, so a file is renamed by copying the object to
+//! its new key and deleting the old one. Credentials and the region come
+//! from the usual `AWS_*` environment variables, and
+//! `AWS_ENDPOINT_URL` points crtime at other S3-compatible storage.
+
+use chrono::{DateTime, SubsecRound, TimeZone, Utc};
+use std::env;
+use std::io;
+
+use crate::backend::{Backend, RemoteFile, RemoteOptions, Url};
+use crate::curl::{encode, Request, Response};
+use crate::throttle;
+use crate::xml::{elements, first, unescape};
+
+pub struct S3 {
+    bucket: String,
+    /// The start of the keys in the directory, ending in `/` unless empty.
+    prefix: String,
+    region: String,
+    /// `https://host` of an S3-compatible service, addressed path-style.
+    endpoint: Option<String>,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    /// The user metadata field holding the creation time of objects.
+    meta_time: Option<String>,
+}
+
+/// Fails unless the status is 2xx, with the S3 error message if there is
+/// one.
+fn check(response: Response) -> io::Result<Response> {
+    // A copy can fail after the 200 has been sent.
+    if (200..300).contains(&response.status) && !response.body.contains("<Error>") {
+        return Ok(response);
+    }
+
+    let message = match first(&response.body, "Message") {
+        Some(message) => unescape(message),
+        None => format!("S3 answered with status {}", response.status),
+    };
+
+    Err(io::Error::new(response.error_kind(), message))
+}
+
+impl S3 {
+    /// Reads credentials for the bucket `url` names, `s3://bucket/prefix`.
+    pub fn connect(url: &Url, options: &RemoteOptions) -> io::Result<S3> {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let missing = |name| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is not set", name),
+            )
+        };
+
+        let prefix = match url.path.as_str() {
+            "" => String::new(),
+            path => format!("{}/", path),
+        };
+
+        Ok(S3 {
+            bucket: url.host.clone(),
+            prefix,
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_owned()),
+            endpoint: var("AWS_ENDPOINT_URL_S3")
+                .or_else(|| var("AWS_ENDPOINT_URL"))
+                .map(|endpoint| endpoint.trim_end_matches('/').to_owned()),
+            access_key: var("AWS_ACCESS_KEY_ID").ok_or_else(|| missing("AWS_ACCESS_KEY_ID"))?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")
+                .ok_or_else(|| missing("AWS_SECRET_ACCESS_KEY"))?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            meta_time: options.meta_time.clone(),
+        })
+    }
+
+    /// The URL of the object `key`, or of the bucket for an empty key.
+    fn url(&self, key: &str) -> String {
+        match &self.endpoint {
+            Some(endpoint) => format!("{}/{}/{}", endpoint, self.bucket, encode(key, false)),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                self.bucket,
+                self.region,
+                encode(key, false)
+            ),
+        }
+    }
+
+    /// Sends a request signed with the credentials.
+    fn request(&self, method: &str, url: &str, headers: &[String]) -> io::Result<Response> {
+        let mut request = Request::new(method, url);
+
+        // Without a body, some services refuse a `PUT` for lacking a length.
+        if method == "PUT" {
+            request.option("data-binary", "");
+        }
+
+        request
+            .option("user", &format!("{}:{}", self.access_key, self.secret_key))
+            .option("aws-sigv4", &format!("aws:amz:{}:s3", self.region));
+
+        if let Some(token) = &self.session_token {
+            request.header(&format!("x-amz-security-token: {}", token));
+        }
+
+        for header in headers {
+            request.header(header);
+        }
+
+        request.send()
+    }
+
+    fn head(&self, key: &str) -> io::Result<Option<Response>> {
+        let response = self.request("HEAD", &self.url(key), &[])?;
+
+        match response.status {
+            404 => Ok(None),
+            _ => check(response).map(Some),
+        }
+    }
+
+    fn remote_file(&self, name: String, response: &Response) -> RemoteFile {
+        let modified = response
+            .header("last-modified")
+            .and_then(|time| DateTime::parse_from_rfc2822(time).ok())
+            .map(|time| time.with_timezone(&Utc));
+        let created = self.meta_time.as_ref().and_then(|field| {
+            let value = response.header(&format!("x-amz-meta-{}", field.to_lowercase()))?;
+            meta_time(value)
+        });
+
+        RemoteFile {
+            name,
+            len: response
+                .header("content-length")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0),
+            modified,
+            created,
+        }
+    }
+}
+
+impl Backend for S3 {
+    fn list(&mut self) -> io::Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut url = format!(
+                "{}?list-type=2&prefix={}&delimiter=%2F",
+                self.url(""),
+                encode(&self.prefix, true)
+            );
+
+            if let Some(token) = &token {
+                url.push_str(&format!("&continuation-token={}", encode(token, true)));
+            }
+
+            let response = check(self.request("GET", &url, &[])?)?;
+            files.extend(listed(&response.body, &self.prefix));
+
+            match first(&response.body, "NextContinuationToken") {
+                Some(next) if first(&response.body, "IsTruncated") == Some("true") => {
+                    token = Some(unescape(next))
+                }
+                _ => break,
+            }
+        }
+
+        // The listing leaves out user metadata.
+        if self.meta_time.is_some() {
+            for file in &mut files {
+                throttle::wait();
+
+                if let Some(response) = self.head(&format!("{}{}", self.prefix, file.name))? {
+                    file.created = self.remote_file(file.name.clone(), &response).created;
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn stat(&mut self, name: &str) -> io::Result<Option<RemoteFile>> {
+        let response = self.head(&format!("{}{}", self.prefix, name))?;
+
+        Ok(response.map(|response| self.remote_file(name.to_owned(), &response)))
+    }
+
+    /// Copies the object to its new key, keeping its metadata, and deletes
+    /// the old one. Objects over 5 GB cannot be copied this way.
+    ///
+    /// A copy already at the new key, as an attempt that failed to delete
+    /// leaves, is taken as done when it has the ETag and length of the
+    /// object, so that it is only deleted again.
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let (from, to) = (
+            format!("{}{}", self.prefix, from),
+            format!("{}{}", self.prefix, to),
+        );
+
+        if let Some(existing) = self.head(&to)? {
+            let copied = self.head(&from)?.is_some_and(|original| {
+                ["etag", "content-length"].iter().all(|name| {
+                    original.header(name).is_some()
+                        && original.header(name) == existing.header(name)
+                })
+            });
+
+            if !copied {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", to),
+                ));
+            }
+
+            return check(self.request("DELETE", &self.url(&from), &[])?).map(|_| ());
+        }
+
+        let copy_source = format!(
+            "x-amz-copy-source: /{}/{}",
+            self.bucket,
+            encode(&from, false)
+        );
+        let headers = [copy_source, "x-amz-metadata-directive: COPY".to_owned()];
+        check(self.request("PUT", &self.url(&to), &headers)?)?;
+        check(self.request("DELETE", &self.url(&from), &[])?)?;
+
+        Ok(())
+    }
+}
+
+/// The objects in a page of a listing of the keys starting with `prefix`.
+///
+/// Modification times are cut to whole seconds, as the `Last-Modified` of
+/// an object has them, so that files are not taken for changed when looked
+/// at again before they are renamed.
+fn listed(body: &str, prefix: &str) -> Vec<RemoteFile> {
+    let mut files = Vec::new();
+
+    for object in elements(body, "Contents") {
+        let key = match first(object, "Key") {
+            Some(key) => unescape(key),
+            None => continue,
+        };
+        let name = match key.strip_prefix(prefix) {
+            // The marker some tools leave for the directory itself.
+            Some("") | None => continue,
+            Some(name) => name.to_owned(),
+        };
+
+        files.push(RemoteFile {
+            name,
+            len: first(object, "Size")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0),
+            modified: first(object, "LastModified")
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc).trunc_subsecs(0)),
+            created: None,
+        });
+    }
+
+    files
+}
+
+/// A time kept in user metadata, as seconds since 1970 like rclone and
+/// s3cmd write them, or in RFC 3339.
+fn meta_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(secs) = value.parse::<f64>() {
+        let nanos = (secs.fract() * 1e9) as u32;
+        return Utc.timestamp_opt(secs.trunc() as i64, nanos).single();
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curl::parse_response;
+
+    #[test]
+    fn lists_objects_as_a_head_request_shows_them() {
+        let listing = "<ListBucketResult><Contents><Key>photos/a.jpg</Key>\
+                       <LastModified>2024-03-05T12:10:00.123Z</LastModified>\
+                       <Size>3</Size></Contents></ListBucketResult>";
+        let head = "HTTP/1.1 200 OK\r\n\
+                    Last-Modified: Tue, 05 Mar 2024 12:10:00 GMT\r\n\
+                    Content-Length: 3\r\n\r\n";
+        let s3 = S3 {
+            bucket: "bucket".to_owned(),
+            prefix: "photos/".to_owned(),
+            region: "us-east-1".to_owned(),
+            endpoint: None,
+            access_key: String::new(),
+            secret_key: String::new(),
+            session_token: None,
+            meta_time: None,
+        };
+
+        let listed = listed(listing, &s3.prefix);
+        let looked_at = s3.remote_file("a.jpg".to_owned(), &parse_response(head).unwrap());
+
+        assert_eq!(listed, vec![looked_at]);
+        assert_eq!(
+            listed[0].modified,
+            Utc.with_ymd_and_hms(2024, 3, 5, 12, 10, 0).single()
+        );
+    }
+}
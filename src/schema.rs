@@ -0,0 +1,152 @@
+//! The JSON Schema of what `--output ndjson` and `crtime history export`
+//! print, as `crtime schema` shows it.
+//!
+//! Keep it in step with `Event::to_json` and `history::to_json`, and bump
+//! `event::SCHEMA_VERSION` as it says.
+
+pub const JSON_SCHEMA: &str = r##"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "crtime machine-readable output",
+  "description": "Every line of --output ndjson is an event; crtime history export --format json prints an array of runs. Objects may gain fields without schema_version changing.",
+  "oneOf": [
+    { "$ref": "#/$defs/event" },
+    { "type": "array", "items": { "$ref": "#/$defs/run" } }
+  ],
+  "$defs": {
+    "schema_version": { "const": 1 },
+    "path": { "type": "string" },
+    "code": {
+      "description": "Stable short code of the kind of problem; more may be added.",
+      "type": "string",
+      "pattern": "^E_[A-Z_]+$"
+    },
+    "event": {
+      "type": "object",
+      "required": ["schema_version", "event"],
+      "properties": {
+        "schema_version": { "$ref": "#/$defs/schema_version" },
+        "event": {
+          "enum": [
+            "planned", "skipped", "renamed", "rolled_back", "failed",
+            "cancelled", "interrupted", "stopped", "summary", "profile"
+          ]
+        }
+      },
+      "allOf": [
+        {
+          "if": { "properties": { "event": { "const": "planned" } } },
+          "then": {
+            "required": ["path", "new_path", "created"],
+            "properties": {
+              "path": { "$ref": "#/$defs/path" },
+              "new_path": { "$ref": "#/$defs/path" },
+              "created": { "type": "string", "format": "date-time" }
+            }
+          }
+        },
+        {
+          "if": { "properties": { "event": { "const": "skipped" } } },
+          "then": {
+            "required": ["phase", "path", "reason", "code"],
+            "properties": {
+              "phase": { "enum": ["plan", "apply"] },
+              "path": { "$ref": "#/$defs/path" },
+              "reason": { "type": "string" },
+              "code": { "$ref": "#/$defs/code" }
+            }
+          }
+        },
+        {
+          "if": { "properties": { "event": { "enum": ["renamed", "rolled_back"] } } },
+          "then": {
+            "required": ["path", "new_path"],
+            "properties": {
+              "path": { "$ref": "#/$defs/path" },
+              "new_path": { "$ref": "#/$defs/path" }
+            }
+          }
+        },
+        {
+          "if": { "properties": { "event": { "const": "failed" } } },
+          "then": {
+            "required": ["path", "new_path", "error", "code"],
+            "properties": {
+              "path": { "$ref": "#/$defs/path" },
+              "new_path": { "$ref": "#/$defs/path" },
+              "error": { "type": "string" },
+              "code": { "$ref": "#/$defs/code" }
+            }
+          }
+        },
+        {
+          "if": { "properties": { "event": { "const": "summary" } } },
+          "then": {
+            "required": ["scanned", "renamed", "skipped", "failed", "bytes_copied", "elapsed_ms"],
+            "properties": {
+              "scanned": { "type": "integer", "minimum": 0 },
+              "renamed": { "type": "integer", "minimum": 0 },
+              "skipped": {
+                "description": "Number of skipped files by reason.",
+                "type": "object",
+                "additionalProperties": { "type": "integer", "minimum": 0 }
+              },
+              "failed": { "type": "integer", "minimum": 0 },
+              "bytes_copied": { "type": "integer", "minimum": 0 },
+              "elapsed_ms": { "type": "integer", "minimum": 0 }
+            }
+          }
+        },
+        {
+          "if": { "properties": { "event": { "const": "profile" } } },
+          "then": {
+            "required": ["scanning_ms", "sources", "planning_ms", "applying_ms"],
+            "properties": {
+              "scanning_ms": { "type": "number" },
+              "sources": {
+                "description": "Time spent reading each source, by source name.",
+                "type": "object",
+                "additionalProperties": {
+                  "type": "object",
+                  "required": ["ms", "files"],
+                  "properties": {
+                    "ms": { "type": "number" },
+                    "files": { "type": "integer", "minimum": 0 }
+                  }
+                }
+              },
+              "planning_ms": { "type": "number" },
+              "applying_ms": { "type": "number" }
+            }
+          }
+        }
+      ]
+    },
+    "run": {
+      "type": "object",
+      "required": ["schema_version", "id", "kind", "target", "started", "ops"],
+      "properties": {
+        "schema_version": { "$ref": "#/$defs/schema_version" },
+        "id": { "type": "integer", "minimum": 1 },
+        "kind": { "enum": ["rename", "undo", "redo"] },
+        "target": {
+          "description": "The run undone or redone.",
+          "type": ["integer", "null"]
+        },
+        "started": { "type": ["string", "null"], "format": "date-time" },
+        "ops": {
+          "type": "array",
+          "items": {
+            "type": "object",
+            "required": ["op", "from", "to"],
+            "properties": {
+              "op": { "enum": ["move", "copy", "remove"] },
+              "from": { "$ref": "#/$defs/path" },
+              "to": { "type": ["string", "null"] }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"##;
@@ -0,0 +1,405 @@
+//! A small SFTP client, speaking version 3 of the protocol to the `sftp`
+//! subsystem of an `ssh` the system provides, which takes care of
+//! authentication and `~/.ssh/config`.
+//!
+//! Version 3 is what OpenSSH speaks. It keeps modification times in
+//! seconds and no creation times.
+
+use chrono::{DateTime, TimeZone, Utc};
+use std::io::{self, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::backend::{Backend, RemoteFile, Url};
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_LSTAT: u8 = 7;
+const SSH_FXP_OPENDIR: u8 = 11;
+const SSH_FXP_READDIR: u8 = 12;
+const SSH_FXP_MKDIR: u8 = 14;
+const SSH_FXP_RENAME: u8 = 18;
+const SSH_FXP_STATUS: u8 = 101;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FXP_NAME: u8 = 104;
+const SSH_FXP_ATTRS: u8 = 105;
+
+const SSH_FX_OK: u32 = 0;
+const SSH_FX_EOF: u32 = 1;
+const SSH_FX_NO_SUCH_FILE: u32 = 2;
+const SSH_FX_PERMISSION_DENIED: u32 = 3;
+
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x1;
+const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x2;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x4;
+const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x8;
+const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// The longest packet accepted, well above what servers send.
+const MAX_PACKET: usize = 1 << 24;
+
+pub struct Sftp {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    /// The remote directory, relative to the login directory unless it
+    /// starts with `/`.
+    root: String,
+    next_id: u32,
+}
+
+/// File attributes, as far as crtime needs them.
+#[derive(Default)]
+struct Attrs {
+    len: Option<u64>,
+    permissions: Option<u32>,
+    modified: Option<u32>,
+}
+
+impl Sftp {
+    /// Starts `ssh` for `url` and opens an SFTP session.
+    ///
+    /// A path starting with `~/`, as in `sftp://host/~/photos`, is taken
+    /// relative to the login directory.
+    pub fn connect(url: &Url) -> io::Result<Sftp> {
+        let mut command = Command::new("ssh");
+
+        if let Some(port) = url.port {
+            command.arg("-p").arg(port.to_string());
+        }
+
+        let host = match &url.user {
+            Some(user) => format!("{}@{}", user, url.host),
+            None => url.host.clone(),
+        };
+        // Ends the options, so that a host starting with `-` is not read as
+        // one.
+        let mut child = command
+            .arg("-s")
+            .arg("--")
+            .arg(host)
+            .arg("sftp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let root = match url.path.as_str() {
+            "" | "~" => ".".to_owned(),
+            path => match path.strip_prefix("~/") {
+                Some(path) => path.to_owned(),
+                None => format!("/{}", path),
+            },
+        };
+
+        let mut sftp = Sftp {
+            child,
+            stdin,
+            stdout,
+            root,
+            next_id: 0,
+        };
+
+        sftp.send(SSH_FXP_INIT, &3u32.to_be_bytes())?;
+
+        match sftp.receive()? {
+            (SSH_FXP_VERSION, _) => Ok(sftp),
+            _ => Err(protocol_error()),
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("{}/{}", self.root, name)
+    }
+
+    fn send(&mut self, kind: u8, body: &[u8]) -> io::Result<()> {
+        let stdin = self.stdin.as_mut().expect("session is open");
+        let mut packet = Vec::with_capacity(body.len() + 5);
+        packet.extend_from_slice(&(body.len() as u32 + 1).to_be_bytes());
+        packet.push(kind);
+        packet.extend_from_slice(body);
+
+        stdin.write_all(&packet)?;
+        stdin.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut len = [0; 4];
+        self.stdout.read_exact(&mut len).map_err(|error| {
+            if error.kind() == io::ErrorKind::UnexpectedEof {
+                io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "ssh closed the connection",
+                )
+            } else {
+                error
+            }
+        })?;
+
+        let len = u32::from_be_bytes(len) as usize;
+
+        if len == 0 || len > MAX_PACKET {
+            return Err(protocol_error());
+        }
+
+        let mut packet = vec![0; len];
+        self.stdout.read_exact(&mut packet)?;
+        let kind = packet.remove(0);
+
+        Ok((kind, packet))
+    }
+
+    /// Sends a request of `kind` with `fields` and returns the type and
+    /// fields of the response.
+    fn request(&mut self, kind: u8, fields: &[u8]) -> io::Result<(u8, Reader)> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut body = id.to_be_bytes().to_vec();
+        body.extend_from_slice(fields);
+        self.send(kind, &body)?;
+
+        let (kind, packet) = self.receive()?;
+        let mut reader = Reader(packet, 0);
+
+        if reader.u32()? != id {
+            return Err(protocol_error());
+        }
+
+        Ok((kind, reader))
+    }
+
+    /// Sends a request answered by a status, and fails unless it is OK.
+    fn expect_ok(&mut self, kind: u8, fields: &[u8]) -> io::Result<()> {
+        match self.request(kind, fields)? {
+            (SSH_FXP_STATUS, mut reader) => reader.status(),
+            _ => Err(protocol_error()),
+        }
+    }
+
+    fn lstat(&mut self, path: &str) -> io::Result<Option<Attrs>> {
+        match self.request(SSH_FXP_LSTAT, &string(path))? {
+            (SSH_FXP_ATTRS, mut reader) => Ok(Some(reader.attrs()?)),
+            (SSH_FXP_STATUS, mut reader) => match reader.status() {
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error),
+                Ok(()) => Err(protocol_error()),
+            },
+            _ => Err(protocol_error()),
+        }
+    }
+
+    /// Creates the directory `name` and those above it, up to the root.
+    fn create_dirs(&mut self, name: &str) -> io::Result<()> {
+        let mut dir = String::new();
+
+        for part in name.split('/') {
+            if !dir.is_empty() {
+                dir.push('/');
+            }
+            dir.push_str(part);
+
+            let path = self.path(&dir);
+
+            if self.lstat(&path)?.is_none() {
+                let mut fields = string(&path);
+                // No attributes.
+                fields.extend_from_slice(&0u32.to_be_bytes());
+                self.expect_ok(SSH_FXP_MKDIR, &fields)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Backend for Sftp {
+    fn list(&mut self) -> io::Result<Vec<RemoteFile>> {
+        let root = self.root.clone();
+        let handle = match self.request(SSH_FXP_OPENDIR, &string(&root))? {
+            (SSH_FXP_HANDLE, mut reader) => reader.bytes()?,
+            (SSH_FXP_STATUS, mut reader) => {
+                reader.status()?;
+                return Err(protocol_error());
+            }
+            _ => return Err(protocol_error()),
+        };
+        let mut handle_field = (handle.len() as u32).to_be_bytes().to_vec();
+        handle_field.extend_from_slice(&handle);
+
+        let mut files = Vec::new();
+
+        loop {
+            match self.request(SSH_FXP_READDIR, &handle_field)? {
+                (SSH_FXP_NAME, mut reader) => {
+                    for _ in 0..reader.u32()? {
+                        let name = reader.bytes()?;
+                        let _long_name = reader.bytes()?;
+                        let attrs = reader.attrs()?;
+
+                        // Names that are not UTF-8 could not be renamed
+                        // back to themselves, so those files are left out.
+                        let name = match String::from_utf8(name) {
+                            Ok(name) => name,
+                            Err(_) => continue,
+                        };
+
+                        if attrs
+                            .permissions
+                            .is_some_and(|mode| mode & S_IFMT == S_IFREG)
+                        {
+                            files.push(remote_file(name, &attrs));
+                        }
+                    }
+                }
+                (SSH_FXP_STATUS, mut reader) => match reader.status() {
+                    Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(error) => return Err(error),
+                    Ok(()) => return Err(protocol_error()),
+                },
+                _ => return Err(protocol_error()),
+            }
+        }
+
+        self.expect_ok(SSH_FXP_CLOSE, &handle_field)?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(files)
+    }
+
+    fn stat(&mut self, name: &str) -> io::Result<Option<RemoteFile>> {
+        let path = self.path(name);
+
+        Ok(self
+            .lstat(&path)?
+            .map(|attrs| remote_file(name.to_owned(), &attrs)))
+    }
+
+    /// Renames with `SSH_FXP_RENAME`, which servers refuse when something
+    /// is at `to`.
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        if let Some((dir, _)) = to.rsplit_once('/') {
+            self.create_dirs(dir)?;
+        }
+
+        let mut fields = string(&self.path(from));
+        fields.extend_from_slice(&string(&self.path(to)));
+
+        self.expect_ok(SSH_FXP_RENAME, &fields)
+    }
+}
+
+impl Drop for Sftp {
+    fn drop(&mut self) {
+        // Closing its input ends the session and `ssh`.
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+fn remote_file(name: String, attrs: &Attrs) -> RemoteFile {
+    RemoteFile {
+        name,
+        len: attrs.len.unwrap_or(0),
+        modified: attrs.modified.and_then(time),
+        created: None,
+    }
+}
+
+fn time(secs: u32) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(i64::from(secs), 0).single()
+}
+
+/// An SFTP string field.
+fn string(text: &str) -> Vec<u8> {
+    let mut field = (text.len() as u32).to_be_bytes().to_vec();
+    field.extend_from_slice(text.as_bytes());
+    field
+}
+
+fn protocol_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "unexpected SFTP response")
+}
+
+/// The fields of a packet, read from the front.
+struct Reader(Vec<u8>, usize);
+
+impl Reader {
+    fn take(&mut self, len: usize) -> io::Result<&[u8]> {
+        let start = self.1;
+        let end = start.checked_add(len).ok_or_else(protocol_error)?;
+        let bytes = self.0.get(start..end).ok_or_else(protocol_error)?;
+        self.1 = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok((u64::from(self.u32()?) << 32) | u64::from(self.u32()?))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn attrs(&mut self) -> io::Result<Attrs> {
+        let flags = self.u32()?;
+        let mut attrs = Attrs::default();
+
+        if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+            attrs.len = Some(self.u64()?);
+        }
+
+        if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+            self.take(8)?;
+        }
+
+        if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(self.u32()?);
+        }
+
+        if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            let _accessed = self.u32()?;
+            attrs.modified = Some(self.u32()?);
+        }
+
+        if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            for _ in 0..self.u32()? {
+                self.bytes()?;
+                self.bytes()?;
+            }
+        }
+
+        Ok(attrs)
+    }
+
+    /// The status following the request id, as a result. The end of a
+    /// listing comes as `io::ErrorKind::UnexpectedEof`.
+    fn status(&mut self) -> io::Result<()> {
+        let code = self.u32()?;
+        let message = String::from_utf8_lossy(&self.bytes().unwrap_or_default()).into_owned();
+
+        let kind = match code {
+            SSH_FX_OK => return Ok(()),
+            SSH_FX_EOF => io::ErrorKind::UnexpectedEof,
+            SSH_FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+            SSH_FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+            _ => io::ErrorKind::Other,
+        };
+        let message = match message.as_str() {
+            "" => format!("SFTP error {}", code),
+            _ => message,
+        };
+
+        Err(io::Error::new(kind, message))
+    }
+}
@@ -0,0 +1,602 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Timelike, Utc};
+use std::fmt;
+use std::fs;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::btime;
+use crate::cache::TimeCache;
+use crate::dst;
+use crate::exif::{self, Exif, Ifd};
+use crate::fs_kind::FsKind;
+use crate::FsItemError;
+
+/// Places a file's creation time can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Source {
+    /// Filesystem birth time.
+    Btime,
+    /// Last modification time.
+    Mtime,
+    /// Last status change time (Unix only).
+    Ctime,
+    /// EXIF `DateTimeOriginal` of photos.
+    Exif,
+    /// A date and time written in the file name.
+    Filename,
+    /// `DateTimeOriginal` or `CreateDate` in an XMP sidecar file.
+    Sidecar,
+}
+
+impl Source {
+    pub const ALL: [Source; 6] = [
+        Source::Btime,
+        Source::Mtime,
+        Source::Ctime,
+        Source::Exif,
+        Source::Filename,
+        Source::Sidecar,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Btime => "btime",
+            Source::Mtime => "mtime",
+            Source::Ctime => "ctime",
+            Source::Exif => "exif",
+            Source::Filename => "filename",
+            Source::Sidecar => "sidecar",
+        }
+    }
+
+    /// Whether the filesystem itself keeps this timestamp.
+    fn is_filesystem(self) -> bool {
+        matches!(self, Source::Btime | Source::Mtime | Source::Ctime)
+    }
+
+    /// Reads this timestamp of the file at `path`.
+    pub fn read(
+        self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+    ) -> Result<DateTime<Utc>, FsItemError> {
+        let time = match self {
+            Source::Btime => DateTime::<Utc>::from(btime::birth_time(path, meta)?),
+            Source::Mtime => DateTime::<Utc>::from(meta.modified()?),
+            Source::Ctime => ctime(meta)?,
+            Source::Exif => match Exif::read(path)?
+                .and_then(|exif| exif_recorded(&exif))
+                .and_then(|recorded| recorded_time(&recorded))
+            {
+                Some(time) => time,
+                None => return Err(FsItemError::NoTimestamp(self)),
+            },
+            Source::Filename => {
+                let name = path.file_name().map(|name| name.to_string_lossy());
+                match name.and_then(|name| filename_time(&name)) {
+                    Some(time) => time,
+                    None => return Err(FsItemError::NoTimestamp(self)),
+                }
+            }
+            Source::Sidecar => match sidecar_time(path) {
+                Some(time) => time,
+                None => return Err(FsItemError::NoTimestamp(self)),
+            },
+        };
+
+        if self.is_filesystem() {
+            Ok(fs_kind.normalize(time))
+        } else {
+            Ok(time)
+        }
+    }
+
+    /// Like `read`, giving up after `timeout` for sources that parse the
+    /// contents of the file, which a damaged file can keep busy for long.
+    /// The read carries on in the background then, its result unused.
+    pub fn read_within(
+        self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+        timeout: Option<Duration>,
+    ) -> Result<DateTime<Utc>, FsItemError> {
+        if !matches!(self, Source::Exif | Source::Sidecar) {
+            return self.read(path, meta, fs_kind);
+        }
+
+        let (owned_path, owned_meta) = (path.to_path_buf(), meta.clone());
+        self.within(timeout, move || {
+            self.read(&owned_path, &owned_meta, fs_kind)
+        })
+    }
+
+    /// Like `read_within`, taking the EXIF time from `read.cache` when it
+    /// has the file's and keeping it there when not.
+    pub fn read_with(
+        self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+        read: &ReadOptions,
+    ) -> Result<DateTime<Utc>, FsItemError> {
+        let cache = match read.cache {
+            Some(cache) if self == Source::Exif => cache,
+            _ => return self.read_within(path, meta, fs_kind, read.timeout),
+        };
+
+        let recorded = match cache.get(self, path, meta) {
+            Some(recorded) => recorded,
+            None => {
+                let owned_path = path.to_path_buf();
+                let recorded = self.within(read.timeout, move || {
+                    Ok(Exif::read(&owned_path)?.and_then(|exif| exif_recorded(&exif)))
+                })?;
+                cache.put(self, path, meta, recorded.clone());
+                recorded
+            }
+        };
+
+        match recorded.as_deref().and_then(recorded_time) {
+            Some(time) => Ok(time),
+            None => Err(FsItemError::NoTimestamp(self)),
+        }
+    }
+
+    /// Runs `read` on a thread of its own when there is a `timeout` to keep
+    /// to, failing with `TimedOut` once it is over.
+    fn within<T: Send + 'static>(
+        self,
+        timeout: Option<Duration>,
+        read: impl FnOnce() -> Result<T, FsItemError> + Send + 'static,
+    ) -> Result<T, FsItemError> {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return read(),
+        };
+
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let _ = sender.send(read());
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(_) => Err(FsItemError::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "reading the {} timestamp took over {} s",
+                    self,
+                    timeout.as_secs_f64()
+                ),
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Source {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Source::ALL
+            .iter()
+            .cloned()
+            .find(|source| source.name() == s)
+            .ok_or(())
+    }
+}
+
+/// Settings for reading timestamps.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions<'a> {
+    /// Longest a source parsing the contents of a file may take, as
+    /// `Source::read_within` tells.
+    pub timeout: Option<Duration>,
+    /// EXIF times read on earlier runs.
+    pub cache: Option<&'a TimeCache>,
+}
+
+/// One step of a source chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// The timestamp of a single source.
+    Single(Source),
+    /// The earliest timestamp any of the sources has.
+    Earliest(Vec<Source>),
+}
+
+impl Strategy {
+    /// Reads the timestamp this step picks, reporting how long each source
+    /// took to `on_read`.
+    fn resolve(
+        &self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+        read: &ReadOptions,
+        on_read: &mut dyn FnMut(Source, Duration),
+    ) -> Result<(Source, DateTime<Utc>), FsItemError> {
+        let sources = match self {
+            Strategy::Single(source) => std::slice::from_ref(source),
+            Strategy::Earliest(sources) => sources.as_slice(),
+        };
+        let mut earliest: Option<(Source, DateTime<Utc>)> = None;
+        let mut last_error = FsItemError::NoTimestamp(Source::Btime);
+
+        for &source in sources {
+            let started = Instant::now();
+            let result = source.read_with(path, meta, fs_kind, read);
+            on_read(source, started.elapsed());
+
+            match result {
+                Ok(time) if earliest.is_none_or(|(_, earliest)| time < earliest) => {
+                    earliest = Some((source, time))
+                }
+                Ok(_) => {}
+                Err(error) => last_error = error,
+            }
+        }
+
+        earliest.ok_or(last_error)
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strategy::Single(source) => write!(f, "{}", source),
+            Strategy::Earliest(sources) => {
+                let names: Vec<_> = sources.iter().map(|source| source.name()).collect();
+                write!(f, "earliest({})", names.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = ();
+
+    /// Parses a source name, or `earliest(…)` around a comma-separated
+    /// list of them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        match s
+            .strip_prefix("earliest(")
+            .and_then(|s| s.strip_suffix(')'))
+        {
+            Some(list) => {
+                let sources = list
+                    .split(',')
+                    .map(|name| name.trim().parse())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Strategy::Earliest(sources))
+            }
+            None => s.parse().map(Strategy::Single),
+        }
+    }
+}
+
+/// Steps tried in order until one yields a timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceChain(pub Vec<Strategy>);
+
+impl Default for SourceChain {
+    fn default() -> Self {
+        SourceChain(vec![Strategy::Single(Source::Btime)])
+    }
+}
+
+impl FromStr for SourceChain {
+    type Err = ();
+
+    /// Parses a comma-separated list such as `exif,earliest(btime,mtime)`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return Err(()),
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    steps.push(s[start..i].parse()?);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+
+        if depth != 0 {
+            return Err(());
+        }
+
+        steps.push(s[start..].parse()?);
+
+        Ok(SourceChain(steps))
+    }
+}
+
+impl fmt::Display for SourceChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps: Vec<_> = self.0.iter().map(|step| step.to_string()).collect();
+        write!(f, "{}", steps.join(","))
+    }
+}
+
+impl SourceChain {
+    /// Whether any step reads a timestamp the filesystem keeps.
+    pub fn reads_filesystem(&self) -> bool {
+        self.0.iter().any(|step| match step {
+            Strategy::Single(source) => source.is_filesystem(),
+            Strategy::Earliest(sources) => sources.iter().any(|source| source.is_filesystem()),
+        })
+    }
+
+    /// The first timestamp found, and where it came from.
+    ///
+    /// When no step has one, the error of the last source is returned.
+    pub fn resolve(
+        &self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+    ) -> Result<(Source, DateTime<Utc>), FsItemError> {
+        self.resolve_timed(path, meta, fs_kind, &mut |_, _| {})
+    }
+
+    /// Like `resolve`, reporting how long each source took to `on_read`.
+    pub fn resolve_timed(
+        &self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+        on_read: &mut dyn FnMut(Source, Duration),
+    ) -> Result<(Source, DateTime<Utc>), FsItemError> {
+        self.resolve_within(path, meta, fs_kind, &ReadOptions::default(), on_read)
+    }
+
+    /// Like `resolve_timed`, reading as `read` says: going on to the next
+    /// step when a source takes longer than its timeout, as
+    /// `Source::read_within` tells, and with EXIF times cached.
+    pub fn resolve_within(
+        &self,
+        path: &Path,
+        meta: &Metadata,
+        fs_kind: FsKind,
+        read: &ReadOptions,
+        on_read: &mut dyn FnMut(Source, Duration),
+    ) -> Result<(Source, DateTime<Utc>), FsItemError> {
+        let mut last_error = FsItemError::NoTimestamp(Source::Btime);
+
+        for step in &self.0 {
+            match step.resolve(path, meta, fs_kind, read, on_read) {
+                Ok(picked) => return Ok(picked),
+                Err(error) => last_error = error,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Whether any step reads `source`.
+    pub fn uses(&self, source: Source) -> bool {
+        self.0.iter().any(|step| match step {
+            Strategy::Single(single) => *single == source,
+            Strategy::Earliest(sources) => sources.contains(&source),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn ctime(meta: &Metadata) -> Result<DateTime<Utc>, FsItemError> {
+    use chrono::TimeZone;
+    use std::os::unix::fs::MetadataExt;
+
+    Utc.timestamp_opt(meta.ctime(), meta.ctime_nsec() as u32)
+        .single()
+        .ok_or(FsItemError::NoTimestamp(Source::Ctime))
+}
+
+#[cfg(not(unix))]
+fn ctime(_meta: &Metadata) -> Result<DateTime<Utc>, FsItemError> {
+    Err(FsItemError::NoTimestamp(Source::Ctime))
+}
+
+/// Interprets a wall-clock time in the local timezone, by the
+/// `dst::DstPolicy` set.
+fn local_to_utc(naive: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    dst::to_utc(&Local, naive)
+}
+
+/// Capture time of a photo as recorded, in RFC 3339 with its offset or,
+/// where it has none, without.
+fn exif_recorded(exif: &Exif) -> Option<String> {
+    let (text, sub_sec) = match exif.ascii(Ifd::Exif, exif::TAG_DATE_TIME_ORIGINAL) {
+        Some(text) => (text, exif::TAG_SUB_SEC_TIME_ORIGINAL),
+        None => (
+            exif.ascii(Ifd::Exif, exif::TAG_DATE_TIME_DIGITIZED)?,
+            exif::TAG_SUB_SEC_TIME_DIGITIZED,
+        ),
+    };
+
+    let mut naive = NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S").ok()?;
+
+    // Fractions of a second are kept apart, as digits after the decimal
+    // point.
+    if let Some(digits) = exif.ascii(Ifd::Exif, sub_sec) {
+        let digits = &digits[..digits.len().min(9)];
+
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            let nanos = format!("{:0<9}", digits).parse().unwrap_or(0);
+            naive = naive.with_nanosecond(nanos).unwrap_or(naive);
+        }
+    }
+
+    let text = naive.format(RECORDED_FORMAT).to_string();
+
+    match exif.ascii(Ifd::Exif, exif::TAG_OFFSET_TIME_ORIGINAL) {
+        Some(offset) => DateTime::parse_from_rfc3339(&format!("{}{}", text, offset))
+            .ok()
+            .map(|time| time.to_rfc3339()),
+        None => Some(text),
+    }
+}
+
+/// How `exif_recorded` writes a time without an offset.
+const RECORDED_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// The instant a time from `exif_recorded` stands for, in local time
+/// unless it has an offset.
+fn recorded_time(recorded: &str) -> Option<DateTime<Utc>> {
+    match DateTime::parse_from_rfc3339(recorded) {
+        Ok(time) => Some(time.with_timezone(&Utc)),
+        Err(_) => local_to_utc(&NaiveDateTime::parse_from_str(recorded, RECORDED_FORMAT).ok()?),
+    }
+}
+
+/// Finds a date like `20240305_121000`, `2024-03-05 12.10.00` or
+/// `2024-03-05` in a file name, read as local time.
+pub fn filename_time(name: &str) -> Option<DateTime<Utc>> {
+    let bytes = name.as_bytes();
+
+    (0..bytes.len())
+        .filter(|&i| i == 0 || !bytes[i - 1].is_ascii_digit())
+        .find_map(|i| parse_date_at(&bytes[i..]))
+        .and_then(|naive| local_to_utc(&naive))
+}
+
+fn parse_date_at(bytes: &[u8]) -> Option<NaiveDateTime> {
+    let mut pos = 0;
+    let mut fields = [0u32; 6];
+    let widths = [4, 2, 2, 2, 2, 2];
+    let mut parsed = 0;
+    let mut date_end = 0;
+
+    for (field, &width) in widths.iter().enumerate() {
+        if field > 0 {
+            if let Some(&b) = bytes.get(pos) {
+                let separators: &[u8] = if field == 3 { b"_-T ." } else { b"_-.:" };
+                if separators.contains(&b) {
+                    pos += 1;
+                }
+            }
+        }
+
+        let digits = bytes.get(pos..pos + width)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            break;
+        }
+
+        fields[field] = digits
+            .iter()
+            .fold(0, |acc, &d| acc * 10 + u32::from(d - b'0'));
+        pos += width;
+        parsed += 1;
+
+        if parsed == 3 {
+            date_end = pos;
+        }
+    }
+
+    // A full date and time, or else just the date, with no digits running on.
+    let end = match parsed {
+        6 => pos,
+        3..=5 => {
+            fields[3..].iter_mut().for_each(|field| *field = 0);
+            date_end
+        }
+        _ => return None,
+    };
+
+    if bytes.get(end).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+
+    if !(1970..2100).contains(&fields[0]) {
+        return None;
+    }
+
+    NaiveDate::from_ymd_opt(fields[0] as i32, fields[1], fields[2])?
+        .and_hms_opt(fields[3], fields[4], fields[5])
+}
+
+/// XMP sidecars of `path`: `photo.jpg.xmp` and `photo.xmp`.
+pub fn sidecar_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if let Some(name) = path.file_name() {
+        let mut with_suffix = name.to_owned();
+        with_suffix.push(".xmp");
+        paths.push(path.with_file_name(with_suffix));
+    }
+
+    paths.push(path.with_extension("xmp"));
+    paths.push(path.with_extension("XMP"));
+    paths.retain(|sidecar| sidecar != path);
+    paths
+}
+
+fn sidecar_time(path: &Path) -> Option<DateTime<Utc>> {
+    const KEYS: &[&str] = &[
+        "exif:DateTimeOriginal",
+        "xmp:CreateDate",
+        "photoshop:DateCreated",
+    ];
+
+    let xmp = sidecar_paths(path)
+        .iter()
+        .find_map(|sidecar| fs::read_to_string(sidecar).ok())?;
+
+    KEYS.iter()
+        .filter_map(|key| xmp_value(&xmp, key))
+        .find_map(parse_iso_time)
+}
+
+/// Value of `key`, written either as an attribute or as an element.
+fn xmp_value<'a>(xmp: &'a str, key: &str) -> Option<&'a str> {
+    let start = xmp.find(key)? + key.len();
+    let rest = xmp[start..].trim_start();
+
+    if let Some(rest) = rest.strip_prefix('=') {
+        let rest = rest.trim_start();
+        let quote = rest.chars().next()?;
+        let rest = &rest[quote.len_utf8()..];
+        rest.find(quote).map(|end| &rest[..end])
+    } else if let Some(rest) = rest.strip_prefix('>') {
+        rest.find('<').map(|end| rest[..end].trim())
+    } else {
+        None
+    }
+}
+
+pub(crate) fn parse_iso_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .and_then(|naive| local_to_utc(&naive))
+}
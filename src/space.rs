@@ -0,0 +1,141 @@
+//! Whether the files a plan copies fit on the disks they go to, checked
+//! before starting rather than finding out halfway through.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::summary::format_bytes;
+use crate::transfer::TransferMode;
+use crate::RenamePlanItem;
+
+/// Fails with `StorageFull` unless copying the files of `items` leaves
+/// room to spare on every filesystem written to. In `Move` mode only files
+/// moved across filesystems count, as the others are just renamed.
+///
+/// Filesystems whose free space cannot be told are assumed to have enough.
+pub fn check(items: &[RenamePlanItem], mode: TransferMode) -> io::Result<()> {
+    let mut dirs: HashMap<PathBuf, Option<(PathBuf, Option<u64>)>> = HashMap::new();
+    let mut needed: HashMap<Result<u64, PathBuf>, (PathBuf, u64)> = HashMap::new();
+
+    for item in items {
+        let (dir, volume) = match target(&mut dirs, &item.new_path) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        if mode == TransferMode::Move {
+            let source = item.path.parent().and_then(volume_of);
+
+            // Without a way to tell, moves are taken as renames.
+            if volume.is_none() || source == volume {
+                continue;
+            }
+        }
+
+        // Directories on unknown filesystems are counted apart.
+        let key = volume.ok_or_else(|| dir.clone());
+        let entry = needed.entry(key).or_insert((dir, 0));
+        entry.1 += item.stamp.len;
+    }
+
+    for (dir, bytes) in needed.values() {
+        let available = match available(dir) {
+            Ok(available) => available,
+            Err(_) => continue,
+        };
+        let wanted = bytes + margin(*bytes);
+
+        if wanted > available {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "Not enough free space in {}: {} needed, {} available",
+                    dir.display(),
+                    format_bytes(wanted),
+                    format_bytes(available)
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Room left besides the files themselves, for the filesystem's own
+/// bookkeeping and so the disk is not filled to the last byte.
+fn margin(bytes: u64) -> u64 {
+    bytes / 20 + 1024 * 1024
+}
+
+/// The closest directory of `path` that exists and the filesystem it is
+/// on, looked up once per directory.
+fn target(
+    dirs: &mut HashMap<PathBuf, Option<(PathBuf, Option<u64>)>>,
+    path: &Path,
+) -> Option<(PathBuf, Option<u64>)> {
+    let parent = path.parent()?;
+
+    dirs.entry(parent.to_path_buf())
+        .or_insert_with(|| {
+            let dir = parent.ancestors().find(|dir| dir.is_dir())?;
+            Some((dir.to_path_buf(), volume_of(dir)))
+        })
+        .clone()
+}
+
+#[cfg(unix)]
+fn volume_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    path.metadata().ok().map(|meta| meta.dev())
+}
+
+#[cfg(not(unix))]
+fn volume_of(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Bytes free for the user in the filesystem holding `dir`.
+#[cfg(unix)]
+pub fn available(dir: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+    let mut buf: libc::statvfs = unsafe { mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_dir.as_ptr(), &mut buf) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(buf.f_bavail as u64 * buf.f_frsize as u64)
+}
+
+#[cfg(windows)]
+pub fn available(dir: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut free = 0;
+
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut free, ptr::null_mut(), ptr::null_mut()) }
+        == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(free)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn available(_dir: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space cannot be told on this platform",
+    ))
+}
@@ -0,0 +1,188 @@
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Whether the file `meta` describes has holes, taking up less space than
+/// its length.
+#[cfg(unix)]
+pub fn is_sparse(meta: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    meta.is_file() && meta.blocks() * 512 < meta.len()
+}
+
+#[cfg(windows)]
+pub fn is_sparse(meta: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+
+    meta.is_file() && meta.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn is_sparse(_meta: &Metadata) -> bool {
+    false
+}
+
+/// Copies the contents and permissions of `from` to `to`, writing only
+/// the parts holding data so that the holes stay holes, and returns the
+/// number of bytes written.
+pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
+    let mut source = File::open(from)?;
+    let meta = source.metadata()?;
+    let mut copy = File::create(to)?;
+    let mut written = 0;
+
+    mark_sparse(&copy)?;
+
+    for (start, end) in data_ranges(&source, meta.len())? {
+        source.seek(SeekFrom::Start(start))?;
+        copy.seek(SeekFrom::Start(start))?;
+        written += io::copy(&mut (&mut source).take(end - start), &mut copy)?;
+    }
+
+    copy.set_len(meta.len())?;
+    copy.set_permissions(meta.permissions())?;
+
+    Ok(written)
+}
+
+/// Start and end offsets of the parts of `file` holding data, found with
+/// `SEEK_DATA` and `SEEK_HOLE`. The whole file counts as data where these
+/// are not supported.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn data_ranges(file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+
+    while offset < len {
+        let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+
+        if start < 0 {
+            return match io::Error::last_os_error().raw_os_error() {
+                // Only a hole is left.
+                Some(libc::ENXIO) => Ok(ranges),
+                Some(libc::EINVAL) if ranges.is_empty() => Ok(vec![(0, len)]),
+                _ => Err(io::Error::last_os_error()),
+            };
+        }
+
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+
+        if end < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        ranges.push((start as u64, (end as u64).min(len)));
+        offset = end as u64;
+    }
+
+    Ok(ranges)
+}
+
+/// Start and end offsets of the allocated parts of `file`.
+#[cfg(windows)]
+fn data_ranges(file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::windows::io::AsRawHandle;
+    use std::{mem, ptr};
+    use windows_sys::Win32::Foundation::ERROR_MORE_DATA;
+    use windows_sys::Win32::System::Ioctl::{
+        FILE_ALLOCATED_RANGE_BUFFER, FSCTL_QUERY_ALLOCATED_RANGES,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    let mut ranges = Vec::new();
+    let mut query = FILE_ALLOCATED_RANGE_BUFFER {
+        FileOffset: 0,
+        Length: len as i64,
+    };
+    let mut found = vec![
+        FILE_ALLOCATED_RANGE_BUFFER {
+            FileOffset: 0,
+            Length: 0,
+        };
+        64
+    ];
+
+    loop {
+        let mut returned = 0;
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle() as _,
+                FSCTL_QUERY_ALLOCATED_RANGES,
+                (&query as *const FILE_ALLOCATED_RANGE_BUFFER).cast(),
+                mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>() as u32,
+                found.as_mut_ptr().cast(),
+                (found.len() * mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>()) as u32,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        let more =
+            ok == 0 && io::Error::last_os_error().raw_os_error() == Some(ERROR_MORE_DATA as i32);
+
+        if ok == 0 && !more {
+            return Err(io::Error::last_os_error());
+        }
+
+        let count = returned as usize / mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>();
+
+        for range in &found[..count] {
+            let start = range.FileOffset as u64;
+            ranges.push((start, start + range.Length as u64));
+        }
+
+        match ranges.last() {
+            Some(&(_, end)) if more => {
+                query.FileOffset = end as i64;
+                query.Length = (len - end) as i64;
+            }
+            _ => return Ok(ranges),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn data_ranges(_file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    Ok(vec![(0, len)])
+}
+
+/// Lets `file` have holes, which on Windows must be asked for, unlike
+/// elsewhere.
+#[cfg(windows)]
+fn mark_sparse(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_SPARSE;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    let mut returned = 0;
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle() as _,
+            FSCTL_SET_SPARSE,
+            ptr::null(),
+            0,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn mark_sparse(_file: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::fs;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Identity and state of a file when it was planned.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Device and inode number, where the platform exposes them.
+    pub inode: Option<(u64, u64)>,
+}
+
+/// Why a planned file was left alone when applying.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleReason {
+    Missing,
+    Replaced,
+    Modified,
+    /// Whether it changed could not be told, as when a remote directory
+    /// did not answer.
+    Unknown,
+}
+
+impl fmt::Display for StaleReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StaleReason::Missing => write!(f, "file was removed since planning"),
+            StaleReason::Replaced => write!(f, "file was replaced since planning"),
+            StaleReason::Modified => write!(f, "file was modified since planning"),
+            StaleReason::Unknown => write!(f, "file could not be checked against the plan"),
+        }
+    }
+}
+
+impl StaleReason {
+    /// Stable short code of the reason, like `FsItemError::code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            StaleReason::Missing => "E_MISSING",
+            StaleReason::Replaced => "E_REPLACED",
+            StaleReason::Modified => "E_MODIFIED",
+            StaleReason::Unknown => "E_UNCHECKED",
+        }
+    }
+}
+
+impl FileStamp {
+    pub fn of(meta: &Metadata) -> FileStamp {
+        FileStamp {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            inode: inode(meta),
+        }
+    }
+
+    /// Compares the contents of the file now at `path` with this stamp,
+    /// by size and modification time only, as for a copy of the file.
+    pub fn check_contents(&self, path: &Path) -> Result<(), StaleReason> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.len() == self.len && meta.modified().ok() == self.modified => Ok(()),
+            Ok(_) => Err(StaleReason::Modified),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Err(StaleReason::Missing),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Compares the file now at `path` with this stamp.
+    pub fn check(&self, path: &Path) -> Result<(), StaleReason> {
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(StaleReason::Missing)
+            }
+            // Let the rename itself report any other problem.
+            Err(_) => return Ok(()),
+        };
+
+        let current = FileStamp::of(&meta);
+
+        if current.inode != self.inode {
+            Err(StaleReason::Replaced)
+        } else if current != *self {
+            Err(StaleReason::Modified)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for FileStamp {
+    /// Writes `len:seconds.nanos:dev:ino`, with `-` for unknown parts.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.len)?;
+
+        match self
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        {
+            Some(since) => write!(f, "{}.{:09}:", since.as_secs(), since.subsec_nanos())?,
+            None => write!(f, "-:")?,
+        }
+
+        match self.inode {
+            Some((dev, ino)) => write!(f, "{}:{}", dev, ino),
+            None => write!(f, "-:-"),
+        }
+    }
+}
+
+impl FromStr for FileStamp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(':').collect();
+
+        let (len, modified, dev, ino) = match parts[..] {
+            [len, modified, dev, ino] => (len, modified, dev, ino),
+            _ => return Err(()),
+        };
+
+        let modified = match modified {
+            "-" => None,
+            modified => {
+                let (secs, nanos) = modified.split_once('.').ok_or(())?;
+                let since = Duration::new(
+                    secs.parse().map_err(|_| ())?,
+                    nanos.parse().map_err(|_| ())?,
+                );
+                Some(UNIX_EPOCH + since)
+            }
+        };
+
+        let inode = match (dev, ino) {
+            ("-", "-") => None,
+            (dev, ino) => Some((dev.parse().map_err(|_| ())?, ino.parse().map_err(|_| ())?)),
+        };
+
+        Ok(FileStamp {
+            len: len.parse().map_err(|_| ())?,
+            modified,
+            inode,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::Plan;
+
+/// Bucket size of the creation time histogram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Period {
+    Day,
+    #[default]
+    Month,
+    Year,
+}
+
+impl FromStr for Period {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "month" => Ok(Period::Month),
+            "year" => Ok(Period::Year),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Period {
+    fn label(self, time: &DateTime<Utc>) -> String {
+        let format = match self {
+            Period::Day => "%Y-%m-%d",
+            Period::Month => "%Y-%m",
+            Period::Year => "%Y",
+        };
+
+        time.format(format).to_string()
+    }
+}
+
+/// Overview of the files in a directory.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// File counts per period, oldest first.
+    pub histogram: BTreeMap<String, usize>,
+    /// File counts per lowercased extension, `""` for none.
+    pub extensions: BTreeMap<String, usize>,
+    /// Files whose creation time could not be read.
+    pub unreadable: usize,
+}
+
+impl Stats {
+    pub fn new(plan: &Plan, period: Period) -> Stats {
+        let mut stats = Stats::default();
+
+        for item in &plan.items {
+            *stats
+                .histogram
+                .entry(period.label(&item.created))
+                .or_insert(0) += 1;
+
+            let extension = Path::new(&item.name)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            *stats.extensions.entry(extension).or_insert(0) += 1;
+        }
+
+        stats.unreadable = plan.skipped.len();
+        stats
+    }
+}
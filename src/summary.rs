@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::{FsItemError, Plan, RenameOutcome};
+
+/// Counts describing a whole run.
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Files considered, whether planned or not.
+    pub scanned: usize,
+    pub renamed: usize,
+    /// Files left out while planning or applying, by reason.
+    pub skipped: BTreeMap<String, usize>,
+    pub failed: usize,
+    /// Files already named as planned.
+    pub unchanged: usize,
+    pub bytes_copied: u64,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    pub fn new(plan: &Plan, outcome: &RenameOutcome, elapsed: Duration) -> Summary {
+        let mut skipped = BTreeMap::new();
+
+        for item in &plan.skipped {
+            let reason = match item.reason {
+                // Group I/O errors by kind rather than by full message.
+                FsItemError::Io(ref error) => error.kind().to_string(),
+                ref reason => reason.to_string(),
+            };
+
+            *skipped.entry(reason).or_insert(0) += 1;
+        }
+
+        for skip in &outcome.skipped {
+            *skipped.entry(skip.reason.to_string()).or_insert(0) += 1;
+        }
+
+        Summary {
+            scanned: plan.items.len() + plan.skipped.len() + plan.unchanged.len(),
+            renamed: outcome.renamed.len(),
+            skipped,
+            failed: outcome.failed.len(),
+            unchanged: plan.unchanged.len(),
+            bytes_copied: outcome.bytes_copied,
+            elapsed,
+        }
+    }
+
+    pub fn skipped_total(&self) -> usize {
+        self.skipped.values().sum()
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Reads a byte count like `512`, `100k` or `1.5MiB`, with units of 1024
+/// whether written `k`, `K`, `KB` or `KiB`.
+pub fn parse_bytes(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.trim_start();
+    let unit = unit
+        .strip_suffix("iB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(unit);
+    let power = match unit.to_ascii_lowercase().as_str() {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return None,
+    };
+
+    Some((number * 1024f64.powi(power)).round() as u64)
+}
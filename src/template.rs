@@ -0,0 +1,636 @@
+use chrono::format::{Fixed, Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, Utc};
+use sha2::Digest as _;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::{self, Path};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::audio_tags::{self, Tags};
+use crate::dimensions;
+use crate::exif::{self, Exif, Ifd};
+use crate::hash::{blake3_file, sha256_file};
+use crate::{name_time, prefix, PlanOptions, RenamePlanItem};
+
+/// A pattern new file names are made from, like `{time} {name}`.
+///
+/// Placeholders in braces are replaced for each file; `{{` and `}}` stand
+/// for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// `{time}`, the creation time in `prefix::FORMAT`, or `{time:<format>}`
+    /// in a strftime format.
+    Time(Option<String>),
+    /// `{ordinal}`, the ordinal date of the creation time, like `2024-065`.
+    Ordinal,
+    /// `{day_num}`, the number of days from `PlanOptions::day_zero` to the
+    /// creation date.
+    DayNum,
+    /// `{name}`, the original file name.
+    Name,
+    /// `{stem}`, the original file name without its extension.
+    Stem,
+    /// `{ext}`, the extension with its dot, or nothing.
+    Ext,
+    /// `{sha256}` or `{blake3}`, the hex digest of the contents, or
+    /// `{sha256:<n>}` and `{blake3:<n>}`, its first `n` digits.
+    Hash(Digest, usize),
+    /// `{camera}`, the EXIF camera model.
+    Camera,
+    /// `{lens}`, the EXIF lens model.
+    Lens,
+    /// `{iso}`, the EXIF ISO speed.
+    Iso,
+    /// `{focal}`, the EXIF focal length, like `35mm`.
+    Focal,
+    /// `{width}`, the image width in pixels.
+    Width,
+    /// `{height}`, the image height in pixels.
+    Height,
+    /// `{artist}`, the artist tag of an audio file.
+    Artist,
+    /// `{album}`, the album tag of an audio file.
+    Album,
+    /// `{title}`, the title tag of an audio file.
+    Title,
+    /// `{track}`, the track number of an audio file, in two digits or more.
+    Track,
+    /// `{rand}` or `{rand:<n>}`, `n` random letters and digits.
+    Rand(usize),
+    /// `{uniq}`, the position of the file in the plan, oldest first.
+    Uniq,
+    /// `{parent}`, the name of the directory the file is in, or with
+    /// `{parent2}`, the one above that.
+    Parent(usize),
+    /// `{orig_index}`, the position of the file in the plan when sorted by
+    /// its original path.
+    OrigIndex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Digest {
+    Sha256,
+    Blake3,
+}
+
+impl Digest {
+    fn name(self) -> &'static str {
+        match self {
+            Digest::Sha256 => "sha256",
+            Digest::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Length of both digests in hex digits.
+const HASH_LEN: usize = 64;
+
+/// Length of `{rand}` without a length, and the most it can be given.
+const RAND_LEN: usize = 4;
+const MAX_RAND_LEN: usize = 32;
+
+/// Stand in for `{uniq}` and `{orig_index}` in new names until the plan
+/// is complete. No file name can contain a NUL.
+const UNIQ_MARK: &str = "\0u";
+const ORIG_INDEX_MARK: &str = "\0o";
+
+/// Templates for common naming schemes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// ISO week date, like `2024-W10-2 IMG_0001.jpg`.
+    Week,
+    /// Ordinal date, like `2024-065 IMG_0001.jpg`.
+    Ordinal,
+}
+
+impl FromStr for Preset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" => Ok(Preset::Week),
+            "ordinal" => Ok(Preset::Ordinal),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Preset {
+    pub fn template(self) -> Template {
+        let template = match self {
+            Preset::Week => "{time:%G-W%V-%u} {name}",
+            Preset::Ordinal => "{ordinal} {name}",
+        };
+
+        template.parse().expect("presets are valid templates")
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template(vec![
+            Part::Time(None),
+            Part::Literal(" ".to_owned()),
+            Part::Name,
+        ])
+    }
+}
+
+impl FromStr for Template {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or(())?;
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    parts.push(placeholder(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(()),
+                c if path::is_separator(c) => return Err(()),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if parts.is_empty() {
+            return Err(());
+        }
+
+        Ok(Template(parts))
+    }
+}
+
+fn placeholder(text: &str) -> Result<Part, ()> {
+    let (key, arg) = match text.split_once(':') {
+        Some((key, arg)) => (key, Some(arg)),
+        None => (text, None),
+    };
+
+    let hash_len = || match arg {
+        Some(arg) => match arg.parse() {
+            Ok(len) if (1..=HASH_LEN).contains(&len) => Ok(len),
+            _ => Err(()),
+        },
+        None => Ok(HASH_LEN),
+    };
+
+    match (key, arg) {
+        ("time", None) => Ok(Part::Time(None)),
+        ("ordinal", None) => Ok(Part::Ordinal),
+        ("day_num", None) => Ok(Part::DayNum),
+        ("time", Some(format)) => {
+            let is_valid = !format.is_empty()
+                && !format.contains(path::is_separator)
+                && StrftimeItems::new(format).all(|item| item != Item::Error);
+
+            if is_valid {
+                Ok(Part::Time(Some(format.to_owned())))
+            } else {
+                Err(())
+            }
+        }
+        ("name", None) => Ok(Part::Name),
+        ("stem", None) => Ok(Part::Stem),
+        ("ext", None) => Ok(Part::Ext),
+        ("sha256", _) => Ok(Part::Hash(Digest::Sha256, hash_len()?)),
+        ("blake3", _) => Ok(Part::Hash(Digest::Blake3, hash_len()?)),
+        ("camera", None) => Ok(Part::Camera),
+        ("lens", None) => Ok(Part::Lens),
+        ("iso", None) => Ok(Part::Iso),
+        ("focal", None) => Ok(Part::Focal),
+        ("width", None) => Ok(Part::Width),
+        ("height", None) => Ok(Part::Height),
+        ("artist", None) => Ok(Part::Artist),
+        ("album", None) => Ok(Part::Album),
+        ("title", None) => Ok(Part::Title),
+        ("track", None) => Ok(Part::Track),
+        ("rand", None) => Ok(Part::Rand(RAND_LEN)),
+        ("rand", Some(arg)) => match arg.parse() {
+            Ok(len) if (1..=MAX_RAND_LEN).contains(&len) => Ok(Part::Rand(len)),
+            _ => Err(()),
+        },
+        ("uniq", None) => Ok(Part::Uniq),
+        ("parent", None) => Ok(Part::Parent(1)),
+        ("parent2", None) => Ok(Part::Parent(2)),
+        ("orig_index", None) => Ok(Part::OrigIndex),
+        _ => Err(()),
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.0 {
+            match part {
+                Part::Literal(text) => write!(f, "{}", text.replace('{', "{{").replace('}', "}}"))?,
+                Part::Time(None) => write!(f, "{{time}}")?,
+                Part::Time(Some(format)) => write!(f, "{{time:{}}}", format)?,
+                Part::Ordinal => write!(f, "{{ordinal}}")?,
+                Part::DayNum => write!(f, "{{day_num}}")?,
+                Part::Name => write!(f, "{{name}}")?,
+                Part::Stem => write!(f, "{{stem}}")?,
+                Part::Ext => write!(f, "{{ext}}")?,
+                Part::Hash(digest, HASH_LEN) => write!(f, "{{{}}}", digest.name())?,
+                Part::Hash(digest, len) => write!(f, "{{{}:{}}}", digest.name(), len)?,
+                Part::Camera => write!(f, "{{camera}}")?,
+                Part::Lens => write!(f, "{{lens}}")?,
+                Part::Iso => write!(f, "{{iso}}")?,
+                Part::Focal => write!(f, "{{focal}}")?,
+                Part::Width => write!(f, "{{width}}")?,
+                Part::Height => write!(f, "{{height}}")?,
+                Part::Artist => write!(f, "{{artist}}")?,
+                Part::Album => write!(f, "{{album}}")?,
+                Part::Title => write!(f, "{{title}}")?,
+                Part::Track => write!(f, "{{track}}")?,
+                Part::Rand(len) => write!(f, "{{rand:{}}}", len)?,
+                Part::Uniq => write!(f, "{{uniq}}")?,
+                Part::Parent(1) => write!(f, "{{parent}}")?,
+                Part::Parent(level) => write!(f, "{{parent{}}}", level)?,
+                Part::OrigIndex => write!(f, "{{orig_index}}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Template {
+    /// Whether the template has a `{day_num}`, which needs a day zero.
+    pub fn counts_days(&self) -> bool {
+        self.0.contains(&Part::DayNum)
+    }
+
+    /// Whether the template has placeholders read from the contents of
+    /// files, such as `{sha256}` or `{camera}`.
+    pub fn reads_contents(&self) -> bool {
+        self.0.iter().any(|part| {
+            matches!(
+                part,
+                Part::Hash(..)
+                    | Part::Camera
+                    | Part::Lens
+                    | Part::Iso
+                    | Part::Focal
+                    | Part::Width
+                    | Part::Height
+                    | Part::Artist
+                    | Part::Album
+                    | Part::Title
+                    | Part::Track
+            )
+        })
+    }
+
+    /// The new name of the file at `path`, called `name`, created at
+    /// `created`.
+    pub fn render(
+        &self,
+        path: &Path,
+        name: &str,
+        created: DateTime<Utc>,
+        options: &PlanOptions,
+    ) -> io::Result<String> {
+        let (stem, ext) = match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => (
+                &name[..name.len() - ext.len() - 1],
+                &name[name.len() - ext.len() - 1..],
+            ),
+            None => (name, ""),
+        };
+        let mut digests: Vec<(Digest, String)> = Vec::new();
+        let mut exif = None;
+        let mut size = None;
+        let mut tags = None;
+        let mut out = String::new();
+
+        for part in &self.0 {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Time(format) => {
+                    let format = format.as_deref().unwrap_or(prefix::FORMAT);
+                    out.push_str(&name_time(path, created, options, format));
+                }
+                Part::Ordinal => out.push_str(&name_time(path, created, options, "%Y-%j")),
+                Part::DayNum => {
+                    let date = name_time(path, created, options, "%Y-%m-%d");
+
+                    if let (Some(day_zero), Ok(date)) = (
+                        options.day_zero,
+                        NaiveDate::parse_from_str(&date, "%Y-%m-%d"),
+                    ) {
+                        out.push_str(&(date - day_zero).num_days().to_string());
+                    }
+                }
+                Part::Name => out.push_str(name),
+                Part::Stem => out.push_str(stem),
+                Part::Ext => out.push_str(ext),
+                Part::Hash(digest, len) => {
+                    let hex = match digests.iter().find(|(d, _)| d == digest) {
+                        Some((_, hex)) => hex.clone(),
+                        None => {
+                            let bytes = match digest {
+                                Digest::Sha256 => sha256_file(path)?,
+                                Digest::Blake3 => blake3_file(path)?,
+                            };
+                            let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                            digests.push((*digest, hex.clone()));
+                            hex
+                        }
+                    };
+
+                    out.push_str(&hex[..*len]);
+                }
+                Part::Camera | Part::Lens | Part::Iso | Part::Focal => {
+                    if exif.is_none() {
+                        exif = Some(Exif::read(path)?);
+                    }
+
+                    if let Some(Some(exif)) = &exif {
+                        out.push_str(&exif_field(exif, part).unwrap_or_default());
+                    }
+                }
+                Part::Width | Part::Height => {
+                    if size.is_none() {
+                        size = Some(dimensions::read(path)?);
+                    }
+
+                    match (part, size) {
+                        (Part::Width, Some(Some((width, _)))) => out.push_str(&width.to_string()),
+                        (Part::Height, Some(Some((_, height)))) => {
+                            out.push_str(&height.to_string())
+                        }
+                        _ => {}
+                    }
+                }
+                Part::Rand(len) => out.push_str(&random_chars(*len)),
+                Part::Uniq => out.push_str(UNIQ_MARK),
+                Part::OrigIndex => out.push_str(ORIG_INDEX_MARK),
+                Part::Parent(level) => {
+                    let dir = path.ancestors().nth(*level).and_then(Path::file_name);
+                    out.push_str(&dir.map(|dir| dir.to_string_lossy()).unwrap_or_default());
+                }
+                Part::Artist | Part::Album | Part::Title | Part::Track => {
+                    if tags.is_none() {
+                        tags = Some(audio_tags::read(path)?);
+                    }
+
+                    if let Some(Some(tags)) = &tags {
+                        out.push_str(&tag_field(tags, part).unwrap_or_default());
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// The value of an EXIF placeholder, with path separators replaced.
+fn exif_field(exif: &Exif, part: &Part) -> Option<String> {
+    let value = match part {
+        Part::Camera => exif.ascii(Ifd::Primary, exif::TAG_MODEL)?.to_owned(),
+        Part::Lens => exif.ascii(Ifd::Exif, exif::TAG_LENS_MODEL)?.to_owned(),
+        Part::Iso => exif.long(Ifd::Exif, exif::TAG_ISO)?.to_string(),
+        Part::Focal => {
+            let focal = exif.rational(Ifd::Exif, exif::TAG_FOCAL_LENGTH)?;
+            let focal = format!("{:.1}", focal);
+            format!("{}mm", focal.trim_end_matches(".0"))
+        }
+        _ => return None,
+    };
+
+    Some(value.replace(path::is_separator, "_"))
+}
+
+/// The value of an audio tag placeholder, with path separators replaced.
+fn tag_field(tags: &Tags, part: &Part) -> Option<String> {
+    let value = match part {
+        Part::Artist => tags.artist.clone()?,
+        Part::Album => tags.album.clone()?,
+        Part::Title => tags.title.clone()?,
+        Part::Track => format!("{:02}", tags.track?),
+        _ => return None,
+    };
+
+    Some(value.replace(path::is_separator, "_"))
+}
+
+/// Whether the strftime `format` includes the UTC offset.
+pub(crate) fn shows_offset(format: &str) -> bool {
+    StrftimeItems::new(format).any(|item| {
+        matches!(
+            item,
+            Item::Fixed(
+                Fixed::TimezoneOffset
+                    | Fixed::TimezoneOffsetColon
+                    | Fixed::TimezoneOffsetDoubleColon
+                    | Fixed::TimezoneOffsetTripleColon
+                    | Fixed::TimezoneOffsetColonZ
+                    | Fixed::TimezoneOffsetZ
+            )
+        )
+    })
+}
+
+/// `len` random lowercase letters and digits.
+fn random_chars(len: usize) -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut out = String::new();
+
+    while out.len() < len {
+        // The keys of `RandomState` are random once per thread but only
+        // counted up after, so they are hashed together with the time,
+        // process and a counter for bits unrelated to those drawn before.
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(RandomState::new().hash_one(0u8).to_le_bytes());
+        hasher.update(process::id().to_le_bytes());
+        hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.update(now.as_nanos().to_le_bytes());
+        }
+
+        for chunk in hasher.finalize().chunks(8) {
+            let mut bits = chunk
+                .iter()
+                .fold(0, |bits, &byte| bits << 8 | u64::from(byte));
+
+            for _ in 0..12 {
+                out.push(char::from(ALPHABET[(bits % 36) as usize]));
+                bits /= 36;
+            }
+        }
+    }
+
+    out.truncate(len);
+    out
+}
+
+/// Replaces `{uniq}` in the new names of `items` by their positions, and
+/// `{orig_index}` by their positions when sorted by path, padded to the
+/// same number of digits. Both count from `options.counter_start`.
+pub(crate) fn number(items: &mut [RenamePlanItem], options: &PlanOptions) {
+    let start = options.counter_start.unwrap_or(1);
+    let last = (start + items.len()).saturating_sub(1);
+    let width = last.to_string().len().max(options.counter_width);
+    let mut by_path: Vec<_> = (0..items.len()).collect();
+    by_path.sort_by(|&a, &b| items[a].path.cmp(&items[b].path));
+
+    let mut orig_index = vec![0; items.len()];
+
+    for (position, i) in by_path.into_iter().enumerate() {
+        orig_index[i] = start + position;
+    }
+
+    for (i, item) in items.iter_mut().enumerate() {
+        if item.new_name.contains('\0') {
+            let number = |n| format!("{:0width$}", n, width = width);
+            item.new_name = item
+                .new_name
+                .replace(UNIQ_MARK, &number(start + i))
+                .replace(ORIG_INDEX_MARK, &number(orig_index[i]));
+            item.new_path.set_file_name(&item.new_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Template, ()> {
+        text.parse()
+    }
+
+    #[test]
+    fn parses_placeholders_and_literals() {
+        assert_eq!(parse("{time} {name}"), Ok(Template::default()));
+        assert_eq!(
+            parse("{time:%Y-%m-%d}_{stem}{ext}"),
+            Ok(Template(vec![
+                Part::Time(Some("%Y-%m-%d".to_owned())),
+                Part::Literal("_".to_owned()),
+                Part::Stem,
+                Part::Ext,
+            ]))
+        );
+        assert_eq!(
+            parse("{{{stem}}}"),
+            Ok(Template(vec![
+                Part::Literal("{".to_owned()),
+                Part::Stem,
+                Part::Literal("}".to_owned()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_hash_lengths() {
+        assert_eq!(
+            parse("{sha256:8}{blake3}"),
+            Ok(Template(vec![
+                Part::Hash(Digest::Sha256, 8),
+                Part::Hash(Digest::Blake3, HASH_LEN),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for text in [
+            "",
+            "{nope}",
+            "{time",
+            "name}",
+            "a/{name}",
+            "{time:%Y/%m}",
+            "{time:}",
+            "{sha256:0}",
+            "{sha256:65}",
+            "{name:x}",
+            "{rand:0}",
+            "{rand:33}",
+        ] {
+            assert_eq!(parse(text), Err(()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_random_lengths() {
+        assert_eq!(parse("{rand}"), Ok(Template(vec![Part::Rand(RAND_LEN)])));
+        assert_eq!(parse("{rand:10}"), Ok(Template(vec![Part::Rand(10)])));
+        assert_eq!(
+            parse("{time:%H.%M} {rand:6}").unwrap().to_string(),
+            "{time:%H.%M} {rand:6}"
+        );
+    }
+
+    #[test]
+    fn draws_random_characters() {
+        let one = random_chars(20);
+
+        assert_eq!(one.len(), 20);
+        assert!(one
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b.is_ascii_lowercase()));
+        assert_ne!(one, random_chars(20));
+        assert_eq!(random_chars(100).len(), 100);
+    }
+
+    #[test]
+    fn parses_parent_levels() {
+        assert_eq!(parse("{parent}"), Ok(Template(vec![Part::Parent(1)])));
+        assert_eq!(parse("{parent2}"), Ok(Template(vec![Part::Parent(2)])));
+    }
+
+    #[test]
+    fn tells_whether_days_are_counted() {
+        assert!(parse("{day_num} {name}").unwrap().counts_days());
+        assert!(!Template::default().counts_days());
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for text in [
+            "{time} {name}",
+            "{{x}} {sha256:8}{ext}",
+            "{time:%H.%M} {stem}",
+        ] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+    }
+}
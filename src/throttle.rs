@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between two operations in nanoseconds, or 0 for no limit.
+static INTERVAL: AtomicU64 = AtomicU64::new(0);
+/// When the next operation may start.
+static NEXT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Limits the file operations of the rest of the run to `ops_per_sec`,
+/// across all threads.
+pub fn set(ops_per_sec: f64) {
+    let interval = Duration::from_secs_f64(1.0 / ops_per_sec);
+    INTERVAL.store(interval.as_nanos() as u64, Ordering::SeqCst);
+}
+
+/// Waits until the next operation on the filesystem may start, when a
+/// limit has been set.
+///
+/// Operations are spread out evenly rather than let through in bursts, so
+/// a slow network share sees a steady load.
+pub fn wait() {
+    let interval = INTERVAL.load(Ordering::SeqCst);
+
+    if interval == 0 {
+        return;
+    }
+
+    let start = {
+        let mut next = NEXT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        let start = next.map_or(now, |next| next.max(now));
+        *next = Some(start + Duration::from_nanos(interval));
+        start
+    };
+
+    let now = Instant::now();
+
+    if start > now {
+        thread::sleep(start - now);
+    }
+}
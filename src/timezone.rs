@@ -0,0 +1,16 @@
+use chrono_tz::Tz;
+use std::path::Path;
+use std::sync::OnceLock;
+use tzf_rs::DefaultFinder;
+
+use crate::exif::Exif;
+
+/// Timezone of the place a photo was taken at, from its GPS position.
+pub fn capture_zone(path: &Path) -> Option<Tz> {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+
+    let (latitude, longitude) = Exif::read(path).ok()??.gps_position()?;
+    let finder = FINDER.get_or_init(DefaultFinder::new);
+
+    finder.get_tz_name(longitude, latitude).parse().ok()
+}
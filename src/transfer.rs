@@ -0,0 +1,661 @@
+use std::fs;
+use std::fs::{File, FileTimes, Metadata, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use crate::hash::sha256_file;
+use crate::reflink;
+use crate::sparse;
+use crate::ApplyOptions;
+
+/// Whether files are moved to their new path or copied there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferMode {
+    #[default]
+    Move,
+    Copy,
+}
+
+/// Moves or copies `from` to `to` according to `options`, returning the
+/// number of bytes that had to be copied.
+///
+/// Missing parent directories of `to` are created. Moves across
+/// filesystems fall back to copying the file and removing the original
+/// afterwards. A file already at `to`, perhaps put there since the rename
+/// was planned, is left alone, and the transfer fails.
+pub fn transfer(from: &Path, to: &Path, options: &ApplyOptions) -> io::Result<u64> {
+    transfer_to(from, to, options, false)
+}
+
+/// Like `transfer`, but replaces a file already at `to`.
+pub(crate) fn transfer_replacing(
+    from: &Path,
+    to: &Path,
+    options: &ApplyOptions,
+) -> io::Result<u64> {
+    transfer_to(from, to, options, true)
+}
+
+fn transfer_to(from: &Path, to: &Path, options: &ApplyOptions, replace: bool) -> io::Result<u64> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let rename = |from: &Path, to: &Path| match replace {
+        true => fs::rename(from, to),
+        false => rename_new(from, to),
+    };
+
+    match options.mode {
+        TransferMode::Move => match options.retry.run(|| rename(from, to)) {
+            Ok(()) => Ok(0),
+            Err(ref error) if error.kind() == io::ErrorKind::CrossesDevices => {
+                let copied = copy(from, to, options, replace)?;
+                options.retry.run(|| fs::remove_file(from))?;
+                Ok(copied)
+            }
+            Err(error) => Err(error),
+        },
+        TransferMode::Copy => copy(from, to, options, replace),
+    }
+}
+
+/// Puts `contents` at `to` in place of the original contents of `from`,
+/// moving or copying according to `options.mode`, and returns the number
+/// of bytes written.
+///
+/// The new file is written next to `to` under a temporary name and only
+/// renamed into place once complete, and a moved original is removed only
+/// after that, unless it was `to` itself and so is already replaced. Any
+/// other file at `to` is only replaced if `replace` says so.
+pub fn transfer_with_contents(
+    from: &Path,
+    to: &Path,
+    contents: &[u8],
+    options: &ApplyOptions,
+    replace: bool,
+) -> io::Result<u64> {
+    let temp = match to.file_name() {
+        Some(name) => to.with_file_name(format!(".crtime-tmp-{}", name.to_string_lossy())),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name")),
+    };
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let in_place = same_file(from, to);
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.set_permissions(fs::metadata(from)?.permissions())?;
+        file.sync_all()?;
+        drop(file);
+
+        copy_streams(from, &temp, options)?;
+        preserve_metadata(from, &temp)?;
+        options.retry.run(|| match replace || in_place {
+            true => fs::rename(&temp, to),
+            false => rename_new(&temp, to),
+        })
+    })();
+
+    if let Err(error) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+
+    if options.mode == TransferMode::Move && !in_place {
+        options.retry.run(|| fs::remove_file(from))?;
+    }
+
+    Ok(contents.len() as u64)
+}
+
+/// Copies `from` to `to` along with its metadata, checking the copy
+/// against the original when `options.verify` is set.
+///
+/// On copy-on-write filesystems the copy is a clone sharing the blocks of
+/// the original, and otherwise files with holes, like disk images, keep
+/// them in the copy. A copy that does not match or whose metadata cannot
+/// be restored is removed again, unless a file was at `to` before, which
+/// is only copied over if `replace` says so.
+fn copy(from: &Path, to: &Path, options: &ApplyOptions, replace: bool) -> io::Result<u64> {
+    let existed = fs::symlink_metadata(to).is_ok();
+
+    if existed && !replace {
+        return Err(already_exists(to));
+    }
+
+    let copied = if options.retry.run(|| reflink::clone(from, to))? {
+        0
+    } else if sparse::is_sparse(&fs::metadata(from)?) {
+        options
+            .retry
+            .run(|| sparse::copy(from, to))
+            .and_then(|copied| copy_streams(from, to, options).map(|()| copied))?
+    } else {
+        options.retry.run(|| fs::copy(from, to))?
+    };
+
+    let result = strip_streams(to, options)
+        .and_then(|()| preserve_metadata(from, to))
+        .and_then(|()| {
+            if options.verify && sha256_file(from)? != sha256_file(to)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "copy does not match the original",
+                ));
+            }
+
+            Ok(copied)
+        });
+
+    if result.is_err() && !existed {
+        let _ = fs::remove_file(to);
+    }
+
+    result
+}
+
+/// Renames `from` to `to` unless a file is already at `to`.
+///
+/// Linux, macOS and Windows check this as they rename, so a file put
+/// there in the meantime is not replaced either. Elsewhere, and on
+/// filesystems that cannot, `to` is checked just before renaming.
+fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+    // Only the case of the name changes, on a filesystem ignoring it.
+    if same_file(from, to) {
+        return fs::rename(from, to);
+    }
+
+    if let Some(result) = rename_exclusive(from, to) {
+        return result;
+    }
+
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(already_exists(to));
+    }
+
+    fs::rename(from, to)
+}
+
+/// Renames `from` to `to` if no file is there, or returns `None` where
+/// the system cannot tell.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn rename_exclusive(from: &Path, to: &Path) -> Option<io::Result<()>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+    };
+    let (c_from, c_to) = match (c_path(from), c_path(to)) {
+        (Ok(c_from), Ok(c_to)) => (c_from, c_to),
+        (Err(error), _) | (_, Err(error)) => return Some(Err(error)),
+    };
+
+    #[cfg(target_os = "linux")]
+    let renamed = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_from.as_ptr(),
+            libc::AT_FDCWD,
+            c_to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    #[cfg(target_os = "macos")]
+    let renamed = unsafe { libc::renamex_np(c_from.as_ptr(), c_to.as_ptr(), libc::RENAME_EXCL) };
+
+    if renamed == 0 {
+        return Some(Ok(()));
+    }
+
+    match io::Error::last_os_error() {
+        error
+            if matches!(
+                error.raw_os_error(),
+                Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::ENOTSUP)
+            ) =>
+        {
+            None
+        }
+        error => Some(Err(error)),
+    }
+}
+
+#[cfg(windows)]
+fn rename_exclusive(from: &Path, to: &Path) -> Option<io::Result<()>> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::MoveFileExW;
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+
+    // Without MOVEFILE_REPLACE_EXISTING, an existing file makes it fail.
+    match unsafe { MoveFileExW(wide(from).as_ptr(), wide(to).as_ptr(), 0) } {
+        0 => Some(Err(io::Error::last_os_error())),
+        _ => Some(Ok(())),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn rename_exclusive(_from: &Path, _to: &Path) -> Option<io::Result<()>> {
+    None
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+/// Whether `a` and `b` are the same file, through the same path or a link.
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Gives the copy `to` the timestamps and, where permitted, the owner of
+/// `from`, on Linux its SELinux context and on Windows its access control
+/// list.
+///
+/// `fs::copy` already carries over permission bits, and on Windows the
+/// readonly and hidden attributes.
+fn preserve_metadata(from: &Path, to: &Path) -> io::Result<()> {
+    let meta = fs::metadata(from)?;
+
+    open_for_times(to)?.set_times(file_times(&meta)?)?;
+    preserve_owner(&meta, to)?;
+    preserve_selinux_context(from, to)?;
+    preserve_acl(from, to)
+}
+
+fn file_times(meta: &Metadata) -> io::Result<FileTimes> {
+    let times = FileTimes::new()
+        .set_accessed(meta.accessed()?)
+        .set_modified(meta.modified()?);
+
+    #[cfg(windows)]
+    let times = {
+        use std::os::windows::fs::FileTimesExt;
+        times.set_created(meta.created()?)
+    };
+
+    #[cfg(target_os = "macos")]
+    let times = {
+        use std::os::macos::fs::FileTimesExt;
+        times.set_created(meta.created()?)
+    };
+
+    Ok(times)
+}
+
+/// Opens `path` so its timestamps can be changed even if it is read-only.
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+
+    OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .open(path)
+}
+
+/// Opens `path` so its timestamps can be changed even if it is read-only.
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).open(path)
+}
+
+/// Copies the owner of the original, which only succeeds when privileged.
+#[cfg(unix)]
+fn preserve_owner(meta: &Metadata, to: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{chown, MetadataExt};
+
+    match chown(to, Some(meta.uid()), Some(meta.gid())) {
+        Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_meta: &Metadata, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Copies the SELinux security context of the original, as `cp -a` does,
+/// instead of the default one of the new directory. Systems without
+/// SELinux, filesystems without labels and contexts the process may not
+/// set are left alone.
+#[cfg(target_os = "linux")]
+fn preserve_selinux_context(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+    };
+    let (c_from, c_to) = (c_path(from)?, c_path(to)?);
+    let name = b"security.selinux\0".as_ptr() as *const libc::c_char;
+
+    let ignored = |error: io::Error| match error.raw_os_error() {
+        Some(libc::ENODATA) | Some(libc::ENOTSUP) | Some(libc::EPERM) | Some(libc::EACCES) => {
+            Ok(())
+        }
+        _ => Err(error),
+    };
+
+    // The first call only tells the size of the context.
+    let len = unsafe { libc::lgetxattr(c_from.as_ptr(), name, ptr::null_mut(), 0) };
+
+    if len < 0 {
+        return ignored(io::Error::last_os_error());
+    }
+
+    let mut context = vec![0u8; len as usize];
+    let len = unsafe {
+        libc::lgetxattr(
+            c_from.as_ptr(),
+            name,
+            context.as_mut_ptr() as *mut libc::c_void,
+            context.len(),
+        )
+    };
+
+    if len < 0 {
+        return ignored(io::Error::last_os_error());
+    }
+
+    let set = unsafe {
+        libc::lsetxattr(
+            c_to.as_ptr(),
+            name,
+            context.as_ptr() as *const libc::c_void,
+            len as usize,
+            0,
+        )
+    };
+
+    if set != 0 {
+        return ignored(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preserve_selinux_context(_from: &Path, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Copies the discretionary access control list of the original, so that
+/// the copy grants the same access rather than what its new directory
+/// would pass down. Filesystems without ACLs are left alone.
+#[cfg(windows)]
+fn preserve_acl(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use windows_sys::Win32::Security::{
+        GetFileSecurityW, SetFileSecurityW, DACL_SECURITY_INFORMATION,
+    };
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(from), wide(to));
+
+    let result = (|| {
+        let mut needed = 0;
+
+        // The first call only tells the size of the security descriptor.
+        unsafe {
+            GetFileSecurityW(
+                from.as_ptr(),
+                DACL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                0,
+                &mut needed,
+            )
+        };
+
+        if needed == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Kept in u64s for the alignment the descriptor needs.
+        let mut descriptor = vec![0u64; (needed as usize).div_ceil(8)];
+
+        if unsafe {
+            GetFileSecurityW(
+                from.as_ptr(),
+                DACL_SECURITY_INFORMATION,
+                descriptor.as_mut_ptr().cast(),
+                needed,
+                &mut needed,
+            )
+        } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        if unsafe {
+            SetFileSecurityW(
+                to.as_ptr(),
+                DACL_SECURITY_INFORMATION,
+                descriptor.as_mut_ptr().cast(),
+            )
+        } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Err(ref error) if error.kind() == io::ErrorKind::Unsupported => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(windows))]
+fn preserve_acl(_from: &Path, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Name of the alternate data stream Windows records the origin of
+/// downloaded files in.
+#[cfg(windows)]
+const ZONE_IDENTIFIER: &str = ":Zone.Identifier:$DATA";
+
+/// Alternate data streams of `path`, like `:Zone.Identifier:$DATA`. None
+/// are listed on filesystems without them.
+#[cfg(windows)]
+fn streams(path: &Path) -> Vec<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let mut streams = Vec::new();
+
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide.as_ptr(),
+            FindStreamInfoStandard,
+            (&mut data as *mut WIN32_FIND_STREAM_DATA).cast(),
+            0,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return streams;
+    }
+
+    loop {
+        let len = data
+            .cStreamName
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(data.cStreamName.len());
+        let name = String::from_utf16_lossy(&data.cStreamName[..len]);
+
+        // The file's own contents are listed as the unnamed stream.
+        if name != "::$DATA" {
+            streams.push(name);
+        }
+
+        if unsafe { FindNextStreamW(handle, (&mut data as *mut WIN32_FIND_STREAM_DATA).cast()) }
+            == 0
+        {
+            break;
+        }
+    }
+
+    unsafe { FindClose(handle) };
+    streams
+}
+
+/// Copies the alternate data streams of `from` to `to`, which `fs::copy`
+/// does by itself but writing new contents does not.
+#[cfg(windows)]
+fn copy_streams(from: &Path, to: &Path, options: &ApplyOptions) -> io::Result<()> {
+    let with_stream = |path: &Path, stream: &str| {
+        let mut path = path.as_os_str().to_owned();
+        path.push(stream);
+        path
+    };
+
+    for stream in streams(from) {
+        if options.strip_zone_identifier && stream.eq_ignore_ascii_case(ZONE_IDENTIFIER) {
+            continue;
+        }
+
+        let mut reader = File::open(with_stream(from, &stream))?;
+        let mut writer = File::create(with_stream(to, &stream))?;
+        io::copy(&mut reader, &mut writer)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn copy_streams(_from: &Path, _to: &Path, _options: &ApplyOptions) -> io::Result<()> {
+    Ok(())
+}
+
+/// Removes the streams of the copy `to` that `options` leaves out.
+#[cfg(windows)]
+fn strip_streams(to: &Path, options: &ApplyOptions) -> io::Result<()> {
+    if !options.strip_zone_identifier {
+        return Ok(());
+    }
+
+    let mut stream = to.as_os_str().to_owned();
+    stream.push(ZONE_IDENTIFIER);
+
+    match fs::remove_file(stream) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(windows))]
+fn strip_streams(_to: &Path, _options: &ApplyOptions) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crtime-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_contents_written_in_place() {
+        let dir = temp_dir("in-place");
+        let path = dir.join("a.jpg");
+        fs::write(&path, "old").unwrap();
+
+        let written = transfer_with_contents(&path, &path, b"new", &ApplyOptions::default(), false);
+
+        assert_eq!(written.unwrap(), 3);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn moves_contents_to_a_new_path() {
+        let dir = temp_dir("new-path");
+        let (from, to) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&from, "old").unwrap();
+
+        transfer_with_contents(&from, &to, b"new", &ApplyOptions::default(), false).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_files_put_at_the_new_path() {
+        let dir = temp_dir("taken");
+        let (from, to) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&from, "a").unwrap();
+        fs::write(&to, "b").unwrap();
+
+        for mode in [TransferMode::Move, TransferMode::Copy] {
+            let options = ApplyOptions {
+                mode,
+                ..ApplyOptions::default()
+            };
+            let error = transfer(&from, &to, &options).unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+            assert_eq!(fs::read_to_string(&from).unwrap(), "a");
+            assert_eq!(fs::read_to_string(&to).unwrap(), "b");
+        }
+
+        transfer_replacing(&from, &to, &ApplyOptions::default()).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "a");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::prefix;
+use crate::{resolve_collisions, Plan, RenamePlanItem, SkippedItem};
+
+/// A file whose name prefix differs from its creation time.
+#[derive(Debug)]
+pub struct Mismatch<'a> {
+    pub item: &'a RenamePlanItem,
+    /// The prefix the file is named with.
+    pub named: &'a str,
+    /// The prefix its creation time gives now.
+    pub expected: &'a str,
+}
+
+/// How the prefixed files of a plan compare to their creation times.
+#[derive(Debug, Default)]
+pub struct Report<'a> {
+    /// Prefixed files whose creation time could be read.
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch<'a>>,
+    /// Prefixed files whose creation time could not be read.
+    pub unresolved: Vec<&'a SkippedItem>,
+}
+
+/// Whether the file at `path` is named with a prefix.
+fn is_prefixed(path: &std::path::Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(prefix::split)
+        .is_some()
+}
+
+/// Compares the prefix of every already renamed file in `plan` with the
+/// one it would be renamed with now.
+pub fn check(plan: &Plan) -> Report<'_> {
+    let mut report = Report::default();
+
+    for item in &plan.items {
+        let (named, expected) = match (prefix::split(&item.name), prefix::split(&item.new_name)) {
+            (Some((named, _)), Some((expected, _))) => (named, expected),
+            _ => continue,
+        };
+
+        report.checked += 1;
+
+        if named != expected {
+            report.mismatches.push(Mismatch {
+                item,
+                named,
+                expected,
+            });
+        }
+    }
+
+    report.unresolved = plan
+        .skipped
+        .iter()
+        .filter(|skipped| is_prefixed(&skipped.path))
+        .collect();
+
+    report
+}
+
+/// Turns `plan` into one renaming every prefixed file whose prefix is in
+/// the legacy format or differs from its creation time, replacing the
+/// prefix rather than adding another.
+///
+/// Files without a prefix and files already named right are left out,
+/// and so are skipped files without one.
+pub fn fix(mut plan: Plan) -> Plan {
+    plan.items.retain_mut(|item| {
+        let (named, rest) = match prefix::split(&item.name) {
+            Some(split) => split,
+            None => return false,
+        };
+        let expected = match prefix::split(&item.new_name) {
+            Some((expected, _)) => expected.to_owned(),
+            None => return false,
+        };
+
+        if named == expected {
+            return false;
+        }
+
+        item.new_name = format!("{} {}", expected, rest);
+        item.new_path.set_file_name(&item.new_name);
+        true
+    });
+
+    plan.skipped.retain(|skipped| is_prefixed(&skipped.path));
+    resolve_collisions(&mut plan.items);
+
+    plan
+}
+
+/// The files of `items` whose names sort out of the order of their
+/// creation times, comparing files in the same directory: the fewest that
+/// leave the others in order when set aside, by path.
+pub fn out_of_order(items: &[RenamePlanItem]) -> Vec<&RenamePlanItem> {
+    let mut dirs: BTreeMap<&Path, Vec<&RenamePlanItem>> = BTreeMap::new();
+
+    for item in items {
+        let dir = item.path.parent().unwrap_or(Path::new(""));
+        dirs.entry(dir).or_default().push(item);
+    }
+
+    let mut found = Vec::new();
+
+    for files in dirs.values_mut() {
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let in_order = longest_in_order(files);
+        found.extend(
+            files
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !in_order[*i])
+                .map(|(_, item)| *item),
+        );
+    }
+
+    found
+}
+
+/// Which of `files` make up the longest run, not necessarily contiguous,
+/// whose creation times never go back.
+fn longest_in_order(files: &[&RenamePlanItem]) -> Vec<bool> {
+    // Ends of the best runs found of each length, and what comes before
+    // each file in the best run ending with it.
+    let mut ends: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; files.len()];
+
+    for (i, file) in files.iter().enumerate() {
+        let len = ends.partition_point(|&end| files[end].created <= file.created);
+
+        previous[i] = len.checked_sub(1).map(|len| ends[len]);
+
+        if len == ends.len() {
+            ends.push(i);
+        } else {
+            ends[len] = i;
+        }
+    }
+
+    let mut in_order = vec![false; files.len()];
+    let mut next = ends.last().copied();
+
+    while let Some(i) = next {
+        in_order[i] = true;
+        next = previous[i];
+    }
+
+    in_order
+}
@@ -0,0 +1,213 @@
+//! WebDAV shares, like those of Nextcloud and most NAS, through the
+//! system's `curl`.
+//!
+//! Directories are listed with `PROPFIND` and files renamed with `MOVE`.
+//! Passwords are never part of the URL: `curl` looks them up in `~/.netrc`,
+//! for the user the URL names if it names one.
+
+use chrono::{DateTime, Utc};
+use std::io;
+
+use crate::backend::{Backend, RemoteFile, Scheme, Url};
+use crate::curl::{decode, encode, Request, Response};
+use crate::xml::{elements, first, unescape};
+
+pub struct WebDav {
+    /// `http[s]://[user@]host[:port]`, which requests are sent to.
+    origin: String,
+    /// `http[s]://host[:port]`, which the server may start its links with.
+    host: String,
+    /// The path of the directory, starting and ending with `/`.
+    base: String,
+}
+
+/// The properties asked for about each file.
+const PROPFIND: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<d:propfind xmlns:d=\"DAV:\"><d:prop>\
+<d:resourcetype/><d:getcontentlength/><d:getlastmodified/><d:creationdate/>\
+</d:prop></d:propfind>";
+
+/// Fails unless the status is 2xx.
+fn check(response: Response) -> io::Result<Response> {
+    if (200..300).contains(&response.status) {
+        return Ok(response);
+    }
+
+    Err(io::Error::new(
+        response.error_kind(),
+        format!("WebDAV server answered with status {}", response.status),
+    ))
+}
+
+impl WebDav {
+    /// Addresses the share `url` names, `dav[s]://[user@]host[:port]/path`.
+    pub fn connect(url: &Url) -> io::Result<WebDav> {
+        let protocol = match url.scheme {
+            Scheme::Dav => "http",
+            _ => "https",
+        };
+        let port = url
+            .port
+            .map(|port| format!(":{}", port))
+            .unwrap_or_default();
+        let user = url
+            .user
+            .as_ref()
+            .map(|user| format!("{}@", encode(user, true)))
+            .unwrap_or_default();
+        let base = match url.path.as_str() {
+            "" => "/".to_owned(),
+            path => format!("/{}/", path.trim_start_matches('/')),
+        };
+
+        Ok(WebDav {
+            origin: format!("{}://{}{}{}", protocol, user, url.host, port),
+            host: format!("{}://{}{}", protocol, url.host, port),
+            base,
+        })
+    }
+
+    /// The URL of `name` in the directory, with `/` at the end for a
+    /// directory.
+    fn url(&self, name: &str) -> String {
+        format!("{}{}", self.origin, self.path(name))
+    }
+
+    fn path(&self, name: &str) -> String {
+        encode(&format!("{}{}", self.base, name), false)
+    }
+
+    fn request(&self, method: &str, url: &str, headers: &[String]) -> io::Result<Response> {
+        let mut request = Request::new(method, url);
+        request.flag("netrc-optional");
+
+        if method == "PROPFIND" {
+            request
+                .option("data-binary", PROPFIND)
+                .header("Content-Type: application/xml; charset=utf-8");
+        }
+
+        for header in headers {
+            request.header(header);
+        }
+
+        request.send()
+    }
+
+    fn propfind(&self, url: &str, depth: u8) -> io::Result<Response> {
+        self.request("PROPFIND", url, &[format!("Depth: {}", depth)])
+    }
+
+    /// The files in a `multistatus` answer, with names relative to the
+    /// directory. Subdirectories end in `/`, and the directory itself is
+    /// empty.
+    fn files(&self, xml: &str) -> Vec<RemoteFile> {
+        let mut files = Vec::new();
+
+        for response in elements(xml, "response") {
+            let href = match first(response, "href") {
+                Some(href) => decode(&unescape(href.trim())),
+                None => continue,
+            };
+            let path = href.strip_prefix(&self.host).unwrap_or(&href);
+            let mut name = match path.strip_prefix(&self.base) {
+                Some(name) => name.to_owned(),
+                None if format!("{}/", path) == self.base => String::new(),
+                None => continue,
+            };
+
+            if first(response, "collection").is_some() && !name.is_empty() && !name.ends_with('/') {
+                name.push('/');
+            }
+
+            files.push(RemoteFile {
+                name,
+                len: first(response, "getcontentlength")
+                    .and_then(|len| len.trim().parse().ok())
+                    .unwrap_or(0),
+                modified: first(response, "getlastmodified")
+                    .and_then(|time| DateTime::parse_from_rfc2822(time.trim()).ok())
+                    .map(|time| time.with_timezone(&Utc)),
+                created: first(response, "creationdate")
+                    .and_then(|time| DateTime::parse_from_rfc3339(time.trim()).ok())
+                    .map(|time| time.with_timezone(&Utc)),
+            });
+        }
+
+        files
+    }
+
+    /// Creates the directories `name` is in, where missing.
+    fn create_dirs(&self, name: &str) -> io::Result<()> {
+        let mut dir = String::new();
+        let parts: Vec<&str> = name.split('/').collect();
+
+        for part in &parts[..parts.len() - 1] {
+            dir.push_str(part);
+            dir.push('/');
+
+            let response = self.request("MKCOL", &self.url(&dir), &[])?;
+
+            // 405 is for something being there already.
+            if response.status != 405 {
+                check(response)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Backend for WebDav {
+    fn list(&mut self) -> io::Result<Vec<RemoteFile>> {
+        let response = check(self.propfind(&self.url(""), 1)?)?;
+
+        Ok(self
+            .files(&response.body)
+            .into_iter()
+            .filter(|file| !file.name.is_empty() && !file.name.ends_with('/'))
+            .collect())
+    }
+
+    fn stat(&mut self, name: &str) -> io::Result<Option<RemoteFile>> {
+        let response = self.propfind(&self.url(name), 0)?;
+
+        if response.status == 404 {
+            return Ok(None);
+        }
+
+        let response = check(response)?;
+        let file = self.files(&response.body).into_iter().next();
+
+        Ok(Some(RemoteFile {
+            name: name.to_owned(),
+            ..file.unwrap_or(RemoteFile {
+                name: String::new(),
+                len: 0,
+                modified: None,
+                created: None,
+            })
+        }))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.create_dirs(to)?;
+
+        let headers = [
+            // Without the user, which servers need not accept here.
+            format!("Destination: {}{}", self.host, self.path(to)),
+            "Overwrite: F".to_owned(),
+        ];
+        let response = self.request("MOVE", &self.url(from), &headers)?;
+
+        if response.status == 412 {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to),
+            ));
+        }
+
+        check(response)?;
+        Ok(())
+    }
+}